@@ -1,6 +1,7 @@
 use capitalize::Capitalize;
 use md5::compute;
 use std::collections::HashMap;
+use std::env;
 use std::ffi::OsStr;
 use std::fs;
 use std::io::Write;
@@ -109,7 +110,7 @@ pub fn handle_static(_uri: String) -> Option<(Vec<u8>, Option<String>)> {"#
     if !has_bootstrap_js || !has_bootstrap_css {
         log!(
             "Could not found {} in `www` directory, Will replace public bootstrap links inside `*.html` files",
-            if !has_bootstrap_js && !has_bootstrap_js {
+            if !has_bootstrap_js && !has_bootstrap_css {
                 format!("`{}` and `{}`", BOOTSTRAP_JS_FILENAME, BOOTSTRAP_CSS_FILENAME)
             } else if !has_bootstrap_js {
                 format!("`{}`", BOOTSTRAP_JS_FILENAME)
@@ -137,6 +138,12 @@ pub fn handle_static(_uri: String) -> Option<(Vec<u8>, Option<String>)> {"#
                 "Some(\"text/css\".to_string())"
             } else if extension == OsStr::new("js") {
                 "Some(\"text/javascript\".to_string())"
+            } else if extension == OsStr::new("ico") {
+                "Some(\"image/x-icon\".to_string())"
+            } else if extension == OsStr::new("jpg") {
+                "Some(\"image/jpeg\".to_string())"
+            } else if extension == OsStr::new("webmanifest") {
+                "Some(\"application/manifest+json\".to_string())"
             } else {
                 "None"
             }
@@ -333,6 +340,9 @@ pub fn maybe_print(sample_name: CMDSample) {
 }
 
 fn main() {
-    maybe_build_src_www();
+    // Web files are not embedded without `www` feature:
+    if env::var_os("CARGO_FEATURE_WWW").is_some() {
+        maybe_build_src_www();
+    };
     maybe_build_src_samples();
 }
@@ -0,0 +1,82 @@
+//! Serves RestCommander REST-API under `/restcommander/api/` of another warp server.
+//!
+//! Commands can also be run via `/deploy/...` which is authenticated with the same tokens.
+//!
+//! Run it via `cargo run --example embed -- <COMMANDS_ROOT_DIRECTORY>` and try
+//! `curl http://127.0.0.1:8080/restcommander/api/commands`.
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use tokio::sync::RwLock as AsyncRwLock;
+use warp::Filter;
+
+use restcommander::settings::{Cfg, CfgValue};
+use restcommander::{cmd, http, report};
+
+#[tokio::main]
+async fn main() -> Result<(), String> {
+    let mut cfg_value = CfgValue::default();
+    cfg_value.commands.root_directory = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .ok_or_else(|| "commands root directory is not given".to_string())?;
+    // Commands are reported with their HTTP paths under our own prefix:
+    cfg_value.server.http_base_path = "/restcommander/".to_string();
+    let cfg = Cfg::try_from(cfg_value)?;
+    let commands = cmd::tree::Command::new(
+        &cfg.config_value.commands.root_directory,
+        &PathBuf::from(cfg.config_value.server.http_base_path.clone()).join(
+            PathBuf::from(http::API_RUN_BASE_PATH)
+                .strip_prefix("/")
+                .unwrap(),
+        ),
+        cfg.config_value.commands.environment.clone(),
+    )
+    .map_err(|reason| reason.to_string())?;
+    let report_state = report::maybe_setup(cfg.config_value.logging.clone(), None).await?;
+    let cfg = Arc::new(ArcSwap::from_pointee(cfg));
+    // Configuration is not reloaded from a file here, So reload messages are dropped:
+    let (reload_sender, _reload_receiver) = tokio::sync::mpsc::channel(1);
+    let commands = Arc::new(ArcSwap::from_pointee(commands));
+    let report_state = Arc::new(AsyncRwLock::new(report_state));
+    let maintenance = http::MaintenanceState::default();
+    let drain_state = http::DrainState::default();
+    let command_semaphores = http::CommandSemaphores::default();
+    let command_rate_limits = http::CommandRateLimits::default();
+    let tokens = http::AuthTokens::default();
+    let api = http::api_filter(
+        cfg.clone(),
+        commands.clone(),
+        report_state.clone(),
+        maintenance.clone(),
+        reload_sender,
+        drain_state.clone(),
+        http::TrafficState::default(),
+        command_semaphores.clone(),
+        command_rate_limits.clone(),
+        tokens.clone(),
+    );
+    // Tokens that are issued via `/restcommander/api/auth/token` are accepted here too:
+    let deploy = warp::path("deploy")
+        .and(http::authentication_filter(tokens.clone(), cfg.clone()).untuple_one())
+        .and(http::api_run_command_filter(
+            cfg.clone(),
+            commands,
+            report_state,
+            maintenance,
+            command_semaphores,
+            drain_state,
+            command_rate_limits,
+            tokens,
+        ))
+        .map(warp::Reply::into_response);
+    let routes = warp::path("restcommander")
+        .and(warp::path("api"))
+        .and(api)
+        .or(deploy)
+        .unify()
+        .recover(move |rejection| http::handle_rejection(rejection, cfg.clone(), false));
+    warp::serve(routes).run(([127, 0, 0, 1], 8080)).await;
+    Ok(())
+}
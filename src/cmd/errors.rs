@@ -1,7 +1,9 @@
 use crate::cmd::CommandInput;
 use serde_yaml;
+use std::collections::BTreeMap;
 use std::io;
 use std::path::PathBuf;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -84,4 +86,45 @@ pub enum CommandError {
     NoCommandInfo { filename: PathBuf },
     #[error("Could not found command state information for command {filename:?}")]
     NoCommandState { filename: PathBuf },
+    #[error("command {command:?} did not finish in {timeout:?} and is killed")]
+    Timeout { command: PathBuf, timeout: Duration },
+    #[error("command {command:?} is cancelled and killed")]
+    Cancelled { command: PathBuf },
+    #[error("alias {alias:?} refers to unknown command {target:?}: {message}")]
+    UnknownAlias {
+        alias: String,
+        target: String,
+        message: String,
+    },
+    #[error("could not render arguments of command {command:?}: {message}")]
+    RenderArguments { command: PathBuf, message: String },
+    #[error("could not run command {command:?} as configured user or group: {message}")]
+    RunAs { command: PathBuf, message: String },
+}
+
+/// Invalid input options of a command.
+#[derive(Debug, Clone, Error)]
+#[error("{message}")]
+pub struct CheckInputError {
+    /// Summary of all errors.
+    pub message: String,
+    /// Option name to its error message. It is empty for errors that are not about a single
+    /// option (e.g. rendering `arguments`).
+    pub fields: BTreeMap<String, String>,
+}
+
+impl CheckInputError {
+    pub fn from_fields(fields: BTreeMap<String, String>) -> Self {
+        Self {
+            message: fields.values().cloned().collect::<Vec<_>>().join("; "),
+            fields,
+        }
+    }
+
+    pub fn from_message(message: String) -> Self {
+        Self {
+            message,
+            fields: BTreeMap::new(),
+        }
+    }
 }
@@ -1,12 +1,20 @@
-use crate::cmd::errors::CommandError;
+use crate::cmd::errors::{CheckInputError, CommandError};
 pub use crate::cmd::runner::CommandInstruction;
-pub use crate::cmd::runner::{CommandInput, CommandOutput, CommandStats};
-pub use crate::cmd::tree::{Command, CommandInfoGetState, CommandOptionInfo};
+pub use crate::cmd::runner::{
+    CommandInput, CommandOutput, CommandRunSettings, CommandSignal, CommandStats,
+    CommandStdinStream,
+};
+use crate::cmd::runner::{CommandOptionsValue, CommandPriority, CommandRunAs};
+pub use crate::cmd::tree::{
+    Command, CommandInfoGetState, CommandInfoOutputFormat, CommandOptionInfo, CommandRateLimit,
+};
 use crate::cmd::tree::{
-    CommandOptionInfoValueSize, CommandOptionInfoValueType, CommandOptionValue,
+    CommandOptionDependency, CommandOptionInfoValueSize, CommandOptionInfoValueType,
+    CommandOptionTransform, CommandOptionValue,
 };
 use num_traits::cast::FromPrimitive;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
 
 pub mod errors;
 pub mod runner;
@@ -14,25 +22,110 @@ pub mod tree;
 
 static MAX_COMMAND_DIRECTORY_DEPTH: usize = 5;
 
+/// Returns configured `output_format` of command if any.
+pub fn output_format(command: &Command) -> Option<CommandInfoOutputFormat> {
+    command.info.as_ref().and_then(|info| info.output_format)
+}
+
+/// Decodes command's stdout according to its configured `output_format`.
+///
+/// Commands without `output_format` keep the default behavior (JSON or plain string).
+fn maybe_decode_output(command: &Command, mut output: CommandOutput) -> CommandOutput {
+    if let Some(format) = output_format(command) {
+        output.decoded_stdout = decode_output(format, &output.stdout);
+    };
+    output
+}
+
+pub fn decode_output(
+    format: CommandInfoOutputFormat,
+    stdout: &str,
+) -> Result<serde_json::Value, String> {
+    match format {
+        CommandInfoOutputFormat::Json => {
+            serde_json::from_str(stdout).map_err(|reason| reason.to_string())
+        }
+        CommandInfoOutputFormat::Yaml => {
+            serde_yaml::from_str(stdout).map_err(|reason| reason.to_string())
+        }
+        CommandInfoOutputFormat::Lines => Ok(serde_json::Value::Array(
+            stdout
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| serde_json::Value::String(line.to_string()))
+                .collect(),
+        )),
+        CommandInfoOutputFormat::Kv => {
+            let mut object = serde_json::Map::new();
+            for (line_number, line) in stdout.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                };
+                let (key, value) = line.split_once('=').ok_or_else(|| {
+                    format!(
+                        "line {} {:?} is not in form of key=value",
+                        line_number + 1,
+                        line
+                    )
+                })?;
+                object.insert(
+                    key.trim().to_string(),
+                    serde_json::Value::String(value.trim().to_string()),
+                );
+            }
+            Ok(serde_json::Value::Object(object))
+        }
+        CommandInfoOutputFormat::Raw => Ok(serde_json::Value::String(stdout.to_string())),
+    }
+}
+
+/// Finds the command for given path and returns it with its captured path variables.
+///
+/// A command or directory named `{NAME}` matches any path segment (when no command with exactly
+/// the same name exists) and that segment is captured as path variable `NAME`.
 pub fn search_for_command(
     command_path_list: &Vec<String>,
     command: &Command,
-) -> Result<Command, CommandError> {
+) -> Result<(Command, HashMap<String, String>), CommandError> {
     let first_element = command_path_list[0].clone();
-    if first_element == command.name {
+    let maybe_path_variable_name = path_variable_name(&command.name);
+    if first_element == command.name || maybe_path_variable_name.is_some() {
         if command_path_list.len() > 1 {
             if command.is_directory {
                 let second_element = command_path_list[1].clone();
                 // println!("{:?} - {:?} - {:?}", second_element, command_path_list, command.name);
+                let mut result = Err(CommandError::FindCommand {
+                    command_name: command.name.clone(),
+                    http_path: command.http_path.clone(),
+                });
                 if command.commands.contains_key(second_element.as_str()) {
-                    return search_for_command(
+                    result = search_for_command(
                         &command_path_list[1..].to_owned(),
                         command.commands.get(second_element.as_str()).unwrap(),
                     );
                 };
-                return Err(CommandError::FindCommand {
-                    command_name: command.name.clone(),
-                    http_path: command.http_path.clone(),
+                if result.is_err() {
+                    let mut template_list: Vec<&Command> = command
+                        .commands
+                        .values()
+                        .filter(|command| path_variable_name(&command.name).is_some())
+                        .collect();
+                    template_list.sort_by(|x, y| x.name.cmp(&y.name));
+                    for template in template_list {
+                        let template_result =
+                            search_for_command(&command_path_list[1..].to_owned(), template);
+                        if template_result.is_ok() {
+                            result = template_result;
+                            break;
+                        };
+                    }
+                };
+                return result.map(|(found_command, mut path_variables)| {
+                    if let Some(name) = maybe_path_variable_name {
+                        path_variables.insert(name, first_element);
+                    };
+                    (found_command, path_variables)
                 });
             };
             return Err(CommandError::CommandIsNotDirectory {
@@ -40,11 +133,29 @@ pub fn search_for_command(
             });
         };
         if command.is_directory {
+            // Run configured `default_command` of the directory if any:
+            if let Some(default_command) = command
+                .info
+                .as_ref()
+                .and_then(|info| info.default_command.as_ref())
+                .and_then(|name| command.commands.get(name))
+                .filter(|default_command| !default_command.is_directory)
+            {
+                let mut path_variables = HashMap::new();
+                if let Some(name) = maybe_path_variable_name {
+                    path_variables.insert(name, first_element);
+                };
+                return Ok((default_command.clone(), path_variables));
+            };
             return Err(CommandError::CommandIsDirectory {
                 http_path: command.http_path.clone(),
             });
         };
-        return Ok(command.clone());
+        let mut path_variables = HashMap::new();
+        if let Some(name) = maybe_path_variable_name {
+            path_variables.insert(name, first_element);
+        };
+        return Ok((command.clone(), path_variables));
     };
     return Err(CommandError::FindCommand {
         command_name: command.name.clone(),
@@ -52,22 +163,221 @@ pub fn search_for_command(
     });
 }
 
+/// Follows configured aliases of the command path (relative to root command).
+///
+/// Aliases are checked for cycles when configuration is loaded.
+pub fn resolve_alias(aliases: &HashMap<String, String>, command_path: &str) -> String {
+    let mut command_path = command_path.trim_matches('/');
+    for _ in 0..=aliases.len() {
+        match aliases.get(command_path) {
+            Some(target) => command_path = target.as_str(),
+            None => break,
+        }
+    }
+    command_path.to_string()
+}
+
+/// Makes sure every configured alias refers to an existing command.
+pub fn check_aliases(
+    aliases: &HashMap<String, String>,
+    root_command: &Command,
+) -> Result<(), CommandError> {
+    for alias in aliases.keys() {
+        let target = resolve_alias(aliases, alias);
+        let command_path_list: Vec<String> = PathBuf::from(root_command.name.clone())
+            .join(PathBuf::from(target.clone()))
+            .components()
+            .map(|x| x.as_os_str().to_str().unwrap().to_string())
+            .collect();
+        search_for_command(&command_path_list, root_command).map_err(|reason| {
+            CommandError::UnknownAlias {
+                alias: alias.clone(),
+                target,
+                message: reason.to_string(),
+            }
+        })?;
+    }
+    Ok(())
+}
+
+/// Renders command's `arguments` template to its argument list.
+///
+/// The template is split by whitespace and each `{NAME}` placeholder inside a word is replaced with
+/// the value of option `NAME`, so a value that contains whitespace is still a single argument.
+///
+/// A word with an array option is repeated for each item of it (e.g. `--target={targets}` makes
+/// `--target=a --target=b` and nothing for an empty array). Objects are JSON-encoded.
+pub fn render_arguments(
+    template: &str,
+    options: &CommandOptionsValue,
+) -> Result<Vec<String>, String> {
+    let mut argument_list = Vec::new();
+    for word in template.split_whitespace() {
+        let mut maybe_array = None;
+        for name in argument_placeholder_names(word) {
+            if let Some(CommandOptionValue::Array(list)) = options.get(name) {
+                if maybe_array.is_some_and(|(array_name, _)| array_name != name) {
+                    return Err(format!(
+                        "argument {:?} of arguments template has more than one array option",
+                        word
+                    ));
+                };
+                maybe_array = Some((name, list));
+            };
+        }
+        match maybe_array {
+            Some((array_name, list)) => {
+                for item in list {
+                    argument_list.push(render_argument(word, |name| {
+                        if name == array_name {
+                            Ok(render_argument_item(item))
+                        } else {
+                            render_argument_value(name, options.get(name))
+                        }
+                    })?);
+                }
+            }
+            None => argument_list.push(render_argument(word, |name| {
+                render_argument_value(name, options.get(name))
+            })?),
+        }
+    }
+    Ok(argument_list)
+}
+
+/// Returns `NAME` of every `{NAME}` placeholder of the word.
+fn argument_placeholder_names(word: &str) -> Vec<&str> {
+    let mut name_list = Vec::new();
+    let mut rest = word;
+    while let Some((_, after)) = rest.split_once('{') {
+        match after.split_once('}') {
+            Some((name, after)) => {
+                name_list.push(name);
+                rest = after;
+            }
+            None => break,
+        }
+    }
+    name_list
+}
+
+fn render_argument(
+    word: &str,
+    render_value: impl Fn(&str) -> Result<String, String>,
+) -> Result<String, String> {
+    let mut argument = String::new();
+    let mut rest = word;
+    while let Some((before, after)) = rest.split_once('{') {
+        let (name, after) = match after.split_once('}') {
+            Some(placeholder) => placeholder,
+            None => break,
+        };
+        argument.push_str(before);
+        argument.push_str(&render_value(name)?);
+        rest = after;
+    }
+    argument.push_str(rest);
+    Ok(argument)
+}
+
+fn render_argument_value(name: &str, value: Option<&CommandOptionValue>) -> Result<String, String> {
+    match value {
+        Some(CommandOptionValue::None) | None => Err(format!(
+            "option {} of arguments template is not given and has no default value",
+            name
+        )),
+        Some(value) => Ok(render_argument_item(value)),
+    }
+}
+
+fn render_argument_item(value: &CommandOptionValue) -> String {
+    match value {
+        CommandOptionValue::String(value) => value.clone(),
+        CommandOptionValue::Integer(value) => value.to_string(),
+        CommandOptionValue::BigInteger(value) => value.to_string(),
+        CommandOptionValue::Float(value) => value.to_string(),
+        CommandOptionValue::Bool(value) => value.to_string(),
+        CommandOptionValue::None => String::new(),
+        CommandOptionValue::Array(_) | CommandOptionValue::Object(_) => {
+            serde_json::to_string(value).unwrap_or_default()
+        }
+    }
+}
+
+/// Returns `NAME` if command name is in form of `{NAME}`.
+fn path_variable_name(command_name: &str) -> Option<String> {
+    command_name
+        .strip_prefix('{')
+        .and_then(|name| name.strip_suffix('}'))
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+}
+
 pub fn run_command(
     command: &Command,
     input: &CommandInput,
     env_map: HashMap<String, String>,
+    settings: &CommandRunSettings,
+    maybe_stdin_stream: Option<CommandStdinStream>,
 ) -> Result<CommandOutput, CommandError> {
     if command.is_directory {
         return Err(CommandError::CommandIsDirectory {
             http_path: command.http_path.clone(),
         });
     };
-    runner::run_command(&command.file_path, Vec::new(), Some(input), true, env_map)
+    runner::run_command(
+        &command.file_path,
+        command_arguments(command, input)?,
+        Some(input),
+        true,
+        env_map,
+        &command_run_settings(command, settings),
+        maybe_stdin_stream,
+    )
+    .map(|output| maybe_decode_output(command, output))
+}
+
+/// Renders configured `arguments` of command from its input options.
+pub fn command_arguments(
+    command: &Command,
+    input: &CommandInput,
+) -> Result<Vec<String>, CommandError> {
+    match command
+        .info
+        .as_ref()
+        .and_then(|info| info.arguments.as_ref())
+    {
+        Some(template) => render_arguments(template, &input.options).map_err(|message| {
+            CommandError::RenderArguments {
+                command: command.file_path.clone(),
+                message,
+            }
+        }),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Adds configured user, group, resource limits and priority of command to its run settings.
+fn command_run_settings(command: &Command, settings: &CommandRunSettings) -> CommandRunSettings {
+    let mut settings = settings.clone();
+    if let Some(ref info) = command.info {
+        settings.run_as = CommandRunAs {
+            maybe_user: info.run_as_user.clone(),
+            maybe_group: info.run_as_group.clone(),
+        };
+        settings.limits = info.limits.or(&settings.limits);
+        settings.priority = CommandPriority {
+            maybe_nice: info.nice,
+            maybe_ionice: info.ionice,
+        };
+    };
+    settings
 }
 
 pub fn get_state(
     command: &Command,
     env_map: HashMap<String, String>,
+    settings: &CommandRunSettings,
 ) -> Result<CommandOutput, CommandError> {
     if let Some(ref info) = command.info {
         if info.support_state && info.state.is_some() {
@@ -79,9 +389,16 @@ pub fn get_state(
                     output.decoded_stdout = Ok(serde_json::Value::String(value.clone()));
                     Ok(output)
                 }
-                CommandInfoGetState::Options(options) => {
-                    runner::run_command(&command.file_path, options.clone(), None, true, env_map)
-                }
+                CommandInfoGetState::Options(options) => runner::run_command(
+                    &command.file_path,
+                    options.clone(),
+                    None,
+                    true,
+                    env_map,
+                    &command_run_settings(command, settings),
+                    None,
+                )
+                .map(|output| maybe_decode_output(command, output)),
             }
         } else {
             Err(CommandError::NoCommandState {
@@ -95,20 +412,57 @@ pub fn get_state(
     }
 }
 
-pub fn check_input(command: &Command, input: &CommandInput) -> Result<CommandInput, String> {
+/// Checks and converts input options and collects the error of every invalid option.
+pub fn check_input(
+    command: &Command,
+    input: &CommandInput,
+) -> Result<CommandInput, CheckInputError> {
     let mut new_input = input.clone();
     if command.info.is_none() {
         return Ok(new_input);
     };
+    let mut field_errors = BTreeMap::new();
     for (option, definition) in &command.info.as_ref().unwrap().options {
+        if let Some(header) = definition.header.as_ref() {
+            match input.headers.get(&header.to_lowercase()) {
+                Some(value) => {
+                    new_input.options.insert(
+                        option.clone(),
+                        serde_json::from_str::<CommandOptionValue>(value)
+                            .unwrap_or_else(|_| CommandOptionValue::String(value.clone())),
+                    );
+                }
+                None => {
+                    if definition.required && definition.default_value.is_none() {
+                        field_errors.insert(
+                            option.clone(),
+                            format!(
+                                "required option {} is not given via header {}",
+                                option, header
+                            ),
+                        );
+                        continue;
+                    };
+                    new_input.options.remove(option.as_str());
+                }
+            };
+        };
         let new_value = if new_input.options.contains_key(option.as_str()) {
             let input_value = new_input.options.get(option.as_str()).unwrap();
-            check_definition(
+            match check_definition(
                 &option,
                 &definition.value_type,
                 input_value,
                 &definition.size,
-            )?
+            )
+            .and_then(|value| transform_value(&option, &definition.transform, value))
+            {
+                Ok(value) => value,
+                Err(reason) => {
+                    field_errors.insert(option.clone(), reason);
+                    continue;
+                }
+            }
         } else {
             if definition.default_value.is_none() {
                 match definition.value_type {
@@ -117,10 +471,14 @@ pub fn check_input(command: &Command, input: &CommandInput) -> Result<CommandInp
                     _ => {
                         // So it is required
                         if definition.required {
-                            return Err(format!(
-                                "required option {} is not given and has no default value",
-                                option
-                            ));
+                            field_errors.insert(
+                                option.clone(),
+                                format!(
+                                    "required option {} is not given and has no default value",
+                                    option
+                                ),
+                            );
+                            continue;
                         };
                         unreachable!();
                     }
@@ -131,10 +489,62 @@ pub fn check_input(command: &Command, input: &CommandInput) -> Result<CommandInp
         };
         new_input.options.insert(option.clone(), new_value);
     }
-
+    check_dependencies(
+        &command.info.as_ref().unwrap().dependencies,
+        input,
+        &new_input,
+        &mut field_errors,
+    );
+    if !field_errors.is_empty() {
+        return Err(CheckInputError::from_fields(field_errors));
+    };
+    if let Some(ref template) = command.info.as_ref().unwrap().arguments {
+        render_arguments(template, &new_input.options).map_err(CheckInputError::from_message)?;
+    };
     Ok(new_input)
 }
 
+/// Conditions are checked against options after applying default values, but required options
+/// have to be given by the user. Errors are added for missing required options.
+fn check_dependencies(
+    dependencies: &[CommandOptionDependency],
+    given_input: &CommandInput,
+    input: &CommandInput,
+    field_errors: &mut BTreeMap<String, String>,
+) {
+    for dependency in dependencies {
+        let matched = match (&dependency.equals, input.options.get(&dependency.field)) {
+            (None, _) => given_input.options.contains_key(&dependency.field),
+            (Some(expected_value), Some(value)) => expected_value == value,
+            (Some(_), None) => false,
+        };
+        if !matched {
+            continue;
+        };
+        for option in dependency
+            .requires
+            .iter()
+            .filter(|option| !given_input.options.contains_key(option.as_str()))
+        {
+            // The first error of an option is kept:
+            field_errors
+                .entry(option.clone())
+                .or_insert_with(|| match dependency.equals {
+                    Some(ref value) => format!(
+                        "option '{}' is required when option '{}' is {}",
+                        option,
+                        dependency.field,
+                        serde_json::to_string(value).unwrap_or_default()
+                    ),
+                    None => format!(
+                        "option '{}' is required when option '{}' is given",
+                        option, dependency.field
+                    ),
+                });
+        }
+    }
+}
+
 fn check_definition(
     option: &str,
     definition: &CommandOptionInfoValueType,
@@ -156,9 +566,72 @@ fn check_definition(
         (CommandOptionInfoValueType::Integer, CommandOptionValue::Integer(value)) => {
             Ok(CommandOptionValue::Integer(value.clone()))
         }
+        (CommandOptionInfoValueType::Integer, CommandOptionValue::BigInteger(value)) => {
+            Err(format!(
+                "option '{}' takes 'Integer' type but {} is out of its range",
+                option, value
+            ))
+        }
         (CommandOptionInfoValueType::Float, CommandOptionValue::Float(value)) => {
             Ok(CommandOptionValue::Float(value.clone()))
         }
+        (CommandOptionInfoValueType::Float, CommandOptionValue::BigInteger(value)) => Ok(
+            CommandOptionValue::Float(value.as_f64().unwrap_or_default()),
+        ),
+        // Arrays and objects can be given as JSON strings (e.g. via query-string or headers):
+        (
+            CommandOptionInfoValueType::Array(_) | CommandOptionInfoValueType::Object(_),
+            CommandOptionValue::String(value),
+        ) => match serde_json::from_str::<CommandOptionValue>(value) {
+            Ok(value @ (CommandOptionValue::Array(_) | CommandOptionValue::Object(_))) => {
+                check_definition(option, definition, &value, &None)
+            }
+            _ => Err(format!(
+                "option '{}' takes '{}' type but we got 'String' type",
+                option,
+                value_type_name(definition)
+            )),
+        },
+        (CommandOptionInfoValueType::Array(item_definition), CommandOptionValue::Array(list)) => {
+            list.iter()
+                .enumerate()
+                .map(|(index, item)| {
+                    check_definition(
+                        &format!("{}[{}]", option, index),
+                        item_definition,
+                        item,
+                        &None,
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(CommandOptionValue::Array)
+        }
+        (
+            CommandOptionInfoValueType::Object(field_definitions),
+            CommandOptionValue::Object(object),
+        ) => {
+            if let Some(field) = object
+                .keys()
+                .find(|field| !field_definitions.contains_key(field.as_str()))
+            {
+                return Err(format!("option '{}' has unknown field '{}'", option, field));
+            };
+            field_definitions
+                .iter()
+                .map(|(field, field_definition)| {
+                    let field_option = format!("{}.{}", option, field);
+                    match (object.get(field), field_definition) {
+                        (Some(value), _) => {
+                            check_definition(&field_option, field_definition, value, &None)
+                        }
+                        (None, CommandOptionInfoValueType::Any) => Ok(CommandOptionValue::None),
+                        (None, _) => Err(format!("option '{}' is not given", field_option)),
+                    }
+                    .map(|value| (field.clone(), value))
+                })
+                .collect::<Result<BTreeMap<_, _>, _>>()
+                .map(CommandOptionValue::Object)
+        }
         (CommandOptionInfoValueType::Enum(accepted_value_list), value) => match value {
             CommandOptionValue::String(string_value) => {
                 if accepted_value_list.contains(string_value) {
@@ -187,29 +660,87 @@ fn check_definition(
         },
         // Type Errors:
         (x, y) => {
-            let x_type = match x {
-                CommandOptionInfoValueType::Boolean => "Boolean",
-                CommandOptionInfoValueType::String => "String",
-                CommandOptionInfoValueType::Integer => "Integer",
-                CommandOptionInfoValueType::Float => "Float",
-                CommandOptionInfoValueType::Any => "None",
-                _ => unreachable!(),
-            };
             let y_type = match y {
                 CommandOptionValue::None => "None",
                 CommandOptionValue::Float(_) => "Float",
-                CommandOptionValue::Integer(_) => "Integer",
+                CommandOptionValue::Integer(_) | CommandOptionValue::BigInteger(_) => "Integer",
                 CommandOptionValue::String(_) => "String",
                 CommandOptionValue::Bool(_) => "Boolean",
+                CommandOptionValue::Array(_) => "Array",
+                CommandOptionValue::Object(_) => "Object",
             };
             Err(format!(
                 "option '{}' takes '{}' type but we got '{}' type",
-                option, x_type, y_type
+                option,
+                value_type_name(x),
+                y_type
             ))
         }
     }
 }
 
+fn value_type_name(definition: &CommandOptionInfoValueType) -> &'static str {
+    match definition {
+        CommandOptionInfoValueType::Boolean => "Boolean",
+        CommandOptionInfoValueType::String => "String",
+        CommandOptionInfoValueType::Integer => "Integer",
+        CommandOptionInfoValueType::Float => "Float",
+        CommandOptionInfoValueType::Any => "None",
+        CommandOptionInfoValueType::Enum(_) => "String",
+        CommandOptionInfoValueType::Array(_) => "Array",
+        CommandOptionInfoValueType::Object(_) => "Object",
+    }
+}
+
+/// Applies `transform` list of an option to its validated value in order.
+fn transform_value(
+    option: &str,
+    transform: &[CommandOptionTransform],
+    mut value: CommandOptionValue,
+) -> Result<CommandOptionValue, String> {
+    for transformation in transform {
+        let string_value = match value {
+            CommandOptionValue::String(string_value) => string_value,
+            _ => {
+                return Err(format!(
+                    "option '{}' should be 'String' to be transformed via '{}'",
+                    option,
+                    serde_json::to_value(transformation)
+                        .ok()
+                        .and_then(|name| name.as_str().map(|name| name.to_string()))
+                        .unwrap_or_default()
+                ))
+            }
+        };
+        value = match transformation {
+            CommandOptionTransform::Trim => {
+                CommandOptionValue::String(string_value.trim().to_string())
+            }
+            CommandOptionTransform::Lowercase => {
+                CommandOptionValue::String(string_value.to_lowercase())
+            }
+            CommandOptionTransform::Uppercase => {
+                CommandOptionValue::String(string_value.to_uppercase())
+            }
+            CommandOptionTransform::Base64Decode => base64::decode(string_value.as_str())
+                .map_err(|reason| reason.to_string())
+                .and_then(|bytes| String::from_utf8(bytes).map_err(|reason| reason.to_string()))
+                .map(CommandOptionValue::String)
+                .map_err(|reason| {
+                    format!(
+                        "could not decode option '{}' from base64: {}",
+                        option, reason
+                    )
+                })?,
+            CommandOptionTransform::JsonParse => serde_json::from_str(string_value.as_str())
+                .map_err(|reason| {
+                    format!("could not parse option '{}' as JSON: {}", option, reason)
+                })?,
+        };
+    }
+    Ok(value)
+}
+
 fn check_string(_option: &str, input: &CommandOptionValue) -> Result<CommandOptionValue, String> {
     match input {
         CommandOptionValue::String(_) => Ok(input.clone()),
@@ -222,6 +753,12 @@ fn check_string(_option: &str, input: &CommandOptionValue) -> Result<CommandOpti
         CommandOptionValue::Integer(ref value) => Ok(CommandOptionValue::String(
             serde_json::to_string(value).unwrap(),
         )),
+        CommandOptionValue::BigInteger(ref value) => {
+            Ok(CommandOptionValue::String(value.to_string()))
+        }
+        CommandOptionValue::Array(_) | CommandOptionValue::Object(_) => Ok(
+            CommandOptionValue::String(serde_json::to_string(input).unwrap()),
+        ),
         CommandOptionValue::None => Ok(CommandOptionValue::String("".to_string())),
     }
 }
@@ -235,10 +772,17 @@ fn check_integer(_option: &str, input: &CommandOptionValue) -> Result<CommandOpt
         CommandOptionValue::Float(ref value) => Ok(CommandOptionValue::Integer(
             i64::from_f64(value.clone()).unwrap(),
         )),
+        CommandOptionValue::BigInteger(ref value) => {
+            Err(format!("Could not convert `{}` to integer", value))
+        }
         CommandOptionValue::String(ref value) => match value.parse::<i64>() {
             Ok(integer_value) => Ok(CommandOptionValue::Integer(integer_value)),
             Err(_) => Err(format!("Could not convert `{}` to integer", value)),
         },
+        CommandOptionValue::Array(_) | CommandOptionValue::Object(_) => Err(format!(
+            "Could not convert `{}` to integer",
+            serde_json::to_string(input).unwrap()
+        )),
         CommandOptionValue::None => Err(format!("Could not convert null value to integer")),
     }
 }
@@ -255,6 +799,12 @@ fn check_float(option: &str, input: &CommandOptionValue) -> Result<CommandOption
         CommandOptionValue::Integer(ref value) => Ok(CommandOptionValue::String(
             serde_json::to_string(value).unwrap(),
         )),
+        CommandOptionValue::BigInteger(ref value) => {
+            Ok(CommandOptionValue::String(value.to_string()))
+        }
+        CommandOptionValue::Array(_) | CommandOptionValue::Object(_) => Ok(
+            CommandOptionValue::String(serde_json::to_string(input).unwrap()),
+        ),
         CommandOptionValue::None => Ok(CommandOptionValue::String("".to_string())),
     }
 }
@@ -271,6 +821,12 @@ fn check_bool(option: &str, input: &CommandOptionValue) -> Result<CommandOptionV
         CommandOptionValue::Integer(ref value) => Ok(CommandOptionValue::String(
             serde_json::to_string(value).unwrap(),
         )),
+        CommandOptionValue::BigInteger(ref value) => {
+            Ok(CommandOptionValue::String(value.to_string()))
+        }
+        CommandOptionValue::Array(_) | CommandOptionValue::Object(_) => Ok(
+            CommandOptionValue::String(serde_json::to_string(input).unwrap()),
+        ),
         CommandOptionValue::None => Ok(CommandOptionValue::String("".to_string())),
     }
 }
@@ -283,8 +839,11 @@ fn check_size(
     let maybe_input_size = match input {
         &CommandOptionValue::String(ref x) => Some((x.len() as f64).clone()),
         &CommandOptionValue::Integer(ref x) => Some((*x as f64).clone()),
+        &CommandOptionValue::BigInteger(ref x) => x.as_f64(),
         &CommandOptionValue::Float(ref x) => Some(x.clone()),
+        &CommandOptionValue::Array(ref x) => Some(x.len() as f64),
         &CommandOptionValue::Bool(_) => None,
+        &CommandOptionValue::Object(_) => None,
         &CommandOptionValue::None => None,
     };
     if let Some(input_size) = maybe_input_size {
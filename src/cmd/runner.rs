@@ -1,21 +1,30 @@
 use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::io;
 use std::io::ErrorKind;
 use std::io::{Read, Write};
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::time::Instant;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 use std::{process, process::Stdio};
 use tracing::{debug, error, info, trace, warn};
 
 use super::errors::CommandError;
 pub use crate::cmd::tree::CommandOptionValue;
+use crate::utils::{truncate_line, truncate_lines};
 
 pub type CommandOptionsValue = HashMap<String, CommandOptionValue>;
+/// Chunks of a request body that are written to command's stdin as they arrive.
+pub type CommandStdinStream = tokio::sync::mpsc::Receiver<Vec<u8>>;
 
 #[derive(Clone, Debug)]
 pub struct CommandOutput {
     pub exit_code: i32,
+    pub signal: Option<CommandSignal>,
     pub stdout: String,
     pub stderr: String,
     pub decoded_stdout: Result<serde_json::Value, String>,
@@ -27,6 +36,7 @@ impl CommandOutput {
     pub fn new() -> Self {
         Self {
             exit_code: 0,
+            signal: None,
             stdout: "".to_string(),
             stderr: "".to_string(),
             decoded_stdout: Ok(serde_json::Value::String(String::new())),
@@ -40,6 +50,8 @@ impl CommandOutput {
 pub struct CommandStats {
     pub duration: CommandStatsDuration,
     pub size: CommandStatsSize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal: Option<CommandSignal>,
 }
 
 impl CommandStats {
@@ -47,10 +59,130 @@ impl CommandStats {
         Self {
             duration: CommandStatsDuration::new(),
             size: CommandStatsSize::new(),
+            signal: None,
         }
     }
 }
 
+/// Signal that terminated a command process (Unix only).
+#[derive(Clone, Debug, Serialize)]
+pub struct CommandSignal {
+    pub number: i32,
+    pub name: String,
+    /// Why the process got the signal if it is known (e.g. it exceeded a resource limit).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl CommandSignal {
+    pub fn new(number: i32) -> Self {
+        Self {
+            number,
+            name: signal_name(number),
+            reason: signal_reason(number),
+        }
+    }
+
+    /// Like [CommandSignal::new] but also explains signals that are caused by configured resource
+    /// `limits` of the process.
+    pub fn with_limits(number: i32, limits: &CommandLimits) -> Self {
+        let mut signal = Self::new(number);
+        if signal.reason.is_none() {
+            signal.reason = limit_signal_reason(number, limits);
+        };
+        signal
+    }
+}
+
+#[cfg(unix)]
+fn signal_reason(number: i32) -> Option<String> {
+    match number {
+        libc::SIGXCPU => Some("CPU time limit exceeded".to_string()),
+        libc::SIGXFSZ => Some("file size limit exceeded".to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn signal_reason(_number: i32) -> Option<String> {
+    None
+}
+
+#[cfg(unix)]
+fn limit_signal_reason(number: i32, limits: &CommandLimits) -> Option<String> {
+    match number {
+        // A process that ignores `SIGXCPU` gets `SIGKILL` when it reaches the hard CPU time limit:
+        libc::SIGKILL if limits.maybe_cpu_time.is_some() => {
+            Some("CPU time limit exceeded".to_string())
+        }
+        // Memory allocations fail after reaching the limit and most programs abort or crash then:
+        libc::SIGKILL | libc::SIGSEGV | libc::SIGABRT | libc::SIGBUS
+            if limits.maybe_memory.is_some() =>
+        {
+            Some("memory limit exceeded".to_string())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(not(unix))]
+fn limit_signal_reason(_number: i32, _limits: &CommandLimits) -> Option<String> {
+    None
+}
+
+#[cfg(unix)]
+const SIGNAL_LIST: [(i32, &str); 20] = [
+    (libc::SIGHUP, "SIGHUP"),
+    (libc::SIGINT, "SIGINT"),
+    (libc::SIGQUIT, "SIGQUIT"),
+    (libc::SIGILL, "SIGILL"),
+    (libc::SIGTRAP, "SIGTRAP"),
+    (libc::SIGABRT, "SIGABRT"),
+    (libc::SIGBUS, "SIGBUS"),
+    (libc::SIGFPE, "SIGFPE"),
+    (libc::SIGKILL, "SIGKILL"),
+    (libc::SIGUSR1, "SIGUSR1"),
+    (libc::SIGSEGV, "SIGSEGV"),
+    (libc::SIGUSR2, "SIGUSR2"),
+    (libc::SIGPIPE, "SIGPIPE"),
+    (libc::SIGALRM, "SIGALRM"),
+    (libc::SIGTERM, "SIGTERM"),
+    (libc::SIGCHLD, "SIGCHLD"),
+    (libc::SIGCONT, "SIGCONT"),
+    (libc::SIGSTOP, "SIGSTOP"),
+    (libc::SIGXCPU, "SIGXCPU"),
+    (libc::SIGXFSZ, "SIGXFSZ"),
+];
+
+#[cfg(unix)]
+pub fn signal_name(number: i32) -> String {
+    SIGNAL_LIST
+        .iter()
+        .find(|(signal_number, _)| *signal_number == number)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| format!("SIG{}", number))
+}
+
+#[cfg(not(unix))]
+pub fn signal_name(number: i32) -> String {
+    format!("SIG{}", number)
+}
+
+#[cfg(unix)]
+fn exit_code_and_signal(exit_status: &process::ExitStatus) -> (i32, Option<CommandSignal>) {
+    use std::os::unix::process::ExitStatusExt;
+    match (exit_status.code(), exit_status.signal()) {
+        (Some(exit_code), _) => (exit_code, None),
+        (None, Some(signal)) => (-1, Some(CommandSignal::new(signal))),
+        (None, None) => (-1, None),
+    }
+}
+
+#[cfg(not(unix))]
+fn exit_code_and_signal(exit_status: &process::ExitStatus) -> (i32, Option<CommandSignal>) {
+    (exit_status.code().unwrap_or(-1), None)
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct CommandStatsDuration {
     pub total: u64,
@@ -94,6 +226,11 @@ pub struct CommandInput {
     pub options: CommandOptionsValue,
     #[serde(default)]
     pub statistics: bool,
+    #[serde(default)]
+    pub verbose_errors: bool,
+    /// Request headers (with lowercase names) that options with `header` are taken from.
+    #[serde(skip)]
+    pub headers: HashMap<String, String>,
 }
 
 impl Default for CommandInput {
@@ -101,14 +238,94 @@ impl Default for CommandInput {
         Self {
             options: Default::default(),
             statistics: false,
+            verbose_errors: false,
+            headers: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CommandRunSettings {
+    pub spawn_retry_count: usize,
+    pub spawn_retry_delay: Duration,
+    pub maybe_timeout: Option<Duration>,
+    pub kill_signal: String,
+    pub kill_grace_period: Duration,
+    pub log_max_line_length: usize,
+    pub log_max_line_count: usize,
+    pub run_as: CommandRunAs,
+    pub limits: CommandLimits,
+    pub priority: CommandPriority,
+    /// The command is killed once this is set (e.g. its HTTP client is disconnected).
+    pub maybe_cancel: Option<Arc<AtomicBool>>,
+}
+
+/// Resource limits of a command process (Unix only). `None` or 0 means no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CommandLimits {
+    /// CPU time in seconds.
+    #[serde(default, rename = "cpu_time")]
+    pub maybe_cpu_time: Option<u64>,
+    /// Virtual memory size in bytes.
+    #[serde(default, rename = "memory")]
+    pub maybe_memory: Option<u64>,
+    #[serde(default, rename = "open_files")]
+    pub maybe_open_files: Option<u64>,
+}
+
+impl CommandLimits {
+    /// Uses `other` limits for limits that are not set.
+    pub fn or(&self, other: &Self) -> Self {
+        let maybe_limit = |maybe_limit: Option<u64>, maybe_other_limit: Option<u64>| {
+            maybe_limit.or(maybe_other_limit).filter(|limit| *limit > 0)
+        };
+        Self {
+            maybe_cpu_time: maybe_limit(self.maybe_cpu_time, other.maybe_cpu_time),
+            maybe_memory: maybe_limit(self.maybe_memory, other.maybe_memory),
+            maybe_open_files: maybe_limit(self.maybe_open_files, other.maybe_open_files),
         }
     }
 }
 
+/// I/O scheduling class and priority level of a command process (Linux only).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommandIONice {
+    pub class: CommandIONiceClass,
+    /// 0 (highest) to 7 (lowest). It is not used for `idle` class.
+    #[serde(default, rename = "level")]
+    pub maybe_level: Option<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandIONiceClass {
+    Realtime,
+    BestEffort,
+    Idle,
+}
+
+/// Scheduling priority of a command process.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CommandPriority {
+    /// -20 (highest) to 19 (lowest) (Unix only).
+    pub maybe_nice: Option<i32>,
+    pub maybe_ionice: Option<CommandIONice>,
+}
+
+/// OS user and group that a command process runs as (Unix only).
+///
+/// Each of them can be a name or a numeric ID. If just the user is set, Its primary group is used.
+#[derive(Clone, Debug, Default)]
+pub struct CommandRunAs {
+    pub maybe_user: Option<String>,
+    pub maybe_group: Option<String>,
+}
+
 #[derive(Clone, Debug)]
 pub enum CommandInstruction {
     Reload,
     Report(String),
+    Header(String, String),
 }
 
 impl FromStr for CommandInstruction {
@@ -117,6 +334,14 @@ impl FromStr for CommandInstruction {
         if s.starts_with("REPORT ") && s.len() > 7 {
             return Ok(Self::Report(s[7..].to_string()));
         }
+        if let Some(header) = s.strip_prefix("HEADER ") {
+            if let Some((name, value)) = header.split_once(':') {
+                return Ok(Self::Header(
+                    name.trim().to_string(),
+                    value.trim().to_string(),
+                ));
+            }
+        }
         match s.to_lowercase().as_str() {
             "reload" => Ok(Self::Reload),
             _ => Err(Self::Err::Unsupported),
@@ -124,12 +349,407 @@ impl FromStr for CommandInstruction {
     }
 }
 
+#[cfg(unix)]
+pub fn signal_number(name: &str) -> Option<i32> {
+    let name = name.trim().to_uppercase();
+    let name = if name.starts_with("SIG") {
+        name
+    } else {
+        format!("SIG{}", name)
+    };
+    SIGNAL_LIST
+        .iter()
+        .find(|(_, signal_name)| *signal_name == name.as_str())
+        .map(|(signal_number, _)| *signal_number)
+}
+
+/// Sends `signal` to all processes in the process group and returns `false` if there is none.
+#[cfg(unix)]
+fn signal_process_group(process_group_id: u32, signal: i32) -> io::Result<bool> {
+    // Negative ID means the whole process group:
+    if unsafe { libc::kill(-(process_group_id as libc::pid_t), signal) } == 0 {
+        Ok(true)
+    } else {
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() == Some(libc::ESRCH) {
+            Ok(false)
+        } else {
+            Err(error)
+        }
+    }
+}
+
+/// Kills command process and all processes in its process group.
+///
+/// Configured `kill_signal` is sent first and if there are still running processes after
+/// `kill_grace_period`, they are killed with `SIGKILL`.
+#[cfg(unix)]
+fn kill_process_tree(process_id: u32, settings: &CommandRunSettings) -> io::Result<()> {
+    // Command process is its process group leader:
+    let kill_signal = signal_number(&settings.kill_signal).unwrap_or(libc::SIGKILL);
+    if kill_signal == libc::SIGKILL || settings.kill_grace_period.is_zero() {
+        return signal_process_group(process_id, libc::SIGKILL).map(|_| ());
+    };
+    if !signal_process_group(process_id, kill_signal)? {
+        return Ok(());
+    };
+    let start = Instant::now();
+    while start.elapsed() < settings.kill_grace_period {
+        sleep(Duration::from_millis(100));
+        if !signal_process_group(process_id, 0)? {
+            return Ok(());
+        };
+    }
+    debug!(
+        process_group = process_id,
+        "Processes did not exit after grace period. Sending SIGKILL."
+    );
+    signal_process_group(process_id, libc::SIGKILL).map(|_| ())
+}
+
+/// Kills command process and all processes it started.
+///
+/// There are no signals on this platform, so processes are forcibly killed immediately.
+#[cfg(not(unix))]
+fn kill_process_tree(process_id: u32, _settings: &CommandRunSettings) -> io::Result<()> {
+    process::Command::new("taskkill")
+        .args(["/F", "/T", "/PID", process_id.to_string().as_str()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|_| ())
+}
+
+/// Finds user ID and primary group ID of a user name or a numeric user ID.
+#[cfg(unix)]
+fn find_user(user: &str) -> Result<(u32, Option<u32>), String> {
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; 16384];
+    let mut result = std::ptr::null_mut();
+    let maybe_uid = user.parse::<u32>().ok();
+    let code = match maybe_uid {
+        Some(uid) => unsafe {
+            libc::getpwuid_r(
+                uid,
+                &mut passwd,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        },
+        None => {
+            let name = std::ffi::CString::new(user)
+                .map_err(|_| format!("invalid user name {:?}", user))?;
+            unsafe {
+                libc::getpwnam_r(
+                    name.as_ptr(),
+                    &mut passwd,
+                    buffer.as_mut_ptr(),
+                    buffer.len(),
+                    &mut result,
+                )
+            }
+        }
+    };
+    if code != 0 {
+        return Err(format!(
+            "could not find user {:?}: {}",
+            user,
+            io::Error::from_raw_os_error(code)
+        ));
+    };
+    match (result.is_null(), maybe_uid) {
+        (false, _) => Ok((passwd.pw_uid, Some(passwd.pw_gid))),
+        // Numeric IDs do not need to exist in user database:
+        (true, Some(uid)) => Ok((uid, None)),
+        (true, None) => Err(format!("could not find user {:?}", user)),
+    }
+}
+
+/// Finds group ID of a group name or a numeric group ID.
+#[cfg(unix)]
+fn find_group(group: &str) -> Result<u32, String> {
+    if let Ok(gid) = group.parse::<u32>() {
+        return Ok(gid);
+    };
+    let name =
+        std::ffi::CString::new(group).map_err(|_| format!("invalid group name {:?}", group))?;
+    let mut group_entry: libc::group = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; 16384];
+    let mut result = std::ptr::null_mut();
+    let code = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &mut group_entry,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    if code != 0 {
+        return Err(format!(
+            "could not find group {:?}: {}",
+            group,
+            io::Error::from_raw_os_error(code)
+        ));
+    };
+    if result.is_null() {
+        return Err(format!("could not find group {:?}", group));
+    };
+    Ok(group_entry.gr_gid)
+}
+
+/// Sets user and group IDs of command process.
+///
+/// The child process changes them right before running the command and if it could not, Spawning
+/// fails. So the command never runs with our own privileges by mistake. It should be called after
+/// [set_limits] and [set_priority], So they are applied with our own privileges (e.g. negative nice
+/// value) before dropping them.
+#[cfg(unix)]
+fn set_run_as(process_command: &mut process::Command, run_as: &CommandRunAs) -> Result<(), String> {
+    use std::os::unix::process::CommandExt;
+    let maybe_user = run_as
+        .maybe_user
+        .as_ref()
+        .map(|user| find_user(user))
+        .transpose()?;
+    let maybe_gid = match run_as.maybe_group {
+        Some(ref group) => Some(find_group(group)?),
+        None => match maybe_user {
+            Some((_, None)) => {
+                return Err(format!(
+                    "could not find primary group of user {:?}, Please configure the group",
+                    run_as.maybe_user.clone().unwrap()
+                ))
+            }
+            Some((_, maybe_gid)) => maybe_gid,
+            None => None,
+        },
+    };
+    let maybe_uid = maybe_user.map(|(uid, _)| uid);
+    if maybe_uid.is_none() && maybe_gid.is_none() {
+        return Ok(());
+    };
+    // It runs in the child process after fork and after previously registered ones, So it just
+    // calls async-signal-safe functions:
+    unsafe {
+        process_command.pre_exec(move || {
+            // Supplementary groups of our own are dropped whenever user or group changes, Otherwise
+            // the command keeps them. Only root can change them and unprivileged processes do not
+            // have privileged groups to keep:
+            if libc::geteuid() == 0 && libc::setgroups(0, std::ptr::null()) != 0 {
+                return Err(io::Error::last_os_error());
+            };
+            if let Some(gid) = maybe_gid {
+                if libc::setgid(gid) != 0 {
+                    return Err(io::Error::last_os_error());
+                };
+            };
+            if let Some(uid) = maybe_uid {
+                if libc::setuid(uid) != 0 {
+                    return Err(io::Error::last_os_error());
+                };
+            };
+            Ok(())
+        });
+    };
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type LimitResource = libc::__rlimit_resource_t;
+#[cfg(all(unix, not(all(target_os = "linux", target_env = "gnu"))))]
+type LimitResource = libc::c_int;
+
+/// Sets resource limits of command process before running the command.
+///
+/// If a limit could not be set, Spawning fails.
+#[cfg(unix)]
+fn set_limits(process_command: &mut process::Command, limits: CommandLimits) {
+    use std::os::unix::process::CommandExt;
+    if limits == CommandLimits::default() {
+        return;
+    };
+    let set_limit = |resource: LimitResource, soft_limit: u64, hard_limit: u64| {
+        let limit = libc::rlimit {
+            rlim_cur: soft_limit as libc::rlim_t,
+            rlim_max: hard_limit as libc::rlim_t,
+        };
+        if unsafe { libc::setrlimit(resource, &limit) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    };
+    // It runs in the child process after fork, So it just calls `setrlimit` which is
+    // async-signal-safe:
+    unsafe {
+        process_command.pre_exec(move || {
+            if let Some(cpu_time) = limits.maybe_cpu_time {
+                // Process gets `SIGXCPU` and if it ignores that, It gets `SIGKILL` a second later:
+                set_limit(libc::RLIMIT_CPU, cpu_time, cpu_time.saturating_add(1))?;
+            };
+            if let Some(memory) = limits.maybe_memory {
+                set_limit(libc::RLIMIT_AS, memory, memory)?;
+            };
+            if let Some(open_files) = limits.maybe_open_files {
+                set_limit(libc::RLIMIT_NOFILE, open_files, open_files)?;
+            };
+            Ok(())
+        });
+    };
+}
+
+/// Sets nice value and I/O priority of command process before running the command.
+///
+/// If they could not be set (e.g. negative nice value without privileges), Spawning fails.
+#[cfg(unix)]
+fn set_priority(process_command: &mut process::Command, priority: CommandPriority) {
+    use std::os::unix::process::CommandExt;
+    if priority == CommandPriority::default() {
+        return;
+    };
+    unsafe {
+        process_command.pre_exec(move || {
+            if let Some(nice) = priority.maybe_nice {
+                if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                    return Err(io::Error::last_os_error());
+                };
+            };
+            #[cfg(target_os = "linux")]
+            if let Some(ionice) = priority.maybe_ionice {
+                // From linux/ioprio.h:
+                const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+                const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+                let class = match ionice.class {
+                    CommandIONiceClass::Realtime => 1,
+                    CommandIONiceClass::BestEffort => 2,
+                    CommandIONiceClass::Idle => 3,
+                };
+                let level = ionice.maybe_level.unwrap_or_default() as libc::c_long;
+                if libc::syscall(
+                    libc::SYS_ioprio_set,
+                    IOPRIO_WHO_PROCESS,
+                    0,
+                    (class << IOPRIO_CLASS_SHIFT) | level,
+                ) != 0
+                {
+                    return Err(io::Error::last_os_error());
+                };
+            };
+            Ok(())
+        });
+    };
+}
+
+#[cfg(not(unix))]
+fn set_priority(_process_command: &mut process::Command, _priority: CommandPriority) {}
+
+#[cfg(not(unix))]
+fn set_limits(_process_command: &mut process::Command, _limits: CommandLimits) {}
+
+#[cfg(not(unix))]
+fn set_run_as(
+    _process_command: &mut process::Command,
+    run_as: &CommandRunAs,
+) -> Result<(), String> {
+    if run_as.maybe_user.is_some() || run_as.maybe_group.is_some() {
+        return Err("running commands as another user is only supported on Unix".to_string());
+    };
+    Ok(())
+}
+
+fn spawn(
+    command: &PathBuf,
+    option_list: &Vec<String>,
+    env_map: &HashMap<String, String>,
+    settings: &CommandRunSettings,
+) -> Result<process::Child, CommandError> {
+    let mut retry_count = 0;
+    let mut retry_delay = settings.spawn_retry_delay;
+    let mut process_command = process::Command::new(command.clone());
+    process_command
+        .args(option_list)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .envs(env_map);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // Start command in its own process group, so processes it starts can be killed with it:
+        process_command.process_group(0);
+    }
+    // Limits and priority are set before dropping privileges:
+    set_limits(&mut process_command, settings.limits);
+    set_priority(&mut process_command, settings.priority);
+    set_run_as(&mut process_command, &settings.run_as).map_err(|message| CommandError::RunAs {
+        command: command.clone(),
+        message,
+    })?;
+    loop {
+        match process_command.spawn() {
+            Ok(child) => return Ok(child),
+            Err(reason)
+                if retry_count < settings.spawn_retry_count && is_transient_error(&reason) =>
+            {
+                retry_count += 1;
+                warn!(
+                    command = ?command,
+                    error = reason.to_string().as_str(),
+                    retry = retry_count,
+                    delay = ?retry_delay,
+                    "Could not create command process. Retrying."
+                );
+                sleep(retry_delay);
+                retry_delay = retry_delay
+                    .saturating_mul(2)
+                    .min(MAX_SPAWN_RETRY_DELAY.max(settings.spawn_retry_delay));
+            }
+            Err(reason) => {
+                return Err(CommandError::CreateCommandProcess {
+                    message: reason,
+                    command: command.clone(),
+                })
+            }
+        }
+    }
+}
+
+/// Maximum delay between retries of creating a command process unless the configured first delay
+/// is more.
+const MAX_SPAWN_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+const WATCHDOG_CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Why the watchdog of a command killed it.
+enum WatchdogReason {
+    Timeout,
+    Cancel,
+}
+
+fn is_transient_error(error: &io::Error) -> bool {
+    if matches!(
+        error.kind(),
+        ErrorKind::WouldBlock | ErrorKind::Interrupted | ErrorKind::OutOfMemory
+    ) {
+        return true;
+    };
+    #[cfg(unix)]
+    if let Some(code) = error.raw_os_error() {
+        return [libc::EAGAIN, libc::EMFILE, libc::ENFILE, libc::ENOMEM].contains(&code);
+    };
+    false
+}
+
 pub fn run_command(
     command: &PathBuf,
     option_list: Vec<String>,
     input: Option<&CommandInput>,
     capture_stderr: bool,
     env_map: HashMap<String, String>,
+    settings: &CommandRunSettings,
+    maybe_stdin_stream: Option<CommandStdinStream>,
 ) -> Result<CommandOutput, CommandError> {
     let mut input_string = None;
     if input.is_some() {
@@ -154,23 +774,95 @@ pub fn run_command(
     }
     let start = Instant::now();
     let start_process = Instant::now();
-    let mut child = process::Command::new(command.clone())
-        .args(option_list.clone())
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .envs(env_map)
-        .spawn()
-        .map_err(|reason| CommandError::CreateCommandProcess {
-            message: reason,
-            command: command.clone(),
-        })?;
+    let mut child = spawn(command, &option_list, &env_map, settings)?;
     let process_duration = start_process.elapsed().as_micros();
+    let (finished_sender, finished_receiver) = mpsc::channel::<()>();
+    let maybe_watchdog = if settings.maybe_timeout.is_some() || settings.maybe_cancel.is_some() {
+        let process_id = child.id();
+        let command = command.clone();
+        let settings = settings.clone();
+        Some(thread::spawn(move || {
+            let watchdog_start = Instant::now();
+            let reason = loop {
+                let maybe_remaining = settings
+                    .maybe_timeout
+                    .map(|timeout| timeout.saturating_sub(watchdog_start.elapsed()));
+                // Cancel flag is checked periodically:
+                let wait_duration = match (maybe_remaining, &settings.maybe_cancel) {
+                    (Some(remaining), Some(_)) => remaining.min(WATCHDOG_CANCEL_CHECK_INTERVAL),
+                    (Some(remaining), None) => remaining,
+                    (None, _) => WATCHDOG_CANCEL_CHECK_INTERVAL,
+                };
+                if let Err(mpsc::RecvTimeoutError::Disconnected) =
+                    finished_receiver.recv_timeout(wait_duration)
+                {
+                    return None;
+                };
+                if settings
+                    .maybe_cancel
+                    .as_ref()
+                    .is_some_and(|cancel| cancel.load(Ordering::SeqCst))
+                {
+                    warn!(command = ?command, "Command is cancelled. Killing it.");
+                    break WatchdogReason::Cancel;
+                };
+                if let Some(timeout) = settings.maybe_timeout {
+                    if watchdog_start.elapsed() >= timeout {
+                        warn!(command = ?command, timeout = ?timeout, "Command timed out. Killing it.");
+                        break WatchdogReason::Timeout;
+                    };
+                };
+            };
+            if let Err(reason) = kill_process_tree(process_id, &settings) {
+                error!(
+                    command = ?command,
+                    error = reason.to_string().as_str(),
+                    "Could not kill command process"
+                );
+            };
+            Some(reason)
+        }))
+    } else {
+        None
+    };
+
+    // Read stdout and stderr in separate threads, so a command that writes a lot while we are
+    // still writing to its stdin does not block forever on a full pipe:
+    let mut child_stdout_reader = child.stdout.take().unwrap();
+    let stdout_reader = thread::spawn(move || {
+        let mut child_stdout = String::new();
+        child_stdout_reader
+            .read_to_string(&mut child_stdout)
+            .map(|_| child_stdout)
+    });
+    let mut child_stderr_reader = child.stderr.take().unwrap();
+    let stderr_reader = thread::spawn(move || {
+        let mut child_stderr = String::new();
+        child_stderr_reader
+            .read_to_string(&mut child_stderr)
+            .map(|_| child_stderr)
+    });
 
     let mut write_to_stdin_duration = 0;
+    let mut stdin_size = 0;
     let start_write_to_stdin = Instant::now();
-    if input_string.is_some() {
-        let mut child_stdin = child.stdin.take().unwrap();
+    let mut child_stdin = child.stdin.take().unwrap();
+    if let Some(mut stdin_stream) = maybe_stdin_stream {
+        while let Some(chunk) = stdin_stream.blocking_recv() {
+            if let Err(error) = child_stdin.write_all(&chunk) {
+                warn!(
+                    command = ?command,
+                    error = error.to_string().as_str(),
+                    "Could not write request body to process stdin"
+                );
+                break;
+            };
+            stdin_size += chunk.len();
+        }
+        trace!(command = ?command, size = stdin_size, "Streamed request body to process stdin");
+        child_stdin.flush().unwrap_or_default();
+        write_to_stdin_duration = start_write_to_stdin.elapsed().as_micros();
+    } else if input_string.is_some() {
         child_stdin
             .write_all(input_string.clone().unwrap().as_bytes())
             .map(|result| {
@@ -186,8 +878,10 @@ pub fn run_command(
                 error
             }).unwrap_or_default();
         child_stdin.flush().unwrap_or_default();
+        stdin_size = input_string.as_ref().map(String::len).unwrap_or_default();
         write_to_stdin_duration = start_write_to_stdin.elapsed().as_micros();
     };
+    drop(child_stdin);
 
     let wait_for_child = child
         .wait()
@@ -196,37 +890,66 @@ pub fn run_command(
             command: command.clone(),
         })?;
     let command_duration = start.elapsed().as_micros();
-    let child_exit_code = wait_for_child.code().unwrap();
-
-    let mut child_stdout = String::new();
-    child
-        .stdout
-        .take()
-        .unwrap()
-        .read_to_string(&mut child_stdout)
-        .map_err(|reason| CommandError::ReadCommandStdout {
-            message: reason,
-            command: command.clone(),
-        })?;
+    let (child_exit_code, child_signal) = exit_code_and_signal(&wait_for_child);
+    // Timeout and cancellation kills are reported below, So other signals may come from limits:
+    let child_signal =
+        child_signal.map(|signal| CommandSignal::with_limits(signal.number, &settings.limits));
+
+    let mut child_stdout =
+        stdout_reader
+            .join()
+            .unwrap()
+            .map_err(|reason| CommandError::ReadCommandStdout {
+                message: reason,
+                command: command.clone(),
+            })?;
     let stdout_size = child_stdout.len();
     child_stdout = child_stdout.trim_end().to_string();
 
-    let mut child_stderr = String::new();
-    child
-        .stderr
-        .take()
-        .unwrap()
-        .read_to_string(&mut child_stderr)
-        .map_err(|reason| CommandError::ReadCommandStderr {
-            message: reason,
-            command: command.clone(),
-        })?;
+    let mut child_stderr =
+        stderr_reader
+            .join()
+            .unwrap()
+            .map_err(|reason| CommandError::ReadCommandStderr {
+                message: reason,
+                command: command.clone(),
+            })?;
     let stderr_size = child_stderr.len();
+    // Watchdog also covers processes started by the command that keep its stdout/stderr open:
+    drop(finished_sender);
+    if let Some(watchdog) = maybe_watchdog {
+        match watchdog.join().unwrap_or_default() {
+            Some(WatchdogReason::Timeout) => {
+                return Err(CommandError::Timeout {
+                    command: command.clone(),
+                    timeout: settings.maybe_timeout.unwrap_or_default(),
+                })
+            }
+            Some(WatchdogReason::Cancel) => {
+                return Err(CommandError::Cancelled {
+                    command: command.clone(),
+                })
+            }
+            None => (),
+        };
+    };
     let start_logging = Instant::now();
     child_stderr = child_stderr.trim_end().to_string();
     let mut child_log_buffer = String::new();
     let mut instruction_list = Vec::new();
+    let mut logged_line_count = 0;
+    let mut dropped_line_count = 0;
     for line in child_stderr.lines() {
+        if let Ok(instruction) = line.parse::<CommandInstruction>() {
+            instruction_list.push(instruction);
+            continue;
+        };
+        if settings.log_max_line_count != 0 && logged_line_count >= settings.log_max_line_count {
+            dropped_line_count += 1;
+            continue;
+        };
+        logged_line_count += 1;
+        let line = truncate_line(line, settings.log_max_line_length);
         if line.starts_with("INFO") {
             info!(
                 command = ?command,
@@ -255,12 +978,16 @@ pub fn run_command(
                 message = line.replacen("TRACE", "", 1).trim_start()
             )
         } else {
-            match line.parse::<CommandInstruction>() {
-                Ok(instruction) => instruction_list.push(instruction),
-                _ => child_log_buffer += line,
-            };
+            child_log_buffer += &line;
         };
     }
+    if dropped_line_count > 0 {
+        warn!(
+            command = ?command,
+            count = dropped_line_count,
+            "Dropped log lines of command stderr."
+        );
+    };
     if !child_log_buffer.is_empty() {
         error!(
             command = ?command,
@@ -269,13 +996,33 @@ pub fn run_command(
     };
     let logging_duration = start_logging.elapsed().as_micros();
     trace!(
-        stdin = input_string.clone().unwrap_or_default().as_str(),
-        stdout = child_stdout.as_str(),
-        stderr = child_stderr.as_str(),
+        stdin = truncate_lines(
+            input_string.as_deref().unwrap_or_default(),
+            settings.log_max_line_length,
+            settings.log_max_line_count
+        )
+        .as_str(),
+        stdout = truncate_lines(
+            &child_stdout,
+            settings.log_max_line_length,
+            settings.log_max_line_count
+        )
+        .as_str(),
+        stderr = truncate_lines(
+            &child_stderr,
+            settings.log_max_line_length,
+            settings.log_max_line_count
+        )
+        .as_str(),
         exit_status = child_exit_code,
+        signal = ?child_signal,
         command = ?command,
     );
-    info!(command = ?command, exit_status = child_exit_code);
+    if let Some(ref signal) = child_signal {
+        warn!(command = ?command, signal = signal.name.as_str(), "Command terminated by signal.");
+    } else {
+        info!(command = ?command, exit_status = child_exit_code);
+    };
     let decoded_stdout: Result<serde_json::Value, String> =
         match serde_json::from_str(&child_stdout) {
             Ok(value) => Ok(value),
@@ -290,6 +1037,7 @@ pub fn run_command(
         stdout: child_stdout,
         stderr: child_stderr,
         exit_code: child_exit_code,
+        signal: child_signal.clone(),
         stats: CommandStats {
             duration: CommandStatsDuration {
                 total: command_duration as u64,
@@ -298,10 +1046,75 @@ pub fn run_command(
                 logging: logging_duration as u64,
             },
             size: CommandStatsSize {
-                stdin: input_string.unwrap_or(String::new()).len(),
+                stdin: stdin_size,
                 stdout: stdout_size,
                 stderr: stderr_size,
             },
+            signal: child_signal,
         },
     })
 }
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    /// Whether process `process_id` is running (not exited or a zombie).
+    fn is_running(process_id: &str) -> bool {
+        std::fs::read_to_string(format!("/proc/{}/stat", process_id))
+            .map(|stat| {
+                // State is the first field after the executable name in parentheses:
+                let state = stat.rsplit_once(')').map(|(_, rest)| rest.trim_start());
+                !matches!(state, Some(state) if state.starts_with('Z') || state.starts_with('X'))
+            })
+            .unwrap_or(false)
+    }
+
+    #[test]
+    fn timeout_kills_processes_that_command_started() {
+        let directory = tempfile::tempdir().unwrap();
+        let pid_filename = directory.path().join("pids");
+        let command = directory.path().join("command");
+        // The background process does not keep stdout/stderr of the command open:
+        std::fs::write(
+            &command,
+            format!(
+                "#!/bin/sh\nsleep 30 > /dev/null 2>&1 &\necho $$ $! > {:?}\nwait\n",
+                pid_filename
+            ),
+        )
+        .unwrap();
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&command, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let settings = CommandRunSettings {
+            maybe_timeout: Some(Duration::from_millis(500)),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let result = run_command(
+            &command,
+            Vec::new(),
+            None,
+            false,
+            HashMap::new(),
+            &settings,
+            None,
+        );
+        assert!(matches!(result, Err(CommandError::Timeout { .. })));
+
+        let pid_list = std::fs::read_to_string(&pid_filename).unwrap();
+        let pid_list: Vec<&str> = pid_list.split_whitespace().collect();
+        // The command and the process it started in background:
+        assert_eq!(pid_list.len(), 2);
+        while pid_list.iter().any(|pid| is_running(pid)) {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "processes {:?} are still running",
+                pid_list
+            );
+            sleep(Duration::from_millis(50));
+        }
+    }
+}
@@ -1,12 +1,15 @@
 use super::errors::CommandError;
+use crate::cmd::runner::{CommandIONice, CommandIONiceClass, CommandLimits};
 use crate::cmd::MAX_COMMAND_DIRECTORY_DEPTH;
 use crate::http::API_RUN_BASE_PATH;
+use crate::select::Select;
 use serde_derive::{Deserialize, Serialize};
 use serde_yaml;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ffi::OsStr;
 use std::fs;
 use std::path::PathBuf;
+use std::str::FromStr;
 use tracing::{debug, trace, warn};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -22,8 +25,15 @@ pub struct Command {
     pub info: Option<CommandInfo>,
     #[serde(default)]
     pub is_directory: bool,
+    /// Last modification time (RFC 3339) of command file or its information file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     pub commands: HashMap<String, Command>,
+    /// Current environment name of the root command. Commands that are restricted to other
+    /// environments are disabled when it is reloaded.
+    #[serde(skip)]
+    pub environment: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -35,8 +45,110 @@ pub struct CommandInfo {
     pub state: Option<CommandInfoGetState>,
     #[serde(default, skip_deserializing)]
     pub support_state: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_format: Option<CommandInfoOutputFormat>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_content_types: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_content_types: Option<Vec<String>>,
+    /// Content-Type of `/api/run/...` responses whose output is non-JSON text. Such outputs are
+    /// sent as they are instead of being wrapped in a JSON response object.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_content_type: Option<String>,
+    /// Always respond to `/api/run/...` requests with HTTP status-code 200 and report failures
+    /// only via `ok` and `exit_code` fields. It overrides configured `commands.always_ok_status`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub always_ok_status: Option<bool>,
+    /// Respond to successful `/api/run/...` requests with empty output with HTTP status-code 204
+    /// and no body. It overrides configured `commands.no_content_on_empty_output`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_content_on_empty_output: Option<bool>,
+    /// Select expression (e.g. `.path`) of a field of JSON output. If a successful run has a
+    /// non-empty string in it, The response is `201` with this string in its `Location` header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_location: Option<String>,
+    /// `Cache-Control` header value (e.g. `max-age=60`) of successful responses. Other responses
+    /// have `no-store`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_command: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub arguments: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub run_as_user: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub run_as_group: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub limits: CommandLimits,
+    #[serde(default, skip_serializing)]
+    pub nice: Option<i32>,
+    #[serde(default, skip_serializing)]
+    pub ionice: Option<CommandIONice>,
     #[serde(default)]
     pub options: HashMap<String, CommandOptionInfo>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<CommandOptionDependency>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<CommandAuth>,
+    /// Maximum number of runs of the command at the same time (e.g. `1` to never run it
+    /// concurrently).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<usize>,
+    /// Seconds that a run waits for a free slot if `max_concurrency` is reached. If it is not set,
+    /// The run is rejected at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency_wait_timeout: Option<u64>,
+    /// Maximum rate of runs of the command. Runs that exceed it are rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<CommandRateLimit>,
+    /// Names of environments that the command (or all commands of the directory) is enabled in.
+    /// If it is empty, The command is enabled in all environments.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub environments: Vec<String>,
+    #[serde(default, skip_deserializing)]
+    pub inputs: CommandInfoInputs,
+}
+
+/// Authentication that is required to run the command in addition to the general API
+/// authentication.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommandAuth {
+    /// Names (CN) of TLS client certificates that can run the command. Wildcard characters like *
+    /// are allowed.
+    pub client_certificate_names: Vec<String>,
+}
+
+/// Token bucket rate limit of a command.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommandRateLimit {
+    pub requests_per_second: f64,
+    /// Maximum number of runs at once after an idle period. By default it is
+    /// `requests_per_second` rounded up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+}
+
+impl CommandRateLimit {
+    pub fn burst(&self) -> u32 {
+        self.burst
+            .unwrap_or_else(|| self.requests_per_second.ceil() as u32)
+            .max(1)
+    }
+}
+
+/// Compact view of `options` split by requiredness, so clients do not have to work it out.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CommandInfoInputs {
+    pub required: BTreeMap<String, CommandInfoInput>,
+    pub optional: BTreeMap<String, CommandInfoInput>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CommandInfoInput {
+    pub value_type: CommandOptionInfoValueType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_value: Option<CommandOptionValue>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -51,6 +163,26 @@ pub struct CommandOptionInfo {
     pub default_value: Option<CommandOptionValue>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub size: Option<CommandOptionInfoValueSize>,
+    /// Request header (e.g. `X-Tenant-Id`) that the value is taken from. Values of the option in
+    /// request body and query-string are ignored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+    /// Value of the option (e.g. a password) is redacted in debug information of runs.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub secret: bool,
+    /// Transformations that are applied in order to the given value after it is validated.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transform: Vec<CommandOptionTransform>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandOptionTransform {
+    Trim,
+    Lowercase,
+    Uppercase,
+    Base64Decode,
+    JsonParse,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -62,16 +194,137 @@ pub enum CommandOptionInfoValueType {
     Float,
     String,
     Enum(Vec<String>),
+    /// `array: <VALUE_TYPE>` takes a list of values of the same type.
+    Array(Box<CommandOptionInfoValueType>),
+    /// `object: {<FIELD>: <VALUE_TYPE>, ...}` takes an object with exactly these fields.
+    Object(BTreeMap<String, CommandOptionInfoValueType>),
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 #[serde(untagged)]
 pub enum CommandOptionValue {
     None,
     Bool(bool),
     Integer(i64),
+    /// Integer that does not fit in `i64` (e.g. a 64-bit unsigned ID) and is kept exactly.
+    BigInteger(serde_json::Number),
     Float(f64),
     String(String),
+    Array(Vec<CommandOptionValue>),
+    Object(BTreeMap<String, CommandOptionValue>),
+}
+
+/// Key of the single-entry map that `serde_json`'s `arbitrary_precision` feature hands numbers as.
+const ARBITRARY_PRECISION_NUMBER_KEY: &str = "$serde_json::private::Number";
+
+// With `serde_json`'s `arbitrary_precision` feature, non-integer numbers are handed to visitors as
+// a private map instead of `f64`, which an untagged derive can not match.
+impl<'de> serde::Deserialize<'de> for CommandOptionValue {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(CommandOptionValueVisitor)
+    }
+}
+
+struct CommandOptionValueVisitor;
+
+impl<'de> serde::de::Visitor<'de> for CommandOptionValueVisitor {
+    type Value = CommandOptionValue;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("null, boolean, integer, float, string, array or object")
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(CommandOptionValue::None)
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E> {
+        Ok(CommandOptionValue::None)
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Self::Value, E> {
+        Ok(CommandOptionValue::Bool(value))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E> {
+        Ok(CommandOptionValue::Integer(value))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E> {
+        Ok(i64::try_from(value)
+            .map(CommandOptionValue::Integer)
+            .unwrap_or_else(|_| CommandOptionValue::BigInteger(value.into())))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E> {
+        Ok(CommandOptionValue::Float(value))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E> {
+        Ok(CommandOptionValue::String(value.to_string()))
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<Self::Value, E> {
+        Ok(CommandOptionValue::String(value))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let mut value_list = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            value_list.push(value);
+        }
+        Ok(CommandOptionValue::Array(value_list))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        let mut object = BTreeMap::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if key == ARBITRARY_PRECISION_NUMBER_KEY && object.is_empty() {
+                let number = serde_json::Number::from_str(map.next_value::<String>()?.as_str())
+                    .map_err(serde::de::Error::custom)?;
+                return if let Some(integer) = number.as_i64() {
+                    Ok(CommandOptionValue::Integer(integer))
+                } else if is_integer_number(&number) {
+                    Ok(CommandOptionValue::BigInteger(number))
+                } else if let Some(float) = number.as_f64() {
+                    Ok(CommandOptionValue::Float(float))
+                } else {
+                    Err(serde::de::Error::invalid_value(
+                        serde::de::Unexpected::Other("number"),
+                        &self,
+                    ))
+                };
+            };
+            object.insert(key, map.next_value()?);
+        }
+        Ok(CommandOptionValue::Object(object))
+    }
+}
+
+/// Whether `number` is written as an integer (e.g. `18446744073709551616` and not `1.5` or `1e3`).
+fn is_integer_number(number: &serde_json::Number) -> bool {
+    let number = number.to_string();
+    let digits = number.strip_prefix('-').unwrap_or(number.as_str());
+    !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+/// Options in `requires` have to be given when option `field` is given (or has value `equals` if
+/// it is set).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommandOptionDependency {
+    pub field: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equals: Option<CommandOptionValue>,
+    pub requires: Vec<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -87,6 +340,35 @@ pub enum CommandInfoGetState {
     Constant(String),
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandInfoOutputFormat {
+    Json,
+    Yaml,
+    Lines,
+    Kv,
+    Raw,
+}
+
+impl CommandInfoInputs {
+    pub fn from_options(options: &HashMap<String, CommandOptionInfo>) -> Self {
+        options
+            .iter()
+            .fold(Self::default(), |mut inputs, (option, definition)| {
+                let input = CommandInfoInput {
+                    value_type: definition.value_type.clone(),
+                    default_value: definition.default_value.clone(),
+                };
+                if definition.required {
+                    inputs.required.insert(option.clone(), input);
+                } else {
+                    inputs.optional.insert(option.clone(), input);
+                };
+                inputs
+            })
+    }
+}
+
 impl Command {
     pub fn reload(&mut self) -> Result<(), CommandError> {
         self.commands = Self::detect_commands(
@@ -95,16 +377,99 @@ impl Command {
             &self.http_path,
             MAX_COMMAND_DIRECTORY_DEPTH,
         )?;
+        Self::disable_other_environments(&mut self.commands, self.environment.as_ref());
         Ok(())
     }
 
-    pub fn replace(&mut self, other: Self) {
-        self.commands = other.commands;
-        self.http_path = other.http_path;
-        self.info = other.info;
-        self.is_directory = other.is_directory;
-        self.name = other.name;
-        self.file_path = other.file_path;
+    /// Loads all commands and directory information files like [`Command::reload`] but does not
+    /// stop at the first error and returns all of them (e.g. to check commands before deploy).
+    pub fn find_problems(&self) -> Vec<CommandError> {
+        Self::detect_problems(
+            &self.file_path,
+            &self.file_path,
+            &self.http_path,
+            MAX_COMMAND_DIRECTORY_DEPTH,
+        )
+    }
+
+    fn detect_problems(
+        root_directory: &PathBuf,
+        directory: &PathBuf,
+        http_base_path: &PathBuf,
+        recursion_count: usize,
+    ) -> Vec<CommandError> {
+        if recursion_count == 0 {
+            return Vec::new();
+        };
+        let read_directory = match directory.read_dir() {
+            Ok(read_directory) => read_directory,
+            Err(reason) => {
+                return vec![CommandError::ReadDirectory {
+                    message: reason,
+                    directory: directory.clone(),
+                }]
+            }
+        };
+        let mut problem_list = Vec::new();
+        for entry in read_directory {
+            let entry = match entry {
+                Ok(entry) => entry.path(),
+                Err(reason) => {
+                    problem_list.push(CommandError::ReadDirectoryEntry {
+                        directory: directory.clone(),
+                        message: reason,
+                    });
+                    continue;
+                }
+            };
+            if entry.is_file() && is_executable::is_executable(entry.clone()) {
+                if let Err(reason) = Self::from_filename(root_directory, &entry, http_base_path) {
+                    problem_list.push(reason);
+                };
+            };
+            if entry.is_dir() {
+                if let Err(reason) = Self::detect_directory_info(&entry) {
+                    problem_list.push(reason);
+                };
+                problem_list.extend(Self::detect_problems(
+                    root_directory,
+                    &entry,
+                    http_base_path,
+                    recursion_count - 1,
+                ));
+            };
+        }
+        problem_list
+    }
+
+    /// Removes commands (and directories) that are not enabled in given environment.
+    fn disable_other_environments(
+        commands: &mut HashMap<String, Command>,
+        environment: Option<&String>,
+    ) {
+        commands.retain(|_, command| {
+            let enabled = command
+                .info
+                .as_ref()
+                .map(|info| {
+                    info.environments.is_empty()
+                        || environment
+                            .map(|environment| info.environments.contains(environment))
+                            .unwrap_or_default()
+                })
+                .unwrap_or(true);
+            if !enabled {
+                debug!(
+                    command = ?command.http_path,
+                    environment = ?environment,
+                    "Disabled command that is not enabled in current environment.",
+                );
+            };
+            enabled
+        });
+        for command in commands.values_mut() {
+            Self::disable_other_environments(&mut command.commands, environment);
+        }
     }
 
     pub fn detect_commands(
@@ -161,6 +526,7 @@ impl Command {
                 commands.insert(command_name, command);
             };
             if entry.is_dir() {
+                let info = Self::detect_directory_info(&entry)?;
                 let command = Command {
                     name: entry.file_name().unwrap().to_str().unwrap().to_string(),
                     file_path: entry.clone(),
@@ -168,14 +534,34 @@ impl Command {
                     http_path: http_base_path
                         .clone()
                         .join(entry.strip_prefix(root_directory).unwrap()),
-                    info: None,
+                    info,
                     is_directory: true,
+                    modified: None,
                     commands: Command::detect_commands(
                         root_directory,
                         &entry,
                         http_base_path,
                         recursion_count - 1,
                     )?,
+                    environment: None,
+                };
+                if let Some(default_command) = command
+                    .info
+                    .as_ref()
+                    .and_then(|info| info.default_command.as_ref())
+                {
+                    if !command
+                        .commands
+                        .get(default_command)
+                        .map(|default_command| !default_command.is_directory)
+                        .unwrap_or_default()
+                    {
+                        warn!(
+                            directory = ?entry,
+                            default_command = default_command.as_str(),
+                            "Configured default command is not found inside directory."
+                        );
+                    };
                 };
                 commands.insert(
                     entry.file_name().unwrap().to_str().unwrap().to_string(),
@@ -186,13 +572,28 @@ impl Command {
         Ok(commands)
     }
 
-    pub fn new(root_directory: &PathBuf, http_base_path: &PathBuf) -> Result<Self, CommandError> {
+    pub fn new(
+        root_directory: &PathBuf,
+        http_base_path: &PathBuf,
+        environment: Option<String>,
+    ) -> Result<Self, CommandError> {
         if !root_directory.is_dir() {
             return Err(CommandError::CommandIsNotDirectory {
                 http_path: root_directory.clone(),
             });
         };
-        let mut command = Self {
+        let mut command = Self::empty(root_directory, http_base_path, environment);
+        command.reload()?;
+        Ok(command)
+    }
+
+    /// Root command of `root_directory` without loading its commands (e.g. to be reloaded later).
+    pub fn empty(
+        root_directory: &PathBuf,
+        http_base_path: &PathBuf,
+        environment: Option<String>,
+    ) -> Self {
+        Self {
             name: root_directory
                 .file_name()
                 .unwrap_or(OsStr::new(""))
@@ -204,10 +605,34 @@ impl Command {
             http_path: http_base_path.clone(),
             info: None,
             is_directory: true,
+            modified: None,
             commands: HashMap::new(),
-        };
-        command.reload()?;
-        Ok(command)
+            environment,
+        }
+    }
+
+    /// Whether at least one (non-directory) command is loaded under this one.
+    pub fn has_commands(&self) -> bool {
+        self.commands
+            .values()
+            .any(|command| !command.is_directory || command.has_commands())
+    }
+
+    /// All (non-directory) commands under this one sorted by their HTTP path.
+    pub fn runnable_commands(&self) -> Vec<&Command> {
+        let mut commands = self
+            .commands
+            .values()
+            .flat_map(|command| {
+                if command.is_directory {
+                    command.runnable_commands()
+                } else {
+                    vec![command]
+                }
+            })
+            .collect::<Vec<_>>();
+        commands.sort_by(|x, y| x.http_path.cmp(&y.http_path));
+        commands
     }
 
     pub fn from_filename(
@@ -235,11 +660,43 @@ impl Command {
                     .join(filename.strip_prefix(root_directory).unwrap()),
                 info: Some(Command::detect_command_info(filename)?),
                 is_directory: false,
+                modified: Command::detect_modified_time(filename),
                 commands: HashMap::new(),
+                environment: None,
             },
         ));
     }
 
+    /// The latest modification time of command file and its `.yaml` or `.yml` information file.
+    pub fn detect_modified_time(command_filename: &PathBuf) -> Option<String> {
+        ["", ".yaml", ".yml"]
+            .iter()
+            .filter_map(|extension| {
+                fs::metadata(format!(
+                    "{}{}",
+                    command_filename.to_str().unwrap(),
+                    extension
+                ))
+                .and_then(|metadata| metadata.modified())
+                .ok()
+            })
+            .max()
+            .map(|modified| humantime::format_rfc3339_seconds(modified).to_string())
+    }
+
+    /// Directories may have an optional `<DIRECTORY>.yaml` or `<DIRECTORY>.yml` information file
+    /// next to them (e.g. for `default_command`).
+    pub fn detect_directory_info(directory: &PathBuf) -> Result<Option<CommandInfo>, CommandError> {
+        let has_info_file = ["yaml", "yml"].iter().any(|extension| {
+            PathBuf::from(format!("{}.{}", directory.to_str().unwrap(), extension)).is_file()
+        });
+        if has_info_file {
+            Self::detect_command_info(directory).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
     pub fn detect_command_info(command_filename: &PathBuf) -> Result<CommandInfo, CommandError> {
         let mut info_filename = PathBuf::from(format!(
             "{}.yaml",
@@ -260,7 +717,29 @@ impl Command {
                 version: None,
                 state: None,
                 support_state: false,
+                output_format: None,
+                request_content_types: None,
+                response_content_types: None,
+                output_content_type: None,
+                always_ok_status: None,
+                no_content_on_empty_output: None,
+                cache_control: None,
+                created_location: None,
+                environments: Vec::new(),
+                default_command: None,
+                arguments: None,
+                run_as_user: None,
+                run_as_group: None,
+                limits: Default::default(),
+                nice: None,
+                ionice: None,
                 options: Default::default(),
+                dependencies: Default::default(),
+                auth: None,
+                max_concurrency: None,
+                concurrency_wait_timeout: None,
+                rate_limit: None,
+                inputs: Default::default(),
             });
         };
         if !info_filename.is_file() {
@@ -285,7 +764,29 @@ impl Command {
                 version: None,
                 state: None,
                 support_state: false,
+                output_format: None,
+                request_content_types: None,
+                response_content_types: None,
+                output_content_type: None,
+                always_ok_status: None,
+                no_content_on_empty_output: None,
+                cache_control: None,
+                created_location: None,
+                environments: Vec::new(),
+                default_command: None,
+                arguments: None,
+                run_as_user: None,
+                run_as_group: None,
+                limits: Default::default(),
+                nice: None,
+                ionice: None,
                 options: Default::default(),
+                dependencies: Default::default(),
+                auth: None,
+                max_concurrency: None,
+                concurrency_wait_timeout: None,
+                rate_limit: None,
+                inputs: Default::default(),
             });
         };
         let mut command_info =
@@ -298,7 +799,16 @@ impl Command {
         if command_info.state.is_some() {
             command_info.support_state = true;
         }
-        let mut check_options = Ok(command_info.clone());
+        let mut check_options = command_info
+            .check_priority()
+            .and_then(|_| command_info.check_dependencies())
+            .and_then(|_| command_info.check_auth())
+            .and_then(|_| command_info.check_max_concurrency())
+            .and_then(|_| command_info.check_rate_limit())
+            .and_then(|_| command_info.check_output_content_type())
+            .and_then(|_| command_info.check_created_location())
+            .and_then(|_| command_info.check_cache_control())
+            .map(|_| command_info.clone());
         for (option, definition) in command_info.options {
             if !definition.required && definition.default_value.is_none() {
                 check_options = Err(format!(
@@ -315,6 +825,15 @@ impl Command {
                     (CommandOptionInfoValueType::Integer, CommandOptionValue::Integer(_)) => (),
                     (CommandOptionInfoValueType::Float, CommandOptionValue::Float(_)) => (),
                     (CommandOptionInfoValueType::Boolean, CommandOptionValue::Bool(_)) => (),
+                    (CommandOptionInfoValueType::Array(_), CommandOptionValue::Array(_))
+                    | (CommandOptionInfoValueType::Object(_), CommandOptionValue::Object(_))
+                        if super::check_definition(
+                            &option,
+                            &definition.value_type,
+                            default_value,
+                            &None,
+                        )
+                        .is_ok() => {}
                     _ => {
                         check_options = Err(format!("for option '{}' the default value type should be the same as value type", option));
                         break;
@@ -344,10 +863,15 @@ impl Command {
             debug!(command_filename = ?command_filename, info_filename = ?info_filename, "Detected command information.");
             trace!(command_filename = ?command_filename, info_filename = ?info_filename, info = ?command_info);
         };
-        check_options.map_err(|reason| CommandError::InvalidCommandInfo {
-            command: command_filename.clone(),
-            message: reason,
-        })
+        check_options
+            .map(|mut command_info| {
+                command_info.inputs = CommandInfoInputs::from_options(&command_info.options);
+                command_info
+            })
+            .map_err(|reason| CommandError::InvalidCommandInfo {
+                command: command_filename.clone(),
+                message: reason,
+            })
     }
 
     pub fn replace_http_base_path(&mut self, base_path: &PathBuf) {
@@ -368,8 +892,175 @@ impl Command {
     }
 }
 
+impl CommandInfo {
+    fn check_priority(&self) -> Result<(), String> {
+        if let Some(nice) = self.nice {
+            if cfg!(not(unix)) {
+                return Err("nice value is only supported on Unix".to_string());
+            };
+            if !(-20..=19).contains(&nice) {
+                return Err(format!("nice value {} should be between -20 and 19", nice));
+            };
+        };
+        if let Some(ionice) = self.ionice {
+            if cfg!(not(target_os = "linux")) {
+                return Err("ionice is only supported on Linux".to_string());
+            };
+            match (ionice.class, ionice.maybe_level) {
+                (CommandIONiceClass::Idle, Some(_)) => {
+                    return Err("ionice level is not used for idle class".to_string())
+                }
+                (_, Some(level)) if level > 7 => {
+                    return Err(format!("ionice level {} should be between 0 and 7", level))
+                }
+                _ => (),
+            };
+        };
+        Ok(())
+    }
+
+    fn check_dependencies(&self) -> Result<(), String> {
+        for dependency in &self.dependencies {
+            if !self.options.contains_key(&dependency.field) {
+                return Err(format!(
+                    "dependency field '{}' is not a defined option",
+                    dependency.field
+                ));
+            };
+            if dependency.requires.is_empty() {
+                return Err(format!(
+                    "dependency of field '{}' does not require any option",
+                    dependency.field
+                ));
+            };
+            if let Some(option) = dependency
+                .requires
+                .iter()
+                .find(|option| !self.options.contains_key(option.as_str()))
+            {
+                return Err(format!(
+                    "option '{}' required by dependency of field '{}' is not a defined option",
+                    option, dependency.field
+                ));
+            };
+        }
+        Ok(())
+    }
+
+    fn check_auth(&self) -> Result<(), String> {
+        if let Some(auth) = &self.auth {
+            if auth.client_certificate_names.is_empty() {
+                return Err("auth does not allow any client certificate name".to_string());
+            };
+        };
+        Ok(())
+    }
+
+    fn check_max_concurrency(&self) -> Result<(), String> {
+        if self.max_concurrency == Some(0) {
+            return Err("max_concurrency should be greater than 0".to_string());
+        };
+        Ok(())
+    }
+
+    fn check_rate_limit(&self) -> Result<(), String> {
+        if let Some(ref rate_limit) = self.rate_limit {
+            if !(rate_limit.requests_per_second.is_finite() && rate_limit.requests_per_second > 0.0)
+            {
+                return Err("rate_limit.requests_per_second should be greater than 0".to_string());
+            };
+            if rate_limit.burst == Some(0) {
+                return Err("rate_limit.burst should be greater than 0".to_string());
+            };
+        };
+        Ok(())
+    }
+
+    fn check_created_location(&self) -> Result<(), String> {
+        if let Some(ref created_location) = self.created_location {
+            match Select::from_str(created_location) {
+                Ok(Select::Path(_)) => (),
+                Ok(Select::Object(_)) => {
+                    return Err(format!(
+                        "created_location {:?} should select a single field",
+                        created_location
+                    ))
+                }
+                Err(reason) => {
+                    return Err(format!(
+                        "created_location {:?} is invalid: {}",
+                        created_location, reason
+                    ))
+                }
+            };
+        };
+        Ok(())
+    }
+
+    fn check_cache_control(&self) -> Result<(), String> {
+        if let Some(ref cache_control) = self.cache_control {
+            if cache_control.trim().is_empty()
+                || !cache_control
+                    .chars()
+                    .all(|character| character == ' ' || character.is_ascii_graphic())
+            {
+                return Err(format!(
+                    "cache_control {:?} is not a valid header value",
+                    cache_control
+                ));
+            };
+        };
+        Ok(())
+    }
+
+    fn check_output_content_type(&self) -> Result<(), String> {
+        if let Some(ref content_type) = self.output_content_type {
+            let is_valid = content_type
+                .split(';')
+                .next()
+                .and_then(|media_type| media_type.trim().split_once('/'))
+                .map(|(main_type, sub_type)| !main_type.is_empty() && !sub_type.is_empty())
+                .unwrap_or(false)
+                && content_type
+                    .chars()
+                    .all(|character| character == ' ' || character.is_ascii_graphic());
+            if !is_valid {
+                return Err(format!(
+                    "output_content_type {:?} is not a valid media type",
+                    content_type
+                ));
+            };
+        };
+        Ok(())
+    }
+}
+
 impl Default for CommandOptionInfoValueType {
     fn default() -> Self {
         Self::Any
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn big_integer_option_value_is_kept_exactly() {
+        // More than `i64::MAX` but fits in `u64`:
+        let json = r#"{"big":123456789012345678901234567890,"id":9999999999999999999}"#;
+        let value: CommandOptionValue = serde_json::from_str(json).unwrap();
+        let object = match value {
+            CommandOptionValue::Object(ref object) => object,
+            _ => panic!("{:?} is not an object", value),
+        };
+        assert!(matches!(object["id"], CommandOptionValue::BigInteger(_)));
+        assert!(matches!(object["big"], CommandOptionValue::BigInteger(_)));
+        assert_eq!(serde_json::to_string(&value).unwrap(), json);
+
+        let value: CommandOptionValue = serde_json::from_str("-12.5").unwrap();
+        assert_eq!(value, CommandOptionValue::Float(-12.5));
+        let value: CommandOptionValue = serde_json::from_str("42").unwrap();
+        assert_eq!(value, CommandOptionValue::Integer(42));
+    }
+}
@@ -1,15 +1,22 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::Infallible;
-use std::net::{Ipv4Addr, SocketAddr};
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
 use std::ops::Deref;
-use std::path::PathBuf;
-use std::sync::{Arc, RwLock};
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time;
 use tokio::sync::RwLock as AsyncRwLock;
 
+use arc_swap::ArcSwap;
+use parking_lot::RwLock;
+
 use base64;
 
-use tracing::{debug, error, info, trace};
+use tracing::{debug, error, info, trace, warn};
 
 use serde_derive::Deserialize;
 use serde_json;
@@ -19,32 +26,66 @@ use thiserror::Error;
 
 use tokio::sync::mpsc::Receiver;
 
+use futures_util::{FutureExt, Stream, StreamExt, TryStreamExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
 use warp;
-use warp::fs::File;
-use warp::http::header::{HeaderMap, AUTHORIZATION, LOCATION};
-use warp::http::{HeaderValue, Response, StatusCode};
-use warp::hyper::body::Bytes;
-use warp::hyper::Body;
+use warp::filters::BoxedFilter;
+use warp::http::header::{
+    HeaderMap, HeaderName, ACCEPT, AUTHORIZATION, CACHE_CONTROL, CONTENT_DISPOSITION,
+    CONTENT_LENGTH, CONTENT_TYPE, ETAG, EXPIRES, LAST_MODIFIED, LINK, LOCATION, REFERER,
+    RETRY_AFTER, USER_AGENT, VARY, WWW_AUTHENTICATE,
+};
+use warp::http::{HeaderValue, Response, StatusCode, Version};
+use warp::hyper::body::{Buf, Bytes, HttpBody};
+use warp::hyper::server::accept;
+use warp::hyper::service::{make_service_fn, service_fn, Service};
+use warp::hyper::{Body, Request};
+use warp::multipart::FormData;
 use warp::path::Tail;
 use warp::reject::Reject;
-use warp::{Filter, Rejection, Reply};
+use warp::{Filter, Rejection};
+
+use tempfile::NamedTempFile;
 
 use wildmatch::WildMatch;
 
 use crate::captcha;
 use crate::cmd;
+use crate::cmd::errors::CommandError;
 use crate::cmd::runner::CommandOptionValue;
 use crate::cmd::runner::CommandOptionsValue;
-use crate::cmd::{Command, CommandInput, CommandInstruction, CommandStats};
+use crate::cmd::{
+    Command, CommandInfoOutputFormat, CommandInput, CommandInstruction, CommandOutput,
+    CommandRateLimit, CommandRunSettings, CommandSignal, CommandStats,
+};
+use crate::listener;
+use crate::listener::{RemoteAddress, TimeoutStream, TlsConnection};
+use crate::logging;
+use crate::notification;
+use crate::notification::CommandEvent;
 use crate::report::{ReportContext, ReportError, State as ReportState};
-use crate::settings::Cfg;
+use crate::select::Select;
+use crate::settings::{Cfg, CfgLogging, CfgLoggingFormat, CfgNotificationsSeverity, CfgValue};
 use crate::utils;
+#[cfg(feature = "www")]
 use crate::www;
 
 //  for future use for HTTP "Server" header
 // use structopt::clap::crate_name;
 
 pub static API_RUN_BASE_PATH: &str = "/api/run";
+/// Number of request body chunks that are buffered before a slow command slows down the upload.
+static STDIN_STREAM_BUFFER_SIZE: usize = 8;
+static DEFAULT_MAINTENANCE_REASON: &str = "new command runs are not accepted";
+
+type BodyStream = Pin<Box<dyn Stream<Item = Result<Bytes, warp::Error>> + Send>>;
+
+enum CommandRequestBody {
+    Options(CommandOptionsValue),
+    Stream(BodyStream),
+    Multipart(FormData),
+}
 
 #[derive(Error, Debug, Clone)]
 pub enum HTTPError {
@@ -109,6 +150,10 @@ pub enum HTTPAuthenticationError {
     Captcha(String),
     #[error("Invalid IP {0}")]
     InvalidIP(String),
+    #[error("Could not run auth command: {0}")]
+    AuthCommand(String),
+    #[error("Token is not allowed to access {path:?}")]
+    OutOfScope { path: String },
 }
 
 #[derive(Error, Debug, Clone)]
@@ -116,7 +161,11 @@ pub enum HTTPAPIError {
     #[error("{message}")]
     CommandNotFound { message: String },
     #[error("{message}")]
-    CheckInput { message: String },
+    CheckInput {
+        message: String,
+        fields: BTreeMap<String, String>,
+        maybe_usage: Option<serde_json::Value>,
+    },
     #[error("{message}")]
     InitializeCommand { message: String },
     #[error("{message}")]
@@ -135,6 +184,66 @@ pub enum HTTPAPIError {
     Report { message: String },
     #[error("No report found")]
     ReportNotFound,
+    #[error("{message}")]
+    PayloadTooLarge { message: String },
+    #[error("Could not save uploaded file ({message})")]
+    SaveUploadedFile { message: String },
+    #[error("Server configuration does not allow changing the password")]
+    SetPasswordNotAllowed,
+    #[error("{message}")]
+    WeakPassword { message: String },
+    #[error("Current password is not provided or is invalid")]
+    InvalidOldPassword,
+    #[error("Commands root directory {root_directory:?} is not available")]
+    CommandsRootUnavailable { root_directory: PathBuf },
+    #[error("Could not decode command output as {format} ({message})")]
+    DecodeOutput {
+        format: String,
+        message: String,
+        output: String,
+    },
+    #[error("{message}")]
+    UnsupportedMediaType { message: String },
+    #[error("{message}")]
+    NotAcceptable { message: String },
+    #[error("{message}")]
+    CommandTimeout { message: String },
+    #[error("Request is not handled in {timeout:?}")]
+    RequestTimeout { timeout: time::Duration },
+    #[error("Request is not received in {timeout:?}")]
+    ClientTimeout { timeout: time::Duration },
+    #[error("{message}")]
+    CommandForbidden { message: String },
+    #[error("Server is in maintenance mode: {reason}")]
+    Maintenance { reason: String },
+    #[error("Pre-run hook rejected the command: {message}")]
+    PreRunHookRejected { message: String },
+    #[error("Command is already running {max_concurrency} time(s)")]
+    CommandBusy { max_concurrency: usize },
+    #[error("Request with idempotency key {key:?} is still running")]
+    IdempotencyKeyInUse { key: String },
+    #[error("No command is loaded yet")]
+    CommandsNotLoaded,
+    #[error("{message}")]
+    InvalidCommandPath { message: String },
+    #[error("Invalid select expression: {message}")]
+    InvalidSelect { message: String },
+    #[error("Command rate limit is exceeded, Retry after {retry_after} second(s)")]
+    CommandRateLimited { retry_after: u64 },
+    #[error("Server configuration does not allow debug information of runs")]
+    DebugNotAllowed,
+    #[error("Server configuration does not allow reading logs")]
+    LogsNotAllowed,
+    #[error("No log file is found (logging output should be a directory)")]
+    LogFileNotFound,
+    #[error("Could not read log file {filename:?}: {message}")]
+    ReadLogs { filename: PathBuf, message: String },
+    #[error("Unknown commands format {format:?} (should be `tree` or `flat`)")]
+    UnknownCommandsFormat { format: String },
+    #[error("Unknown output encoding {encoding:?} (should be `raw` or `base64`)")]
+    UnknownOutputEncoding { encoding: String },
+    #[error("Could not read request body ({message})")]
+    ReadRequestBody { message: String },
 }
 
 impl HTTPAPIError {
@@ -152,6 +261,34 @@ impl HTTPAPIError {
             Self::ReportNotAvailable { .. } => 1011,
             Self::Report { .. } => 1012,
             Self::ReportNotFound => 1013,
+            Self::PayloadTooLarge { .. } => 1014,
+            Self::SaveUploadedFile { .. } => 1015,
+            Self::SetPasswordNotAllowed => 1016,
+            Self::WeakPassword { .. } => 1017,
+            Self::InvalidOldPassword => 1018,
+            Self::CommandsRootUnavailable { .. } => 1019,
+            Self::DecodeOutput { .. } => 1020,
+            Self::UnsupportedMediaType { .. } => 1021,
+            Self::NotAcceptable { .. } => 1022,
+            Self::CommandTimeout { .. } => 1023,
+            Self::RequestTimeout { .. } => 1024,
+            Self::ClientTimeout { .. } => 1025,
+            Self::CommandForbidden { .. } => 1026,
+            Self::Maintenance { .. } => 1027,
+            Self::PreRunHookRejected { .. } => 1028,
+            Self::CommandBusy { .. } => 1029,
+            Self::IdempotencyKeyInUse { .. } => 1030,
+            Self::CommandsNotLoaded => 1031,
+            Self::InvalidCommandPath { .. } => 1032,
+            Self::InvalidSelect { .. } => 1033,
+            Self::CommandRateLimited { .. } => 1034,
+            Self::DebugNotAllowed => 1035,
+            Self::LogsNotAllowed => 1036,
+            Self::LogFileNotFound => 1037,
+            Self::ReadLogs { .. } => 1038,
+            Self::UnknownCommandsFormat { .. } => 1039,
+            Self::UnknownOutputEncoding { .. } => 1040,
+            Self::ReadRequestBody { .. } => 1041,
         }
     }
 
@@ -168,6 +305,34 @@ impl HTTPAPIError {
             Self::ReportNotAvailable { .. } => StatusCode::NOT_ACCEPTABLE,
             Self::Report { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             Self::ReportNotFound => StatusCode::NOT_FOUND,
+            Self::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::SaveUploadedFile { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::SetPasswordNotAllowed => StatusCode::FORBIDDEN,
+            Self::WeakPassword { .. } => StatusCode::BAD_REQUEST,
+            Self::InvalidOldPassword => StatusCode::FORBIDDEN,
+            Self::CommandsRootUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            Self::DecodeOutput { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::UnsupportedMediaType { .. } => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::NotAcceptable { .. } => StatusCode::NOT_ACCEPTABLE,
+            Self::CommandTimeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+            Self::RequestTimeout { .. } => StatusCode::REQUEST_TIMEOUT,
+            Self::ClientTimeout { .. } => StatusCode::REQUEST_TIMEOUT,
+            Self::CommandForbidden { .. } => StatusCode::FORBIDDEN,
+            Self::Maintenance { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            Self::PreRunHookRejected { .. } => StatusCode::FORBIDDEN,
+            Self::CommandBusy { .. } => StatusCode::CONFLICT,
+            Self::IdempotencyKeyInUse { .. } => StatusCode::CONFLICT,
+            Self::CommandsNotLoaded => StatusCode::SERVICE_UNAVAILABLE,
+            Self::InvalidCommandPath { .. } => StatusCode::BAD_REQUEST,
+            Self::InvalidSelect { .. } => StatusCode::BAD_REQUEST,
+            Self::CommandRateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::DebugNotAllowed => StatusCode::FORBIDDEN,
+            Self::LogsNotAllowed => StatusCode::FORBIDDEN,
+            Self::LogFileNotFound => StatusCode::NOT_FOUND,
+            Self::ReadLogs { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::UnknownCommandsFormat { .. } => StatusCode::BAD_REQUEST,
+            Self::UnknownOutputEncoding { .. } => StatusCode::BAD_REQUEST,
+            Self::ReadRequestBody { .. } => StatusCode::BAD_REQUEST,
         }
     }
 }
@@ -189,6 +354,8 @@ impl HTTPAuthenticationError {
             Self::InvalidToken => 2012,
             Self::Captcha(_) => 2012,
             Self::InvalidIP(_) => 2013,
+            Self::AuthCommand(_) => 2014,
+            Self::OutOfScope { .. } => 2015,
         }
     }
 
@@ -208,6 +375,8 @@ impl HTTPAuthenticationError {
             Self::InvalidToken => StatusCode::UNAUTHORIZED,
             Self::Captcha(_) => StatusCode::NOT_ACCEPTABLE,
             Self::InvalidIP(_) => StatusCode::UNAUTHORIZED,
+            Self::AuthCommand(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::OutOfScope { .. } => StatusCode::FORBIDDEN,
         }
     }
 }
@@ -215,6 +384,7 @@ impl HTTPAuthenticationError {
 #[derive(Debug, Deserialize)]
 struct SetPassword {
     password: String,
+    old_password: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -226,9 +396,23 @@ struct Report {
     limit: Option<usize>,
 }
 
+/// Shells exit with `128 + <SIGNAL_NUMBER>` if a process they run is terminated by a signal.
+const EXIT_CODE_SIGNAL_OFFSET: i32 = 128;
+/// Greatest signal number that is detected from exit-status (`SIGRTMAX` on Linux).
+const EXIT_CODE_SIGNAL_MAX: i32 = 64;
+
+/// Normalizes exit-status of a command process to the range of Unix exit-statuses.
+///
+/// Negative exit-statuses (e.g. when the process did not exit by itself) and ones greater than
+/// `255` (possible on Windows) are clamped to `255`, so they never look like a successful run.
+#[inline]
+fn normalize_exit_code(exit_code: i32) -> u8 {
+    u8::try_from(exit_code).unwrap_or(u8::MAX)
+}
+
 #[inline]
 fn exit_code_to_status_code(exit_code: i32) -> StatusCode {
-    match exit_code {
+    match normalize_exit_code(exit_code) {
         0 => StatusCode::OK,                    // 200
         1 => StatusCode::INTERNAL_SERVER_ERROR, // 500
         2 => StatusCode::BAD_REQUEST,           // 400
@@ -239,48 +423,438 @@ fn exit_code_to_status_code(exit_code: i32) -> StatusCode {
         7 => StatusCode::NOT_IMPLEMENTED,       // 501
         8 => StatusCode::CONFLICT,              // 409
         9 => StatusCode::REQUEST_TIMEOUT,       // 408
+        // Including `128 + <SIGNAL_NUMBER>` and clamped ones:
         _ => StatusCode::INTERNAL_SERVER_ERROR, // 500
     }
 }
 
-pub async fn setup(
-    cfg: Arc<RwLock<Cfg>>,
-    commands: Arc<RwLock<Command>>,
-    report_state: Arc<AsyncRwLock<ReportState>>,
-) -> Result<
-    (
-        tokio::sync::oneshot::Sender<()>,
-        tokio::sync::mpsc::Receiver<()>,
-    ),
-    String,
-> {
-    let (http_start_sender, mut http_start_receiver) = tokio::sync::mpsc::channel::<()>(128);
-    let (http_stop_sender, http_stop_receiver) = tokio::sync::oneshot::channel::<()>();
-    let initialize_channel = http_start_sender.clone();
+/// Returns the signal that terminated the command process and its raw exit-status if its HTTP
+/// status-code is **500**, so clients can tell why the command failed.
+///
+/// If the process exited with `128 + <SIGNAL_NUMBER>`, The signal is detected from its
+/// exit-status since the process that the command ran (e.g. via a shell) was terminated by it.
+fn command_exit_details(command_output: &CommandOutput) -> (Option<CommandSignal>, Option<i32>) {
+    if command_output.signal.is_some() {
+        return (command_output.signal.clone(), None);
+    }
+    let exit_code = command_output.exit_code;
+    if exit_code_to_status_code(exit_code) != StatusCode::INTERNAL_SERVER_ERROR {
+        return (None, None);
+    }
+    let maybe_signal = if exit_code > EXIT_CODE_SIGNAL_OFFSET
+        && exit_code <= EXIT_CODE_SIGNAL_OFFSET + EXIT_CODE_SIGNAL_MAX
+    {
+        Some(CommandSignal::new(exit_code - EXIT_CODE_SIGNAL_OFFSET))
+    } else {
+        None
+    };
+    (maybe_signal, Some(exit_code))
+}
 
-    let server_options = cfg.read().unwrap().config_value.server.clone();
-    let host = server_options.host.clone();
-    let port = server_options.port.clone();
+/// Error message of requests with JSON body that could not be deserialized.
+///
+/// Its detail is in `detail` field of response for requests with `X-RESTCOMMANDER-VERBOSE-ERRORS`
+/// header.
+const BODY_DESERIALIZE_ERROR_MESSAGE: &str = "request body must be valid JSON object";
 
-    let api_run_filter = warp::path("run").and(api_run_command_filter(
-        cfg.clone(),
-        commands.clone(),
-        report_state.clone(),
+/// Semaphores of commands with configured `max_concurrency` and their number of permits by command
+/// HTTP path.
+///
+/// It is kept across HTTP server restarts after configuration reloads.
+pub type CommandSemaphores = Arc<RwLock<HashMap<PathBuf, (usize, Arc<tokio::sync::Semaphore>)>>>;
+
+/// Token buckets of commands with configured `rate_limit` by command HTTP path.
+///
+/// It is kept across HTTP server restarts after configuration reloads.
+pub type CommandRateLimits = Arc<RwLock<HashMap<PathBuf, CommandRateLimitBucket>>>;
+
+/// Lets one reload of commands or configuration (or password change) run at a time, So a slow one
+/// does not store an older tree or configuration over a newer one.
+type ReloadLock = Arc<tokio::sync::Mutex<()>>;
+
+/// Authentication tokens that are issued via `auth/token` of [`api_filter`] by their value.
+///
+/// Share it with [`authentication_filter`] to authenticate your own routes with the same tokens.
+pub type AuthTokens = Arc<RwLock<HashMap<String, AuthToken>>>;
+
+pub struct AuthToken {
+    expire_time: usize,
+    /// It is `None` if the token is issued while basic authentication is disabled.
+    maybe_username: Option<String>,
+    /// API path prefixes (e.g. `run/deploy`) that `auth_command` limited the token to.
+    maybe_scopes: Option<Vec<String>>,
+}
+
+pub struct CommandRateLimitBucket {
+    rate_limit: CommandRateLimit,
+    tokens: f64,
+    updated: time::Instant,
+}
+
+/// Request header that makes repeated `/api/run/...` requests return the first response.
+static IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Responses of `/api/run/...` requests with `Idempotency-Key` header by their key, command path
+/// and requester.
+type IdempotencyKeys = Arc<RwLock<HashMap<IdempotencyKey, IdempotencyKeyState>>>;
+
+/// Maximum number of kept idempotency keys. If it is reached, The response that expires first is
+/// dropped.
+const MAX_IDEMPOTENCY_KEYS: usize = 10000;
+
+/// A request with the same key from another user or client certificate runs the command again.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct IdempotencyKey {
+    key: String,
+    command_path: String,
+    maybe_username: Option<String>,
+    maybe_client_certificate_name: Option<String>,
+}
+
+enum IdempotencyKeyState {
+    Running,
+    Done {
+        status_code: StatusCode,
+        headers: HeaderMap,
+        body: String,
+        expire_time: time::Instant,
+    },
+}
+
+/// Removes the key of a request that did not finish (e.g. its client disconnected), So it can
+/// be retried.
+struct RunningIdempotencyKey {
+    idempotency_keys: IdempotencyKeys,
+    maybe_key: Option<IdempotencyKey>,
+}
+
+impl Drop for RunningIdempotencyKey {
+    fn drop(&mut self) {
+        if let Some(key) = self.maybe_key.take() {
+            self.idempotency_keys.write().remove(&key);
+        }
+    }
+}
+
+/// Removes the finished request that expires first and returns `false` if there is none.
+fn remove_first_expiring_idempotency_key(
+    idempotency_keys: &mut HashMap<IdempotencyKey, IdempotencyKeyState>,
+) -> bool {
+    let maybe_key = idempotency_keys
+        .iter()
+        .filter_map(|(key, state)| match state {
+            IdempotencyKeyState::Done { expire_time, .. } => Some((key, *expire_time)),
+            IdempotencyKeyState::Running => None,
+        })
+        .min_by_key(|(_, expire_time)| *expire_time)
+        .map(|(key, _)| key.clone());
+    match maybe_key {
+        Some(key) => idempotency_keys.remove(&key).is_some(),
+        None => false,
+    }
+}
+
+/// Runs the command unless a request with the same idempotency key is already running (409) or
+/// finished in `ttl` seconds (its response is returned with `Idempotent-Replayed` header).
+///
+/// Requests that are rejected before running the command (e.g. for invalid inputs) are not kept.
+async fn run_once_per_idempotency_key<F>(
+    idempotency_keys: IdempotencyKeys,
+    maybe_key: Option<IdempotencyKey>,
+    ttl: u64,
+    run: F,
+) -> Result<Response<String>, HTTPAPIError>
+where
+    F: Future<Output = Result<Response<String>, HTTPAPIError>>,
+{
+    let key = match maybe_key {
+        Some(key) => key,
+        None => return run.await,
+    };
+    let is_kept = {
+        let mut idempotency_keys = idempotency_keys.write();
+        let now = time::Instant::now();
+        idempotency_keys.retain(|_, state| match state {
+            IdempotencyKeyState::Done { expire_time, .. } => *expire_time > now,
+            IdempotencyKeyState::Running => true,
+        });
+        match idempotency_keys.get(&key) {
+            Some(IdempotencyKeyState::Running) => {
+                return Err(HTTPAPIError::IdempotencyKeyInUse { key: key.key })
+            }
+            Some(IdempotencyKeyState::Done {
+                status_code,
+                headers,
+                body,
+                ..
+            }) => {
+                let mut response = Response::new(body.clone());
+                *response.status_mut() = *status_code;
+                *response.headers_mut() = headers.clone();
+                response
+                    .headers_mut()
+                    .insert("idempotent-replayed", HeaderValue::from_static("true"));
+                return Ok(response);
+            }
+            None => {
+                // If all of them are running, This one is not kept:
+                let is_kept = idempotency_keys.len() < MAX_IDEMPOTENCY_KEYS
+                    || remove_first_expiring_idempotency_key(&mut idempotency_keys);
+                if is_kept {
+                    idempotency_keys.insert(key.clone(), IdempotencyKeyState::Running);
+                };
+                is_kept
+            }
+        }
+    };
+    if !is_kept {
+        return run.await;
+    };
+    let mut running_key = RunningIdempotencyKey {
+        idempotency_keys: idempotency_keys.clone(),
+        maybe_key: Some(key),
+    };
+    let result = run.await;
+    if let Ok(ref response) = result {
+        let key = running_key.maybe_key.take().unwrap();
+        idempotency_keys.write().insert(
+            key,
+            IdempotencyKeyState::Done {
+                status_code: response.status(),
+                headers: response.headers().clone(),
+                body: response.body().clone(),
+                expire_time: time::Instant::now() + time::Duration::from_secs(ttl),
+            },
+        );
+    };
+    result
+}
+
+/// Reason of maintenance mode if it is enabled.
+///
+/// It is kept across HTTP server restarts after configuration reloads.
+pub type MaintenanceState = Arc<RwLock<Option<String>>>;
+
+/// Number of responses and their body bytes for each command HTTP path.
+///
+/// It is kept across HTTP server restarts after configuration reloads.
+pub type TrafficState = Arc<RwLock<BTreeMap<String, CommandTraffic>>>;
+
+#[derive(Debug, Clone, Default)]
+pub struct CommandTraffic {
+    responses: u64,
+    response_bytes: u64,
+}
+
+/// HTTP paths of the commands that a response is made for and the number of response body bytes
+/// of each one.
+///
+/// Command handlers insert it in response extensions and [with_traffic] takes it out.
+struct CommandTrafficList(Vec<(String, u64)>);
+
+/// Counts responses of `filter` and their body bytes in `traffic` under HTTP paths of their
+/// commands.
+///
+/// Responses that are not made for a found command (e.g. unknown paths) are not counted, So the
+/// number of keys is limited to the number of commands.
+fn with_traffic<F>(
+    traffic: TrafficState,
+    filter: F,
+) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (Response<String>,), Error = Rejection> + Clone + Send + Sync + 'static,
+{
+    filter.map(move |mut response: Response<String>| {
+        if let Some(CommandTrafficList(traffic_list)) =
+            response.extensions_mut().remove::<CommandTrafficList>()
+        {
+            let mut traffic = traffic.write();
+            for (http_path, response_bytes) in traffic_list {
+                let command_traffic = traffic.entry(http_path).or_default();
+                command_traffic.responses += 1;
+                command_traffic.response_bytes += response_bytes;
+            }
+        };
+        response
+    })
+}
+
+/// Lets [with_traffic] count the whole response body under HTTP path of the command.
+fn insert_command_traffic(response: &mut Response<String>, command_http_path: &Path) {
+    let response_bytes = response.body().len() as u64;
+    response.extensions_mut().insert(CommandTrafficList(vec![(
+        command_http_path.to_str().unwrap().to_string(),
+        response_bytes,
+    )]));
+}
+
+/// Running commands and HTTP servers that are waited for before the process exits.
+///
+/// It is kept across HTTP server restarts after configuration reloads.
+#[derive(Clone, Default)]
+pub struct DrainState {
+    last_command_id: Arc<AtomicU64>,
+    running_commands: Arc<RwLock<HashMap<u64, (PathBuf, Arc<AtomicBool>)>>>,
+    running_servers: Arc<AtomicUsize>,
+}
+
+/// Removes a command from running commands when it is dropped.
+struct RunningCommand {
+    drain_state: DrainState,
+    id: u64,
+}
+
+impl Drop for RunningCommand {
+    fn drop(&mut self) {
+        self.drain_state.running_commands.write().remove(&self.id);
+    }
+}
+
+/// Decreases the number of running HTTP servers when it is dropped.
+struct RunningServer(DrainState);
+
+impl Drop for RunningServer {
+    fn drop(&mut self) {
+        self.0.running_servers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl DrainState {
+    fn add_command(&self, http_path: PathBuf, cancel: Arc<AtomicBool>) -> RunningCommand {
+        let id = self.last_command_id.fetch_add(1, Ordering::SeqCst);
+        self.running_commands
+            .write()
+            .insert(id, (http_path, cancel));
+        RunningCommand {
+            drain_state: self.clone(),
+            id,
+        }
+    }
+
+    fn add_server(&self) -> RunningServer {
+        self.running_servers.fetch_add(1, Ordering::SeqCst);
+        RunningServer(self.clone())
+    }
+
+    fn is_drained(&self) -> bool {
+        self.running_servers.load(Ordering::SeqCst) == 0 && self.running_commands.read().is_empty()
+    }
+
+    async fn wait(&self, timeout: time::Duration) -> bool {
+        let start_time = time::Instant::now();
+        while !self.is_drained() {
+            if start_time.elapsed() >= timeout {
+                return false;
+            };
+            tokio::time::sleep(DRAIN_CHECK_INTERVAL).await;
+        }
+        true
+    }
+
+    /// Waits for stopped HTTP servers to finish their in-flight requests and kills commands that
+    /// are still running after `timeout`.
+    ///
+    /// Killed commands have `kill_timeout` to exit.
+    pub async fn drain(&self, timeout: time::Duration, kill_timeout: time::Duration) {
+        if self.wait(timeout).await {
+            return;
+        };
+        for (http_path, cancel) in self.running_commands.read().values() {
+            warn!(
+                command = ?http_path,
+                "Killing command that is still running after shutdown drain timeout."
+            );
+            cancel.store(true, Ordering::SeqCst);
+        }
+        if !self.wait(kill_timeout).await {
+            warn!("Some requests are still in-flight after killing their commands.");
+        };
+    }
+}
+
+const DRAIN_CHECK_INTERVAL: time::Duration = time::Duration::from_millis(100);
+
+/// Makes the filter of all REST-API endpoints (e.g. `run/...` and `auth/token`) without `/api`
+/// prefix, so it can be mounted under any path of another warp server.
+///
+/// A message is sent to `reload_sender` after configuration is reloaded via `reload/config` or
+/// `reload/all` endpoints, so the caller can apply new server settings (see [`setup`]).
+/// Rejections of the filter should be handled via [`handle_rejection`].
+pub fn api_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+    commands: Arc<ArcSwap<Command>>,
+    report_state: Arc<AsyncRwLock<ReportState>>,
+    maintenance: MaintenanceState,
+    reload_sender: tokio::sync::mpsc::Sender<()>,
+    drain_state: DrainState,
+    traffic: TrafficState,
+    command_semaphores: CommandSemaphores,
+    command_rate_limits: CommandRateLimits,
+    tokens: AuthTokens,
+) -> BoxedFilter<(Response<Body>,)> {
+    let api_run_filter = warp::path("run").and(with_traffic(
+        traffic.clone(),
+        api_run_command_filter(
+            cfg.clone(),
+            commands.clone(),
+            report_state.clone(),
+            maintenance.clone(),
+            command_semaphores.clone(),
+            drain_state.clone(),
+            command_rate_limits.clone(),
+            tokens.clone(),
+        ),
+    ));
+    let api_map_filter = warp::path("map").and(with_traffic(
+        traffic.clone(),
+        api_map_command_filter(
+            cfg.clone(),
+            commands.clone(),
+            report_state.clone(),
+            maintenance.clone(),
+            command_semaphores.clone(),
+            drain_state.clone(),
+            command_rate_limits.clone(),
+            tokens.clone(),
+        ),
     ));
+    let api_rpc_filter = with_traffic(
+        traffic.clone(),
+        api_rpc_filter(
+            cfg.clone(),
+            commands.clone(),
+            report_state.clone(),
+            maintenance.clone(),
+            command_semaphores,
+            drain_state,
+            command_rate_limits,
+            tokens.clone(),
+        ),
+    );
     let api_state_filter = warp::path("state").and(api_get_command_state_filter(
         cfg.clone(),
         commands.clone(),
         report_state.clone(),
     ));
+    let reload_lock = ReloadLock::default();
     let api_reload_filter = warp::path("reload").and(
-        api_reload_commands_filter(commands.clone())
+        api_reload_commands_filter(cfg.clone(), commands.clone(), reload_lock.clone())
             .or(api_reload_config_filter(
                 cfg.clone(),
-                http_start_sender.clone(),
+                commands.clone(),
+                reload_sender.clone(),
+                reload_lock.clone(),
+            ))
+            .unify()
+            .or(api_reload_all_filter(
+                cfg.clone(),
+                commands.clone(),
+                reload_sender.clone(),
+                reload_lock.clone(),
             ))
             .unify(),
     );
-    let maybe_captcha = if cfg.read().unwrap().config_value.server.captcha {
+    let maybe_captcha = if cfg.load().config_value.server.captcha {
         Some(Arc::new(RwLock::new(captcha::Captcha::new())))
     } else {
         None
@@ -290,7 +864,6 @@ pub async fn setup(
             .or(api_configuration_filter(cfg.clone()))
             .unify(),
     );
-    let tokens = Arc::new(RwLock::new(HashMap::new()));
     let api_auth_filter = warp::path("auth").and(check_ip_address(cfg.clone())).and(
         api_auth_test_filter(tokens.clone(), cfg.clone())
             .or(api_auth_token(
@@ -300,103 +873,253 @@ pub async fn setup(
             ))
             .unify(),
     );
-    let api_filter = warp::path("api").and(
-        api_public_filter
-            .or(api_auth_filter)
-            .unify()
-            .or(check_ip_address(cfg.clone()).and(
-                authentication_with_token_filter(tokens.clone(), cfg.clone())
-                    .untuple_one()
-                    .and(
-                        api_run_filter
-                            .or(api_state_filter)
-                            .unify()
-                            .or(api_reload_filter)
-                            .unify()
-                            .or(api_get_commands_filter(commands.clone()))
-                            .unify()
-                            .or(api_set_password_filter(cfg.clone()))
-                            .unify()
-                            .or(api_report_filter(cfg.clone(), report_state.clone()))
-                            .unify(),
-                    ),
-            )),
-    );
-    let static_filter = warp::path("static").and(
-        static_external_filter(cfg.clone())
-            .or(static_internal_filter(cfg.clone()))
-            .unify(),
-    );
-    let routes = api_filter
-        .or(static_filter)
-        .or(redirect_root_to_index_html_filter(cfg.clone()))
-        .recover(handle_rejection)
-        .with(warp::log::custom(http_logging));
-    let mut has_tls = false;
-    if server_options.tls_cert_file.clone().is_some()
-        && server_options.tls_key_file.clone().is_some()
-    {
-        has_tls = true;
-        let server = warp::serve(routes)
-            .tls()
-            .cert_path(server_options.tls_cert_file.clone().unwrap())
-            .key_path(server_options.tls_key_file.clone().unwrap());
-        tokio::spawn(async move {
-            debug!(
-                "Attempt to start HTTPS server on {}:{} with cert file {:?} and key file {:?}",
-                host,
-                port,
-                server_options.tls_cert_file.clone().unwrap(),
-                server_options.tls_key_file.clone().unwrap()
-            );
-            let (_, server) = server.bind_with_graceful_shutdown(
-                (host.parse::<Ipv4Addr>().unwrap(), port),
-                async {
-                    http_stop_receiver.await.ok();
-                },
-            );
-            initialize_channel.send(()).await.unwrap();
-            server.await;
-            info!("stopped HTTPS listener on {}:{}", host, port);
-        });
+    api_public_filter
+        .or(api_auth_filter)
+        .unify()
+        .map(warp::Reply::into_response)
+        .or(check_ip_address(cfg.clone()).and(
+            anonymous_path_filter(cfg.clone())
+                .or(authentication_filter(tokens.clone(), cfg.clone()))
+                .unify()
+                .untuple_one()
+                .and(
+                    api_run_filter
+                        .or(api_map_filter)
+                        .unify()
+                        .or(api_rpc_filter)
+                        .unify()
+                        .or(api_state_filter)
+                        .unify()
+                        .or(api_reload_filter)
+                        .unify()
+                        .or(api_get_commands_filter(commands.clone()))
+                        .unify()
+                        .or(api_ping_filter(cfg.clone(), commands.clone()))
+                        .unify()
+                        .or(api_set_password_filter(cfg.clone(), reload_lock))
+                        .unify()
+                        .or(api_maintenance_filter(maintenance))
+                        .unify()
+                        .or(api_stats_filter(traffic))
+                        .unify()
+                        .or(api_report_filter(cfg.clone(), report_state.clone()))
+                        .unify()
+                        .map(warp::Reply::into_response)
+                        // It streams followed logs:
+                        .or(api_logs_filter(cfg.clone()))
+                        .unify(),
+                ),
+        ))
+        .unify()
+        .boxed()
+}
+
+/// Starts HTTP(S) servers on configured listeners with [`api_filter`] under `/api`, static files
+/// and the web dashboard.
+///
+/// Returns the sender that stops servers and the receiver of configuration reload messages.
+pub async fn setup(
+    cfg: Arc<ArcSwap<Cfg>>,
+    commands: Arc<ArcSwap<Command>>,
+    report_state: Arc<AsyncRwLock<ReportState>>,
+    maintenance: MaintenanceState,
+    drain_state: DrainState,
+    traffic: TrafficState,
+    command_semaphores: CommandSemaphores,
+    command_rate_limits: CommandRateLimits,
+) -> Result<
+    (
+        tokio::sync::oneshot::Sender<()>,
+        tokio::sync::mpsc::Receiver<()>,
+    ),
+    String,
+> {
+    let (http_start_sender, mut http_start_receiver) = tokio::sync::mpsc::channel::<()>(128);
+    let (http_stop_sender, http_stop_receiver) = tokio::sync::oneshot::channel::<()>();
+    let initialize_channel = http_start_sender.clone();
+
+    let server_options = cfg.load().config_value.server.clone();
+
+    let api_filter = warp::path("api").and(api_filter(
+        cfg.clone(),
+        commands.clone(),
+        report_state.clone(),
+        maintenance.clone(),
+        http_start_sender.clone(),
+        drain_state.clone(),
+        traffic,
+        command_semaphores,
+        command_rate_limits,
+        // Tokens are issued again after restarts:
+        AuthTokens::default(),
+    ));
+    let api_filter = api_filter.map(warp::Reply::into_response).boxed();
+    #[cfg(feature = "www")]
+    let api_or_www_filter = if server_options.api_only {
+        api_filter
     } else {
-        let server = warp::serve(routes);
-        tokio::spawn(async move {
-            debug!("Attempt to start HTTP server on {}:{}", host, port);
-            let (_, server) = server.bind_with_graceful_shutdown(
-                (host.parse::<Ipv4Addr>().unwrap(), port),
-                async {
-                    http_stop_receiver.await.ok();
-                },
-            );
-            initialize_channel.send(()).await.unwrap();
-            server.await;
-            info!("stopped HTTP listener on {}:{}", host, port);
-        });
+        api_filter.or(www_filter(cfg.clone())).unify().boxed()
     };
-    match utils::maybe_receive(&mut http_start_receiver, 5, "http-handler".to_string()).await {
-        Ok(Some(())) => Ok(()),
-        Ok(None) => {
-            Err("could not receive HTTP server ack after initialization after 5s".to_string())
-        }
-        Err(reason) => Err(reason),
-    }?;
-    info!(
-        server_options.host = server_options.host.as_str(),
-        server_options.port = server_options.port,
-        server_options.tls = has_tls,
-        "{}",
-        format!(
-            "Started server on {}{}:{}{}",
-            if has_tls { "https://" } else { "http://" },
-            server_options.host,
-            server_options.port,
-            server_options.http_base_path
+    // Without `www` feature there is nothing else to serve:
+    #[cfg(not(feature = "www"))]
+    let api_or_www_filter = api_filter;
+    let routes = warp::header::headers_cloned()
+        .and(
+            verbose_errors_filter()
+                .and(
+                    api_or_www_filter
+                        .map(Ok)
+                        // Unlike `recover`, This keeps request's `verbose_errors` for the rejection:
+                        .or_else(|rejection| async move { Ok::<_, Infallible>((Err(rejection),)) }),
+                )
+                .and_then({
+                    let cfg = cfg.clone();
+                    move |verbose_errors, result: Result<Response<Body>, Rejection>| {
+                        let cfg = cfg.clone();
+                        async move {
+                            match result {
+                                Ok(response) => Ok(response),
+                                Err(rejection) => handle_rejection(rejection, cfg, verbose_errors)
+                                    .await
+                                    .map(warp::Reply::into_response),
+                            }
+                        }
+                    }
+                }),
         )
-    );
+        .map(remove_authentication_challenge_for_api_clients);
+    let service = warp::service(routes);
+    let listener_settings = server_options.listener_settings();
+    let listener_list = server_options.listener_list();
+    // Bind all listeners before serving anything, So a single bad address stops all of them:
+    let mut bound_listener_list = Vec::with_capacity(listener_list.len());
+    for listener_cfg in listener_list.iter() {
+        let address = SocketAddr::new(
+            listener_cfg.host.parse::<IpAddr>().unwrap(),
+            listener_cfg.port,
+        );
+        let maybe_tls_config = if let (Some(tls_cert_file), Some(tls_key_file)) =
+            (&listener_cfg.tls_cert_file, &listener_cfg.tls_key_file)
+        {
+            debug!(
+                "Attempt to start HTTPS server on {} with cert file {:?} and key file {:?}",
+                address, tls_cert_file, tls_key_file
+            );
+            Some(listener::tls_config(
+                tls_cert_file,
+                tls_key_file,
+                server_options.tls_client_auth().as_ref(),
+            )?)
+        } else {
+            debug!("Attempt to start HTTP server on {}", address);
+            None
+        };
+        let listener = listener::bind(address, &listener_settings)
+            .map_err(|reason| make_bind_error_message(address, reason))?;
+        bound_listener_list.push((address, listener, maybe_tls_config));
+    }
+    // Every listener stops when the stop channel receives a message or gets closed:
+    let shutdown_signal = http_stop_receiver.map(|_| ()).shared();
+    for ((address, listener, maybe_tls_config), listener_cfg) in
+        bound_listener_list.into_iter().zip(listener_list.iter())
+    {
+        let incoming = listener::incoming(listener, listener_settings.clone());
+        let service = service.clone();
+        let shutdown_signal = shutdown_signal.clone();
+        let initialize_channel = initialize_channel.clone();
+        let cfg = cfg.clone();
+        let running_server = drain_state.add_server();
+        match (maybe_tls_config, listener_cfg.proxy_protocol) {
+            (Some(tls_config), false) => spawn_server(
+                service,
+                incoming.map_ok(move |stream| TlsConnection::new(stream, tls_config.clone())),
+                shutdown_signal,
+                cfg,
+                initialize_channel,
+                running_server,
+                format!("HTTPS listener on {}", address),
+            ),
+            (Some(tls_config), true) => spawn_server(
+                service,
+                listener::proxy_protocol_incoming(incoming)
+                    .map_ok(move |stream| TlsConnection::new(stream, tls_config.clone())),
+                shutdown_signal,
+                cfg,
+                initialize_channel,
+                running_server,
+                format!("HTTPS listener with PROXY protocol on {}", address),
+            ),
+            (None, false) => spawn_server(
+                service,
+                incoming,
+                shutdown_signal,
+                cfg,
+                initialize_channel,
+                running_server,
+                format!("HTTP listener on {}", address),
+            ),
+            (None, true) => spawn_server(
+                service,
+                listener::proxy_protocol_incoming(incoming),
+                shutdown_signal,
+                cfg,
+                initialize_channel,
+                running_server,
+                format!("HTTP listener with PROXY protocol on {}", address),
+            ),
+        };
+    }
+    for _ in listener_list.iter() {
+        match utils::maybe_receive(&mut http_start_receiver, 5, "http-handler".to_string()).await {
+            Ok(Some(())) => Ok(()),
+            Ok(None) => {
+                Err("could not receive HTTP server ack after initialization after 5s".to_string())
+            }
+            Err(reason) => Err(reason),
+        }?;
+    }
+    for listener_cfg in listener_list.iter() {
+        let has_tls = listener_cfg.has_tls();
+        info!(
+            server_options.host = listener_cfg.host.as_str(),
+            server_options.port = listener_cfg.port,
+            server_options.tls = has_tls,
+            "{}",
+            format!(
+                "Started server on {}{}:{}{}",
+                if has_tls { "https://" } else { "http://" },
+                listener_cfg.host,
+                listener_cfg.port,
+                server_options.http_base_path
+            )
+        );
+    }
     Ok((http_stop_sender, http_start_receiver))
 }
 
+/// Adds a hint about how to fix common bind failures (e.g. port is already in use) to the error.
+fn make_bind_error_message(address: SocketAddr, reason: std::io::Error) -> String {
+    let maybe_hint = match reason.kind() {
+        std::io::ErrorKind::AddrInUse => Some(format!(
+            "Another RestCommander instance (or another program) is probably listening on port {}, Stop it or configure another port",
+            address.port()
+        )),
+        std::io::ErrorKind::PermissionDenied if address.port() < 1024 => Some(
+            "Ports below 1024 need root privileges (or CAP_NET_BIND_SERVICE capability on GNU/Linux), Configure a port above 1023 or grant the capability"
+                .to_string(),
+        ),
+        std::io::ErrorKind::AddrNotAvailable => Some(format!(
+            "{} is not an address of this machine, Configure another host (e.g. 0.0.0.0 for all addresses)",
+            address.ip()
+        )),
+        _ => None,
+    };
+    match maybe_hint {
+        Some(hint) => format!("could not listen on {}: {}. {}.", address, reason, hint),
+        None => format!("could not listen on {}: {}", address, reason),
+    }
+}
+
 pub async fn maybe_handle_message(channel_receiver: &mut Receiver<()>) -> Result<bool, String> {
     match utils::maybe_receive(channel_receiver, 1, "http handler".to_string()).await {
         Ok(None) => Ok(false),
@@ -405,88 +1128,152 @@ pub async fn maybe_handle_message(channel_receiver: &mut Receiver<()>) -> Result
     }
 }
 
+/// Accepts `GET` and `HEAD` requests. For `HEAD` requests hyper drops the response body and
+/// keeps the status-code and headers.
+fn get_or_head() -> impl Filter<Extract = (), Error = Rejection> + Copy {
+    warp::get().or(warp::head()).unify()
+}
+
 fn api_auth_test_filter(
-    tokens: Arc<RwLock<HashMap<String, usize>>>,
-    cfg: Arc<RwLock<Cfg>>,
+    tokens: AuthTokens,
+    cfg: Arc<ArcSwap<Cfg>>,
 ) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
     warp::path("test")
-        .and(authentication_with_token_filter(tokens, cfg.clone()))
+        .and(authentication_filter(tokens, cfg.clone()))
         .map(|_| make_api_response_ok())
 }
 
-fn authentication_with_token_filter(
-    tokens: Arc<RwLock<HashMap<String, usize>>>,
-    cfg: Arc<RwLock<Cfg>>,
+/// Accepts requests that are authenticated via a token of `tokens` (as `Authorization: Bearer`
+/// header or `token` cookie) or a configured TLS client certificate, Or all requests if
+/// authentication is disabled in configuration.
+///
+/// Token scopes are checked against the remaining request path (e.g. `run/deploy`).
+pub fn authentication_filter(
+    tokens: AuthTokens,
+    cfg: Arc<ArcSwap<Cfg>>,
 ) -> impl Filter<Extract = ((),), Error = Rejection> + Clone {
     let cfg2 = cfg.clone();
     warp::any()
-        .map(move || {
-            !cfg2
-                .read()
-                .unwrap()
-                .config_value
-                .server
-                .password_sha512
-                .is_empty()
-                .clone()
-        })
-        .and_then(|have_password: bool| async move {
-            if have_password {
+        .map(move || cfg2.load().config_value.server.authentication_is_enabled())
+        .and_then(|authentication_is_enabled: bool| async move {
+            if authentication_is_enabled {
                 Err(warp::reject::reject())
             } else {
                 Ok(())
             }
         })
-        .or(extract_token_filter().and_then(move |token: String| {
-            let tokens = tokens.clone();
-            let cfg = cfg.clone();
-            async move {
-                authentication_with_token(tokens, token, cfg)
-                    .map_err(|error| warp::reject::custom(HTTPError::Authentication(error)))
-            }
-        }))
+        .or(client_certificate_filter(cfg.clone()))
+        .unify()
+        .or(extract_token_filter().and(warp::path::peek()).and_then(
+            move |token: String, path: warp::path::Peek| {
+                let tokens = tokens.clone();
+                let cfg = cfg.clone();
+                async move {
+                    authentication_with_token(tokens, token, path.as_str(), cfg)
+                        .map_err(|error| warp::reject::custom(HTTPError::Authentication(error)))
+                }
+            },
+        ))
         .unify()
 }
 
-fn api_auth_token(
-    cfg: Arc<RwLock<Cfg>>,
-    maybe_captcha: Option<Arc<RwLock<captcha::Captcha>>>,
-    tokens: Arc<RwLock<HashMap<String, usize>>>,
-) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
-    let token_timeout = cfg
-        .read()
-        .unwrap()
-        .config_value
-        .server
-        .token_timeout
-        .clone();
-    warp::path("token")
-        .and(extract_basic_authentication_filter())
-        .map(
-            move |authorization_value: String, form: HashMap<String, String>| {
-                authentication_with_basic(
-                    cfg.clone(),
-                    maybe_captcha.clone(),
-                    authorization_value,
-                    form,
-                )
-            },
-        )
-        .map(move |result: Result<_, HTTPAuthenticationError>| {
-            if let Err(error) = result {
-                return make_api_response(Err(HTTPError::Authentication(error)));
-            }
-            let token = utils::to_sha512(uuid::Uuid::new_v4().to_string());
-            let timestamp = time::SystemTime::now()
+/// Allows requests to configured `anonymous_paths` without authentication.
+fn anonymous_path_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+) -> impl Filter<Extract = ((),), Error = Rejection> + Clone {
+    warp::path::peek().and_then(move |path: warp::path::Peek| {
+        let cfg = cfg.clone();
+        async move {
+            let path = path.as_str().trim_end_matches('/');
+            if cfg
+                .load()
+                .config_value
+                .server
+                .anonymous_paths
+                .iter()
+                .any(|prefix| path_has_prefix(path, prefix))
+            {
+                debug!(path = path, "Allowed anonymous request");
+                Ok(())
+            } else {
+                Err(warp::reject::reject())
+            }
+        }
+    })
+}
+
+/// Whether API `path` (e.g. `run/foo/bar`) is `prefix` or is inside it.
+fn path_has_prefix(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Authorizes clients whose verified TLS certificate name is in `tls_client_allowed_names`.
+fn client_certificate_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+) -> impl Filter<Extract = ((),), Error = Rejection> + Clone {
+    warp::ext::optional::<ClientCertificateName>().and_then(
+        move |maybe_client_certificate_name: Option<ClientCertificateName>| {
+            let cfg = cfg.clone();
+            async move {
+                let name = maybe_client_certificate_name
+                    .ok_or_else(warp::reject::reject)?
+                    .0;
+                if cfg
+                    .load()
+                    .config_value
+                    .server
+                    .tls_client_allowed_names
+                    .iter()
+                    .any(|wildcard_name| WildMatch::new(wildcard_name).matches(name.as_str()))
+                {
+                    trace!(name = name.as_str(), "Authorized by TLS client certificate");
+                    Ok(())
+                } else {
+                    Err(warp::reject::reject())
+                }
+            }
+        },
+    )
+}
+
+fn api_auth_token(
+    cfg: Arc<ArcSwap<Cfg>>,
+    maybe_captcha: Option<Arc<RwLock<captcha::Captcha>>>,
+    tokens: AuthTokens,
+) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
+    let token_timeout = cfg.load().config_value.server.token_timeout.clone();
+    warp::path("token")
+        .and(extract_basic_authentication_filter())
+        .then(
+            move |authorization_value: String, form: HashMap<String, String>| {
+                authentication_with_basic(
+                    cfg.clone(),
+                    maybe_captcha.clone(),
+                    authorization_value,
+                    form,
+                )
+            },
+        )
+        .map(move |result: Result<_, HTTPAuthenticationError>| {
+            let (maybe_username, maybe_scopes) = match result {
+                Ok(username_and_scopes) => username_and_scopes,
+                Err(error) => return make_api_response(Err(HTTPError::Authentication(error))),
+            };
+            let token = utils::to_sha512(uuid::Uuid::new_v4().to_string());
+            let timestamp = time::SystemTime::now()
                 .duration_since(time::UNIX_EPOCH)
                 .unwrap()
                 .as_secs() as usize
                 + token_timeout;
-            tokens
-                .clone()
-                .write()
-                .unwrap()
-                .insert(token.clone(), timestamp);
+            tokens.write().insert(
+                token.clone(),
+                AuthToken {
+                    expire_time: timestamp,
+                    maybe_username,
+                    maybe_scopes,
+                },
+            );
             make_api_response_with_headers(
                 Ok(serde_json::json!({ "token": token })),
                 Some({
@@ -543,306 +1330,1648 @@ fn extract_basic_authentication_filter(
 }
 
 fn api_get_commands_filter(
-    commands: Arc<RwLock<Command>>,
+    commands: Arc<ArcSwap<Command>>,
 ) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
-    warp::get().and(warp::path("commands")).then(move || {
-        let commands = commands.clone();
-        async move {
-            make_api_response_ok_with_result(
-                serde_json::to_value(commands.read().unwrap().deref()).unwrap(),
-            )
-        }
-    })
+    get_or_head()
+        .and(warp::path("commands"))
+        .and(warp::query::query::<HashMap<String, String>>())
+        .then(move |query: HashMap<String, String>| {
+            let commands = commands.clone();
+            async move {
+                match query.get("format").map(String::as_str) {
+                    None | Some("tree") => make_api_response_ok_with_result(
+                        serde_json::to_value(commands.load().deref().deref()).unwrap(),
+                    ),
+                    Some("flat") => {
+                        make_api_response_ok_with_result(make_flat_commands(&commands.load()))
+                    }
+                    Some(format) => make_api_response(Err(HTTPError::API(
+                        HTTPAPIError::UnknownCommandsFormat {
+                            format: format.to_string(),
+                        },
+                    ))),
+                }
+            }
+        })
+}
+
+/// Makes a flat list of runnable commands in form of `[{path, description, method, inputs}]`.
+fn make_flat_commands(commands: &Command) -> serde_json::Value {
+    serde_json::Value::Array(
+        commands
+            .runnable_commands()
+            .into_iter()
+            .map(|command| {
+                json!({
+                    "path": command.http_path,
+                    "description": command.info.as_ref().map(|info| info.description.clone()),
+                    "method": "POST",
+                    "inputs": command.info.as_ref().map(|info| info.inputs.clone()).unwrap_or_default(),
+                })
+            })
+            .collect(),
+    )
+}
+
+fn api_ping_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+    commands: Arc<ArcSwap<Command>>,
+) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
+    get_or_head()
+        .and(warp::path("ping"))
+        .and(warp::path::end())
+        .and(warp::query::query::<HashMap<String, String>>())
+        .map(move |query: HashMap<String, String>| {
+            let commands = commands.load();
+            if let Err(reason) = check_commands_root_directory(&commands) {
+                return make_api_response(Err(HTTPError::API(reason)));
+            };
+            // Not ready until the first successful reload of commands:
+            if cfg.load().config_value.commands.wait_for_commands && !commands.has_commands() {
+                return make_api_response(Err(HTTPError::API(HTTPAPIError::CommandsNotLoaded)));
+            };
+            let time = time::SystemTime::now()
+                .duration_since(time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            let mut result = json!({ "time": time });
+            if let Some(nonce) = query.get("nonce") {
+                result["nonce"] = serde_json::Value::String(nonce.clone());
+            };
+            make_api_response_ok_with_result(result)
+        })
 }
 
-fn api_run_command_filter(
-    cfg: Arc<RwLock<Cfg>>,
-    commands: Arc<RwLock<Command>>,
+/// Makes the filter of `POST` requests that run commands by their path (e.g. `deploy/app`)
+/// without `/api/run` prefix.
+///
+/// It does not authenticate requests (see [`authentication_filter`]) and `tokens` are just used to
+/// find the username of the requester. Rejections of the filter should be handled via
+/// [`handle_rejection`].
+pub fn api_run_command_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+    commands: Arc<ArcSwap<Command>>,
     report_state: Arc<AsyncRwLock<ReportState>>,
+    maintenance: MaintenanceState,
+    command_semaphores: CommandSemaphores,
+    drain_state: DrainState,
+    command_rate_limits: CommandRateLimits,
+    tokens: AuthTokens,
 ) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
+    let idempotency_keys = IdempotencyKeys::default();
+    let cfg2 = cfg.clone();
     warp::post()
-        .map(move || (cfg.clone(), commands.clone(), report_state.clone()))
+        .map(move || {
+            (
+                cfg.clone(),
+                commands.clone(),
+                report_state.clone(),
+                maintenance.clone(),
+                command_semaphores.clone(),
+                drain_state.clone(),
+                command_rate_limits.clone(),
+            )
+        })
         .and(warp::path::tail())
         .and(
-            // We want to try to decode Body if `Content-length` header exists and its value is > 0
-            // If we do not do this, for empty bodies and `Content-type: application/json`, We need to post `{}` to make it work
-            warp::body::bytes()
-                .and(
-                    warp::header::<String>(warp::http::header::CONTENT_TYPE.as_str())
-                        .or(warp::any().map(|| "application/json".to_string()))
-                        .unify(),
-                )
-                .and(
-                    warp::header::<usize>(warp::http::header::CONTENT_LENGTH.as_str())
-                        .or(warp::any().map(|| 0))
-                        .unify(),
+            // `application/octet-stream` bodies are streamed to command's stdin as they arrive:
+            media_type_filter("application/octet-stream")
+                .and(warp::body::stream())
+                .map(make_body_stream)
+                .map(CommandRequestBody::Stream)
+                .or(multipart_form_filter(cfg2).map(CommandRequestBody::Multipart))
+                .unify()
+                .or(
+                    // We want to try to decode Body if `Content-length` header exists and its value is > 0
+                    // If we do not do this, for empty bodies and `Content-type: application/json`, We need to post `{}` to make it work
+                    warp::body::bytes()
+                        .and(
+                            warp::header::<String>(warp::http::header::CONTENT_TYPE.as_str())
+                                .or(warp::any().map(|| "application/json".to_string()))
+                                .unify(),
+                        )
+                        .and(
+                            warp::header::<usize>(warp::http::header::CONTENT_LENGTH.as_str())
+                                .or(warp::any().map(|| 0))
+                                .unify(),
+                        )
+                        .and_then(
+                            |bytes: Bytes, content_type: String, content_length: usize| async move {
+                                let content_type = media_type(&content_type);
+                                if &content_type == "multipart/form-data" {
+                                    // Keep the rejection of multipart form filter:
+                                    return Err(warp::reject());
+                                }
+                                if &content_type != "application/json"
+                                    && &content_type != "application/x-www-form-urlencoded"
+                                {
+                                    return Err(warp::reject::custom(HTTPError::Deserialize(
+                                        "Unsupported content-type".to_string(),
+                                    )));
+                                }
+                                if content_length == 0 {
+                                    return Ok(CommandOptionsValue::new());
+                                }
+                                if &content_type == "application/json" {
+                                    serde_json::from_slice::<CommandOptionsValue>(&bytes).map_err(
+                                        |error| {
+                                            warp::reject::custom(HTTPError::Deserialize(
+                                                error.to_string(),
+                                            ))
+                                        },
+                                    )
+                                } else {
+                                    serde_urlencoded::from_bytes::<CommandOptionsValue>(&bytes)
+                                        .map_err(|error| {
+                                            warp::reject::custom(HTTPError::Deserialize(
+                                                error.to_string(),
+                                            ))
+                                        })
+                                }
+                            },
+                        )
+                        .map(CommandRequestBody::Options),
                 )
-                .and_then(
-                    |bytes: Bytes, content_type: String, content_length: usize| async move {
-                        if &content_type != "application/json"
-                            && &content_type != "application/x-www-form-urlencoded"
-                        {
-                            return Err(warp::reject::custom(HTTPError::Deserialize(
-                                "Unsupported content-type".to_string(),
-                            )));
-                        }
-                        if content_length == 0 {
-                            return Ok(CommandOptionsValue::new());
-                        }
-                        if &content_type == "application/json" {
-                            serde_json::from_slice::<CommandOptionsValue>(&bytes).map_err(|error| {
-                                warp::reject::custom(HTTPError::Deserialize(error.to_string()))
-                            })
-                        } else {
-                            serde_urlencoded::from_bytes::<CommandOptionsValue>(&bytes).map_err(
-                                |error| {
-                                    warp::reject::custom(HTTPError::Deserialize(error.to_string()))
-                                },
-                            )
-                        }
-                    },
-                ),
+                .unify(),
         )
         .and(warp::query::query::<CommandOptionsValue>())
         .and(
             warp::header::headers_cloned()
-                .and(warp::addr::remote())
-                .map(|headers: HeaderMap, maybe_address: Option<SocketAddr>| {
-                    let mut options = CommandOptionsValue::new();
-                    let mut statistics = false;
-                    headers
-                        .into_iter()
-                        .for_each(|(maybe_header_name, header_value)| {
-                            if maybe_header_name.is_none() {
-                                return ();
-                            }
-                            let header_name = maybe_header_name.unwrap().to_string();
-                            if header_name.to_uppercase().as_str() == "X-RESTCOMMANDER-STATISTICS" {
-                                statistics = true;
-                                return;
-                            };
-                            if let Ok(header_value_str) = header_value.to_str() {
-                                options.insert(
-                                    if header_name.to_uppercase().starts_with("X-")
-                                        && header_name.len() > 2
-                                    {
-                                        header_name[2..].to_string()
-                                    } else {
-                                        format!(
-                                            "RESTCOMMANDER_HEADER_{}",
-                                            header_name
-                                                .to_string()
-                                                .to_uppercase()
-                                                .replace("-", "_")
-                                        )
-                                    },
-                                    serde_json::from_str::<CommandOptionValue>(header_value_str)
-                                        .unwrap_or_else(|_| {
-                                            CommandOptionValue::String(header_value_str.to_string())
-                                        }),
-                                );
-                            }
-                        });
-                    let address = maybe_address.unwrap();
-                    options.insert(
-                        "RESTCOMMANDER_CLIENT_IP".to_string(),
-                        CommandOptionValue::String(address.clone().ip().to_string()),
-                    );
-                    options.insert(
-                        "RESTCOMMANDER_CLIENT_PORT".to_string(),
-                        CommandOptionValue::Integer(address.port() as i64),
-                    );
-                    (options, statistics)
-                }),
+                .and(remote_address_filter())
+                .map(make_command_options_from_headers),
         )
-        .and(warp::addr::remote())
+        .and(remote_address_filter())
+        .and(client_certificate_name_filter())
+        .and(token_username_filter(tokens))
+        .and(warp::header::optional::<String>(IDEMPOTENCY_KEY_HEADER))
+        .and(warp::any().map(move || idempotency_keys.clone()))
         .and_then(
-            |state: (
-                Arc<RwLock<Cfg>>,
-                Arc<RwLock<Command>>,
-                Arc<AsyncRwLock<ReportState>>,
-            ),
+            |state: CommandFilterState,
              tail: Tail,
-             command_options_from_body: CommandOptionsValue,
-             command_options_from_uri: CommandOptionsValue,
-             (command_input_from_headers, statistics),
-             addr: Option<SocketAddr>| {
+             body: CommandRequestBody,
+             mut command_options_from_uri: CommandOptionsValue,
+             (command_input_from_headers, headers, statistics, verbose_errors, media_types),
+             addr: Option<SocketAddr>,
+             maybe_client_certificate_name: Option<String>,
+             maybe_username: Option<String>,
+             maybe_idempotency_key: Option<String>,
+             idempotency_keys: IdempotencyKeys| {
                 let addr = addr.unwrap();
-                let mut input = CommandInput::default();
-                input.statistics = statistics;
-                input.options = unify_options(
-                    [
-                        command_input_from_headers,
-                        command_options_from_uri,
-                        command_options_from_body,
-                        add_configuration_to_options(state.0.clone()),
-                    ]
-                    .to_vec(),
-                );
+                // It is not a command option:
+                let maybe_select =
+                    command_options_from_uri
+                        .remove(SELECT_QUERY_PARAMETER)
+                        .map(|select| match select {
+                            CommandOptionValue::String(select) => select,
+                            other => json!(other).to_string(),
+                        });
+                let maybe_encoding = command_options_from_uri
+                    .remove(ENCODING_QUERY_PARAMETER)
+                    .map(|encoding| match encoding {
+                        CommandOptionValue::String(encoding) => encoding,
+                        other => json!(other).to_string(),
+                    });
+                let debug = command_options_from_uri
+                    .remove(DEBUG_QUERY_PARAMETER)
+                    .map(|debug| is_true_query_value(&debug))
+                    .unwrap_or_default();
+                let verbose_errors = command_options_from_uri
+                    .remove(VERBOSE_ERRORS_QUERY_PARAMETER)
+                    .map(|verbose_errors| is_true_query_value(&verbose_errors))
+                    .unwrap_or_default()
+                    || verbose_errors;
+                let upload_file_max_size =
+                    state.0.load().config_value.commands.upload_file_max_size;
+                let idempotency_key_ttl = state.0.load().config_value.commands.idempotency_key_ttl;
                 async move {
-                    match maybe_run_command(
-                        state.1,
-                        tail.as_str().to_string(),
-                        input,
-                        state.2,
-                        addr.to_string(),
+                    let (command_options_from_body, maybe_body_stream, uploaded_file_list) =
+                        match body {
+                            CommandRequestBody::Options(options) => (options, None, Vec::new()),
+                            CommandRequestBody::Stream(body_stream) => {
+                                (CommandOptionsValue::new(), Some(body_stream), Vec::new())
+                            }
+                            CommandRequestBody::Multipart(form) => {
+                                let (options, uploaded_file_list) =
+                                    save_multipart_form(form, upload_file_max_size)
+                                        .await
+                                        .map_err(warp::reject::custom)?;
+                                (options, None, uploaded_file_list)
+                            }
+                        };
+                    let mut input = CommandInput::default();
+                    input.statistics = statistics;
+                    input.verbose_errors = verbose_errors;
+                    input.headers = headers;
+                    input.options = unify_options(
+                        [
+                            command_input_from_headers,
+                            command_options_from_uri,
+                            command_options_from_body,
+                            add_configuration_to_options(state.0.clone()),
+                        ]
+                        .to_vec(),
+                    );
+                    let command_path = tail.as_str().to_string();
+                    let maybe_idempotency_key = maybe_idempotency_key
+                        .filter(|_| idempotency_key_ttl > 0)
+                        .map(|key| IdempotencyKey {
+                            key,
+                            command_path: command_path.clone(),
+                            maybe_username: maybe_username.clone(),
+                            maybe_client_certificate_name: maybe_client_certificate_name.clone(),
+                        });
+                    let result = match run_once_per_idempotency_key(
+                        idempotency_keys,
+                        maybe_idempotency_key,
+                        idempotency_key_ttl,
+                        maybe_run_command(
+                            state.0,
+                            state.1,
+                            command_path,
+                            CommandRequest {
+                                input,
+                                media_types,
+                                maybe_body_stream,
+                                maybe_client_certificate_name,
+                                maybe_username,
+                                maybe_select,
+                                maybe_encoding,
+                                debug,
+                            },
+                            state.2,
+                            state.3,
+                            state.4,
+                            state.5,
+                            state.6,
+                            addr.to_string(),
+                        ),
                     )
                     .await
                     {
                         Err(reason) => Err(warp::reject::custom(HTTPError::API(reason))),
                         Ok(response) => Ok(response),
-                    }
+                    };
+                    // Uploaded files are removed here, whether the command succeeded or not:
+                    drop(uploaded_file_list);
+                    result
                 }
             },
         )
 }
 
-fn api_get_command_state_filter(
-    cfg: Arc<RwLock<Cfg>>,
-    commands: Arc<RwLock<Command>>,
+/// Shared state that command filters pass to their handlers.
+type CommandFilterState = (
+    Arc<ArcSwap<Cfg>>,
+    Arc<ArcSwap<Command>>,
+    Arc<AsyncRwLock<ReportState>>,
+    MaintenanceState,
+    CommandSemaphores,
+    DrainState,
+    CommandRateLimits,
+);
+
+fn api_map_command_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+    commands: Arc<ArcSwap<Command>>,
     report_state: Arc<AsyncRwLock<ReportState>>,
+    maintenance: MaintenanceState,
+    command_semaphores: CommandSemaphores,
+    drain_state: DrainState,
+    command_rate_limits: CommandRateLimits,
+    tokens: AuthTokens,
 ) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
-    warp::get()
-        .map(move || (cfg.clone(), commands.clone(), report_state.clone()))
+    let cfg2 = cfg.clone();
+    warp::post()
+        .map(move || {
+            (
+                cfg.clone(),
+                commands.clone(),
+                report_state.clone(),
+                maintenance.clone(),
+                command_semaphores.clone(),
+                drain_state.clone(),
+                command_rate_limits.clone(),
+            )
+        })
         .and(warp::path::tail())
-        .and(warp::addr::remote())
+        .and(map_inputs_filter(cfg2))
+        .and(warp::query::query::<CommandOptionsValue>())
+        .and(
+            warp::header::headers_cloned()
+                .and(remote_address_filter())
+                .map(make_command_options_from_headers),
+        )
+        .and(remote_address_filter())
+        .and(client_certificate_name_filter())
+        .and(token_username_filter(tokens))
         .and_then(
-            |state: (
-                Arc<RwLock<Cfg>>,
-                Arc<RwLock<Command>>,
-                Arc<AsyncRwLock<ReportState>>,
-            ),
+            |state: CommandFilterState,
              tail: Tail,
-             addr: Option<SocketAddr>| async move {
+             command_options_list_from_body: Vec<CommandOptionsValue>,
+             mut command_options_from_uri: CommandOptionsValue,
+             (command_input_from_headers, headers, statistics, verbose_errors, media_types): CommandHeadersInput,
+             addr: Option<SocketAddr>,
+             maybe_client_certificate_name: Option<String>,
+             maybe_username: Option<String>| async move {
                 let addr = addr.unwrap();
-                match maybe_get_command_state(
+                // It is not a command option:
+                let verbose_errors = command_options_from_uri
+                    .remove(VERBOSE_ERRORS_QUERY_PARAMETER)
+                    .map(|verbose_errors| is_true_query_value(&verbose_errors))
+                    .unwrap_or_default()
+                    || verbose_errors;
+                let input_list = command_options_list_from_body
+                    .into_iter()
+                    .map(|command_options_from_body| CommandInput {
+                        options: unify_options(
+                            [
+                                command_input_from_headers.clone(),
+                                command_options_from_uri.clone(),
+                                command_options_from_body,
+                                add_configuration_to_options(state.0.clone()),
+                            ]
+                            .to_vec(),
+                        ),
+                        statistics,
+                        verbose_errors,
+                        headers: headers.clone(),
+                    })
+                    .collect();
+                maybe_map_command(
                     state.0,
                     state.1,
                     tail.as_str().to_string(),
+                    CommandMapRequest {
+                        input_list,
+                        statistics,
+                        media_types,
+                        maybe_client_certificate_name,
+                        maybe_username,
+                    },
                     state.2,
+                    state.3,
+                    state.4,
+                    state.5,
+                    state.6,
                     addr.to_string(),
                 )
                 .await
-                {
-                    Err(reason) => Err(warp::reject::custom(HTTPError::API(reason))),
-                    Ok(response) => Ok(response),
-                }
+                .map_err(|reason| warp::reject::custom(HTTPError::API(reason)))
             },
         )
 }
 
-fn api_reload_commands_filter(
-    commands: Arc<RwLock<Command>>,
-) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
-    warp::get().and(warp::path("commands")).map(move || {
-        commands
-            .write()
-            .unwrap()
-            .reload()
-            .map(|_| make_api_response_ok())
-            .or_else::<Response<String>, _>(|error| {
-                Ok(make_api_response(Err(HTTPError::API(
-                    HTTPAPIError::ReloadCommands {
-                        message: error.to_string(),
-                    },
-                ))))
-            })
-            .unwrap()
-    })
-}
-
-fn api_reload_config_filter(
-    cfg: Arc<RwLock<Cfg>>,
-    http_notify_channel: tokio::sync::mpsc::Sender<()>,
-) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
-    warp::get().and(warp::path("config")).then(move || {
-        let cfg = cfg.clone();
-        let http_notify_channel = http_notify_channel.clone();
-        async move {
-            if let Err(reason) = cfg.write().unwrap().try_reload() {
-                return make_api_response(Err(HTTPError::API(HTTPAPIError::ReloadConfig {
-                    message: reason.to_string(),
-                })));
-            };
-            http_notify_channel.send(()).await.unwrap();
-            make_api_response_ok()
-        }
-    })
-}
+static JSON_RPC_VERSION: &str = "2.0";
+const JSON_RPC_PARSE_ERROR: i64 = -32700;
+const JSON_RPC_INVALID_REQUEST: i64 = -32600;
+const JSON_RPC_METHOD_NOT_FOUND: i64 = -32601;
+const JSON_RPC_INVALID_PARAMS: i64 = -32602;
+const JSON_RPC_SERVER_ERROR: i64 = -32000;
 
-fn api_report_filter(
-    _cfg: Arc<RwLock<Cfg>>,
+/// Accepts JSON-RPC 2.0 requests (or batches of them) whose `method` is the command path and
+/// `params` are its input options.
+fn api_rpc_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+    commands: Arc<ArcSwap<Command>>,
     report_state: Arc<AsyncRwLock<ReportState>>,
+    maintenance: MaintenanceState,
+    command_semaphores: CommandSemaphores,
+    drain_state: DrainState,
+    command_rate_limits: CommandRateLimits,
+    tokens: AuthTokens,
 ) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
+    let cfg2 = cfg.clone();
     warp::post()
-        .and(warp::path("report"))
-        .map(move || report_state.clone())
-        .and(warp::body::json::<Report>())
-        .then(
-            |report_state: Arc<AsyncRwLock<ReportState>>, report: Report| async move {
-                let report_state_locked = report_state.read().await;
-                let search_result = match crate::report::search(
-                    report.from,
-                    report.before_time,
-                    report.after_time,
-                    report.context,
-                    report.limit,
-                    report_state_locked.clone(),
-                )
-                .await
-                {
-                    Ok(report_list) => {
-                        if report_list.is_empty() {
-                            make_api_response(Err(HTTPError::API(HTTPAPIError::ReportNotFound)))
+        .and(warp::path("rpc"))
+        .and(warp::path::end())
+        .map(move || {
+            (
+                cfg.clone(),
+                commands.clone(),
+                report_state.clone(),
+                maintenance.clone(),
+                command_semaphores.clone(),
+                drain_state.clone(),
+                command_rate_limits.clone(),
+            )
+        })
+        .and(limited_body_filter(cfg2))
+        .and(
+            warp::header::headers_cloned()
+                .and(remote_address_filter())
+                .map(make_command_options_from_headers),
+        )
+        .and(remote_address_filter())
+        .and(client_certificate_name_filter())
+        .and(token_username_filter(tokens))
+        .and_then(
+            |state: CommandFilterState,
+             body: Bytes,
+             headers_input: CommandHeadersInput,
+             addr: Option<SocketAddr>,
+             maybe_client_certificate_name: Option<String>,
+             maybe_username: Option<String>| async move {
+                let address = addr.unwrap().to_string();
+                let mut traffic_list = Vec::new();
+                let maybe_response = match serde_json::from_slice::<serde_json::Value>(&body) {
+                    Err(reason) => Some(make_rpc_error(
+                        serde_json::Value::Null,
+                        JSON_RPC_PARSE_ERROR,
+                        reason.to_string(),
+                        None,
+                    )),
+                    Ok(serde_json::Value::Array(request_list)) if request_list.is_empty() => {
+                        Some(make_rpc_error(
+                            serde_json::Value::Null,
+                            JSON_RPC_INVALID_REQUEST,
+                            "empty batch".to_string(),
+                            None,
+                        ))
+                    }
+                    Ok(serde_json::Value::Array(request_list))
+                        if request_list.len()
+                            > state.0.load().config_value.commands.rpc_max_batch_size =>
+                    {
+                        Some(make_rpc_error(
+                            serde_json::Value::Null,
+                            JSON_RPC_INVALID_REQUEST,
+                            format!(
+                                "batch has more than {} requests",
+                                state.0.load().config_value.commands.rpc_max_batch_size
+                            ),
+                            None,
+                        ))
+                    }
+                    Ok(serde_json::Value::Array(request_list)) => {
+                        let semaphore = Arc::new(tokio::sync::Semaphore::new(
+                            state.0.load().config_value.commands.map_concurrency.max(1),
+                        ));
+                        let response_list = futures_util::future::join_all(
+                            request_list.into_iter().map(|request| {
+                                let state = state.clone();
+                                let headers_input = headers_input.clone();
+                                let address = address.clone();
+                                let maybe_client_certificate_name =
+                                    maybe_client_certificate_name.clone();
+                                let maybe_username = maybe_username.clone();
+                                let semaphore = semaphore.clone();
+                                async move {
+                                    // The semaphore is never closed:
+                                    let _permit = semaphore.acquire().await.unwrap();
+                                    maybe_run_rpc_request(
+                                        state,
+                                        request,
+                                        headers_input,
+                                        address,
+                                        maybe_client_certificate_name,
+                                        maybe_username,
+                                    )
+                                    .await
+                                }
+                            }),
+                        )
+                        .await
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<_>>();
+                        if response_list.is_empty() {
+                            None
                         } else {
-                            let report_list = report_list
-                                .into_iter()
-                                .map(|report| serde_json::to_value(&report).unwrap())
-                                .collect();
-                            make_api_response_ok_with_result(report_list)
+                            let (response_list, maybe_command_http_path_list): (Vec<_>, Vec<_>) =
+                                response_list.into_iter().unzip();
+                            for (response, maybe_command_http_path) in
+                                response_list.iter().zip(maybe_command_http_path_list)
+                            {
+                                if let Some(command_http_path) = maybe_command_http_path {
+                                    traffic_list.push((
+                                        command_http_path,
+                                        response.to_string().len() as u64,
+                                    ));
+                                };
+                            }
+                            Some(serde_json::Value::Array(response_list))
                         }
                     }
-                    Err(error) => make_api_response(Err(HTTPError::API(match error {
-                        ReportError::NotAvailable => HTTPAPIError::ReportNotAvailable {
-                            message: error.to_string(),
-                        },
-                        _ => HTTPAPIError::Report {
-                            message: error.to_string(),
-                        },
-                    }))),
+                    Ok(request) => maybe_run_rpc_request(
+                        state,
+                        request,
+                        headers_input,
+                        address,
+                        maybe_client_certificate_name,
+                        maybe_username,
+                    )
+                    .await
+                    .map(|(response, maybe_command_http_path)| {
+                        if let Some(command_http_path) = maybe_command_http_path {
+                            traffic_list
+                                .push((command_http_path, response.to_string().len() as u64));
+                        };
+                        response
+                    }),
                 };
-                drop(report_state_locked);
-                search_result
-            },
-        )
-}
-
-fn api_set_password_filter(
-    cfg: Arc<RwLock<Cfg>>,
-) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
-    warp::post()
-        .and(warp::path("setPassword"))
-        .map(move || cfg.clone())
-        .and(warp::body::json())
-        .then(
-            move |cfg: Arc<RwLock<Cfg>>, password: SetPassword| async move {
-                try_set_password(cfg, password)
-                    .map(|_| make_api_response_ok())
-                    .or_else::<Response<String>, _>(|error| {
-                        Ok(make_api_response(Err(HTTPError::API(error))))
-                    })
-                    .unwrap()
+                // Notifications do not have any response:
+                let (status_code, body) = match maybe_response {
+                    Some(response) => (StatusCode::OK, response.to_string()),
+                    None => (StatusCode::NO_CONTENT, String::new()),
+                };
+                let mut response = Response::builder()
+                    .status(status_code)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body)
+                    .unwrap();
+                response
+                    .extensions_mut()
+                    .insert(CommandTrafficList(traffic_list));
+                Ok::<_, Rejection>(response)
             },
         )
 }
 
-fn redirect_root_to_index_html_filter(
-    cfg: Arc<RwLock<Cfg>>,
-) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
-    warp::get().and(warp::path::end()).then(move || {
-        let cfg = cfg.clone();
+/// Runs the command of a single JSON-RPC request via [maybe_run_command] and makes its JSON-RPC
+/// response.
+///
+/// Also returns HTTP path of the command if it is found and run. Returns `None` for notifications
+/// (requests without `id`).
+async fn maybe_run_rpc_request(
+    state: CommandFilterState,
+    request: serde_json::Value,
+    (command_input_from_headers, headers, statistics, verbose_errors, media_types): CommandHeadersInput,
+    address: String,
+    maybe_client_certificate_name: Option<String>,
+    maybe_username: Option<String>,
+) -> Option<(serde_json::Value, Option<String>)> {
+    let request = match request {
+        serde_json::Value::Object(request) => request,
+        _ => {
+            return Some((
+                make_rpc_error(
+                    serde_json::Value::Null,
+                    JSON_RPC_INVALID_REQUEST,
+                    "request should be an object".to_string(),
+                    None,
+                ),
+                None,
+            ))
+        }
+    };
+    let maybe_id = request.get("id").cloned();
+    let id = maybe_id.clone().unwrap_or(serde_json::Value::Null);
+    let method = match (
+        request.get("jsonrpc").and_then(|version| version.as_str()),
+        request.get("method").and_then(|method| method.as_str()),
+    ) {
+        (Some(version), Some(method)) if version == JSON_RPC_VERSION => method.to_string(),
+        _ => {
+            return Some((
+                make_rpc_error(
+                    id,
+                    JSON_RPC_INVALID_REQUEST,
+                    format!(
+                        "request should have `jsonrpc` with value {:?} and string `method`",
+                        JSON_RPC_VERSION
+                    ),
+                    None,
+                ),
+                None,
+            ))
+        }
+    };
+    let command_options_from_params = match request.get("params") {
+        None => Ok(CommandOptionsValue::new()),
+        Some(params @ serde_json::Value::Object(_)) => {
+            serde_json::from_value::<CommandOptionsValue>(params.clone())
+                .map_err(|reason| reason.to_string())
+        }
+        Some(_) => Err("params should be an object of command options".to_string()),
+    };
+    let command_options_from_params = match command_options_from_params {
+        Ok(options) => options,
+        Err(reason) => {
+            return Some((
+                make_rpc_error(id, JSON_RPC_INVALID_PARAMS, reason, None),
+                None,
+            ))
+        }
+    };
+    let input = CommandInput {
+        options: unify_options(
+            [
+                command_input_from_headers,
+                command_options_from_params,
+                add_configuration_to_options(state.0.clone()),
+            ]
+            .to_vec(),
+        ),
+        statistics,
+        verbose_errors,
+        headers,
+    };
+    let result = maybe_run_command(
+        state.0,
+        state.1,
+        method.trim_start_matches('/').to_string(),
+        CommandRequest {
+            input,
+            media_types,
+            maybe_body_stream: None,
+            maybe_client_certificate_name,
+            maybe_username,
+            maybe_select: None,
+            maybe_encoding: None,
+            debug: false,
+        },
+        state.2,
+        state.3,
+        state.4,
+        state.5,
+        state.6,
+        address,
+    )
+    .await;
+    maybe_id.as_ref()?;
+    let maybe_command_http_path = result.as_ref().ok().and_then(|response| {
+        response
+            .extensions()
+            .get::<CommandTrafficList>()
+            .and_then(|CommandTrafficList(traffic_list)| traffic_list.first())
+            .map(|(command_http_path, _)| command_http_path.clone())
+    });
+    let response = match result {
+        Ok(response) => {
+            // Text outputs of commands with `output_content_type` are not JSON:
+            let mut body = serde_json::from_str::<serde_json::Value>(response.body())
+                .unwrap_or_else(|_| json!({ "result": response.body() }));
+            if response.status().is_success() {
+                json!({
+                    "jsonrpc": JSON_RPC_VERSION,
+                    "result": body.get_mut("result").map(serde_json::Value::take),
+                    "id": id,
+                })
+            } else {
+                let message = body
+                    .get("result")
+                    .and_then(|result| result.as_str())
+                    .map(|result| result.to_string())
+                    .unwrap_or_else(|| format!("command failed with {}", response.status()));
+                make_rpc_error(id, JSON_RPC_SERVER_ERROR, message, Some(body))
+            }
+        }
+        Err(reason) => {
+            let code = match reason {
+                HTTPAPIError::CommandNotFound { .. } => JSON_RPC_METHOD_NOT_FOUND,
+                HTTPAPIError::CheckInput { .. } => JSON_RPC_INVALID_PARAMS,
+                _ => JSON_RPC_SERVER_ERROR,
+            };
+            let message = reason.to_string();
+            let data = make_api_response_body(Err(HTTPError::API(reason)), None, None, None, None);
+            make_rpc_error(id, code, message, Some(data))
+        }
+    };
+    Some((response, maybe_command_http_path))
+}
+
+fn make_rpc_error(
+    id: serde_json::Value,
+    code: i64,
+    message: String,
+    maybe_data: Option<serde_json::Value>,
+) -> serde_json::Value {
+    let mut error = json!({"code": code, "message": message});
+    if let Some(data) = maybe_data {
+        error["data"] = data;
+    };
+    json!({"jsonrpc": JSON_RPC_VERSION, "error": error, "id": id})
+}
+
+/// Command options, raw headers (for options with `header`), `statistics`, `verbose_errors` and
+/// media types from request headers.
+type CommandHeadersInput = (
+    CommandOptionsValue,
+    HashMap<String, String>,
+    bool,
+    bool,
+    CommandRequestMediaTypes,
+);
+
+/// Makes command options from request headers and client address.
+///
+/// Also returns the raw headers, whether `X-RESTCOMMANDER-STATISTICS` and
+/// `X-RESTCOMMANDER-VERBOSE-ERRORS` headers exist and the request media types.
+fn make_command_options_from_headers(
+    headers: HeaderMap,
+    maybe_address: Option<SocketAddr>,
+) -> CommandHeadersInput {
+    let mut options = CommandOptionsValue::new();
+    let mut statistics = false;
+    let mut verbose_errors = false;
+    let media_types = CommandRequestMediaTypes::from_headers(&headers);
+    let raw_headers = headers
+        .iter()
+        .filter_map(|(header_name, header_value)| {
+            header_value
+                .to_str()
+                .ok()
+                .map(|header_value| (header_name.to_string(), header_value.to_string()))
+        })
+        .collect();
+    headers
+        .into_iter()
+        .for_each(|(maybe_header_name, header_value)| {
+            if maybe_header_name.is_none() {
+                return ();
+            }
+            let header_name = maybe_header_name.unwrap().to_string();
+            if header_name.to_uppercase().as_str() == "X-RESTCOMMANDER-STATISTICS" {
+                statistics = true;
+                return;
+            };
+            if header_name.to_uppercase().as_str() == "X-RESTCOMMANDER-VERBOSE-ERRORS" {
+                verbose_errors = true;
+                return;
+            };
+            if let Ok(header_value_str) = header_value.to_str() {
+                options.insert(
+                    if header_name.to_uppercase().starts_with("X-") && header_name.len() > 2 {
+                        header_name[2..].to_string()
+                    } else {
+                        format!(
+                            "RESTCOMMANDER_HEADER_{}",
+                            header_name.to_string().to_uppercase().replace("-", "_")
+                        )
+                    },
+                    serde_json::from_str::<CommandOptionValue>(header_value_str).unwrap_or_else(
+                        |_| CommandOptionValue::String(header_value_str.to_string()),
+                    ),
+                );
+            }
+        });
+    if let Some(address) = maybe_address {
+        options.insert(
+            "RESTCOMMANDER_CLIENT_IP".to_string(),
+            CommandOptionValue::String(address.ip().to_string()),
+        );
+        options.insert(
+            "RESTCOMMANDER_CLIENT_PORT".to_string(),
+            CommandOptionValue::Integer(address.port() as i64),
+        );
+    };
+    (
+        options,
+        raw_headers,
+        statistics,
+        verbose_errors,
+        media_types,
+    )
+}
+
+/// Accepts `multipart/form-data` bodies that are not larger than configured
+/// `commands.upload_max_size` bytes at the time of the request.
+fn multipart_form_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+) -> impl Filter<Extract = (FormData,), Error = Rejection> + Clone {
+    warp::header::<String>(CONTENT_TYPE.as_str())
+        .and_then(|content_type: String| async move {
+            if media_type(&content_type) == "multipart/form-data" {
+                Ok(())
+            } else {
+                Err(warp::reject())
+            }
+        })
+        .untuple_one()
+        .and(warp::header::optional::<u64>(CONTENT_LENGTH.as_str()))
+        .and_then(move |maybe_content_length: Option<u64>| {
+            let max_size = cfg.load().config_value.commands.upload_max_size;
+            async move {
+                match maybe_content_length {
+                    Some(content_length) if content_length > max_size => Err(warp::reject::custom(
+                        HTTPError::API(HTTPAPIError::PayloadTooLarge {
+                            message: format!("Request body is larger than {} bytes", max_size),
+                        }),
+                    )),
+                    _ => Ok(()),
+                }
+            }
+        })
+        .untuple_one()
+        // Its maximum length is fixed when the filter is made, So the current configured one is
+        // checked above. It also rejects bodies without `Content-Length`:
+        .and(warp::multipart::form().max_length(u64::MAX))
+}
+
+/// Saves file parts of the form to temporary files and uses their path as option value.
+///
+/// Other parts are used as string option values. Temporary files are removed when the returned
+/// list is dropped.
+async fn save_multipart_form(
+    mut form: FormData,
+    part_max_size: u64,
+) -> Result<(CommandOptionsValue, Vec<NamedTempFile>), HTTPError> {
+    let mut options = CommandOptionsValue::new();
+    let mut uploaded_file_list = Vec::new();
+    while let Some(maybe_part) = form.next().await {
+        let mut part = maybe_part.map_err(|reason| HTTPError::Deserialize(reason.to_string()))?;
+        let part_too_large = |name: &str| {
+            HTTPError::API(HTTPAPIError::PayloadTooLarge {
+                message: format!(
+                    "Form part {:?} is larger than {} bytes",
+                    name, part_max_size
+                ),
+            })
+        };
+        let value = if part.filename().is_some() {
+            let save_error = |reason: std::io::Error| {
+                HTTPError::API(HTTPAPIError::SaveUploadedFile {
+                    message: reason.to_string(),
+                })
+            };
+            let uploaded_file = tokio::task::spawn_blocking(|| {
+                tempfile::Builder::new()
+                    .prefix("restcommander-upload-")
+                    .tempfile()
+            })
+            .await
+            .map_err(|reason| save_error(std::io::Error::other(reason)))?
+            .map_err(save_error)?;
+            // Chunks are written as they arrive, So the file is not kept in memory:
+            let mut file =
+                tokio::fs::File::from_std(uploaded_file.as_file().try_clone().map_err(save_error)?);
+            let mut size = 0;
+            while let Some(maybe_chunk) = part.data().await {
+                let mut chunk =
+                    maybe_chunk.map_err(|reason| HTTPError::Deserialize(reason.to_string()))?;
+                let chunk = chunk.copy_to_bytes(chunk.remaining());
+                size += chunk.len() as u64;
+                if size > part_max_size {
+                    return Err(part_too_large(part.name()));
+                };
+                file.write_all(&chunk).await.map_err(save_error)?;
+            }
+            file.flush().await.map_err(save_error)?;
+            let filename = uploaded_file.path().to_string_lossy().to_string();
+            trace!(
+                part = part.name(),
+                filename = filename.as_str(),
+                size = size,
+                "Saved uploaded file"
+            );
+            uploaded_file_list.push(uploaded_file);
+            CommandOptionValue::String(filename)
+        } else {
+            let mut data = Vec::new();
+            while let Some(maybe_chunk) = part.data().await {
+                let mut chunk =
+                    maybe_chunk.map_err(|reason| HTTPError::Deserialize(reason.to_string()))?;
+                data.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+                if data.len() as u64 > part_max_size {
+                    return Err(part_too_large(part.name()));
+                }
+            }
+            CommandOptionValue::String(String::from_utf8(data).map_err(|reason| {
+                HTTPError::Deserialize(format!("Form part {:?} {}", part.name(), reason))
+            })?)
+        };
+        options.insert(part.name().to_string(), value);
+    }
+    Ok((options, uploaded_file_list))
+}
+
+/// Returns media-type of a `Content-Type` header value without its parameters (e.g. `charset`).
+fn media_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+}
+
+/// Accepts requests whose `Content-Type` is `expected` media type with or without parameters
+/// (e.g. `application/octet-stream; charset=binary`).
+fn media_type_filter(
+    expected: &'static str,
+) -> impl Filter<Extract = (), Error = Rejection> + Copy {
+    warp::header::<String>(CONTENT_TYPE.as_str())
+        .and_then(move |content_type: String| async move {
+            if media_type(&content_type) == expected {
+                Ok(())
+            } else {
+                Err(warp::reject())
+            }
+        })
+        .untuple_one()
+}
+
+/// Everything from an `/api/run/...` request that is needed to run the command.
+struct CommandRequest {
+    input: CommandInput,
+    media_types: CommandRequestMediaTypes,
+    maybe_body_stream: Option<BodyStream>,
+    maybe_client_certificate_name: Option<String>,
+    /// Username of the session token that the request is sent with.
+    maybe_username: Option<String>,
+    /// Expression of `select` query-string parameter that selects parts of JSON result.
+    maybe_select: Option<String>,
+    /// Encoding of `encoding` query-string parameter for outputs that are sent as they are.
+    maybe_encoding: Option<String>,
+    /// Whether the resolved arguments and environment variables of the command are included in
+    /// response (via `debug` query-string parameter).
+    debug: bool,
+}
+
+/// Everything from an `/api/map/...` request that is needed to run the command once per input.
+struct CommandMapRequest {
+    input_list: Vec<CommandInput>,
+    statistics: bool,
+    media_types: CommandRequestMediaTypes,
+    maybe_client_certificate_name: Option<String>,
+    maybe_username: Option<String>,
+}
+
+/// Request `Content-Type` and `Accept` media types that are checked against command's configured
+/// `request_content_types` and `response_content_types`.
+#[derive(Clone, Debug)]
+struct CommandRequestMediaTypes {
+    content_type: String,
+    accept_list: Vec<String>,
+}
+
+impl CommandRequestMediaTypes {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(media_type)
+            .unwrap_or_else(|| "application/json".to_string());
+        let accept_list = headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            // Skip media types that client does not accept (`q=0`):
+            .filter(|accept| {
+                !accept.split(';').skip(1).any(|parameter| {
+                    parameter
+                        .trim()
+                        .strip_prefix("q=")
+                        .and_then(|quality| quality.parse::<f32>().ok())
+                        == Some(0.0)
+                })
+            })
+            .map(media_type)
+            .filter(|accept| !accept.is_empty())
+            .collect();
+        Self {
+            content_type,
+            accept_list,
+        }
+    }
+
+    fn check(&self, command: &Command) -> Result<(), HTTPAPIError> {
+        // Commands that declare no content-types accept and produce anything:
+        let (request_content_types, response_content_types) = match command.info {
+            Some(ref info) => (
+                info.request_content_types.clone(),
+                info.response_content_types.clone(),
+            ),
+            None => (None, None),
+        };
+        if let Some(request_content_types) = request_content_types {
+            if !request_content_types
+                .iter()
+                .any(|pattern| media_type_matches(pattern, &self.content_type))
+            {
+                return Err(HTTPAPIError::UnsupportedMediaType {
+                    message: format!(
+                        "Command does not accept content-type {:?} (accepted content-types: {})",
+                        self.content_type,
+                        request_content_types.join(", ")
+                    ),
+                });
+            };
+        };
+        if let Some(response_content_types) = response_content_types {
+            if !self.accept_list.is_empty()
+                && !self.accept_list.iter().any(|accept| {
+                    response_content_types.iter().any(|content_type| {
+                        media_type_matches(accept, content_type)
+                            || media_type_matches(content_type, accept)
+                    })
+                })
+            {
+                return Err(HTTPAPIError::NotAcceptable {
+                    message: format!(
+                        "Command does not produce any of accepted media types {:?} (produced content-types: {})",
+                        self.accept_list,
+                        response_content_types.join(", ")
+                    ),
+                });
+            };
+        };
+        Ok(())
+    }
+}
+
+/// Checks if `media_type` matches `pattern` which can be `*/*` or in form of `type/*`.
+fn media_type_matches(pattern: &str, media_type: &str) -> bool {
+    let pattern = self::media_type(pattern);
+    let media_type = self::media_type(media_type);
+    if pattern == "*/*" || pattern == media_type {
+        return true;
+    };
+    match (pattern.strip_suffix("/*"), media_type.split_once('/')) {
+        (Some(pattern_type), Some((media_type_type, _))) => pattern_type == media_type_type,
+        _ => false,
+    }
+}
+
+/// Decodes input option objects of a `/api/map/...` request body and rejects requests with more
+/// than configured `commands.map_max_inputs` inputs.
+fn map_inputs_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+) -> impl Filter<Extract = (Vec<CommandOptionsValue>,), Error = Rejection> + Clone {
+    let cfg2 = cfg.clone();
+    limited_body_filter(cfg).and_then(move |body: Bytes| {
+        let max_inputs = cfg2.load().config_value.commands.map_max_inputs;
         async move {
-            let cfg_value = cfg.read().unwrap().config_value.clone();
+            let input_list = serde_json::from_slice::<Vec<CommandOptionsValue>>(&body)
+                .map_err(|error| warp::reject::custom(HTTPError::Deserialize(error.to_string())))?;
+            if input_list.len() > max_inputs {
+                return Err(warp::reject::custom(HTTPError::API(
+                    HTTPAPIError::PayloadTooLarge {
+                        message: format!("Request has more than {} inputs", max_inputs),
+                    },
+                )));
+            };
+            Ok(input_list)
+        }
+    })
+}
+
+/// Reads the whole request body and rejects it if it is larger than configured
+/// `commands.upload_max_size` (including chunked bodies without `Content-Length`).
+fn limited_body_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+) -> impl Filter<Extract = (Bytes,), Error = Rejection> + Clone {
+    warp::header::optional::<u64>(CONTENT_LENGTH.as_str())
+        .and(warp::body::stream())
+        .and_then(move |maybe_content_length: Option<u64>, body_stream| {
+            let max_size = cfg.load().config_value.commands.upload_max_size;
+            async move {
+                let payload_too_large = || {
+                    warp::reject::custom(HTTPError::API(HTTPAPIError::PayloadTooLarge {
+                        message: format!("Request body is larger than {} bytes", max_size),
+                    }))
+                };
+                if maybe_content_length.unwrap_or_default() > max_size {
+                    return Err(payload_too_large());
+                };
+                let mut body_stream = make_body_stream(body_stream);
+                let mut body = Vec::new();
+                while let Some(chunk) = body_stream.try_next().await.map_err(|reason| {
+                    warp::reject::custom(HTTPError::Deserialize(reason.to_string()))
+                })? {
+                    if (body.len() + chunk.len()) as u64 > max_size {
+                        return Err(payload_too_large());
+                    };
+                    body.extend_from_slice(&chunk);
+                }
+                Ok(Bytes::from(body))
+            }
+        })
+}
+
+fn make_body_stream<S, B>(body_stream: S) -> BodyStream
+where
+    S: Stream<Item = Result<B, warp::Error>> + Send + 'static,
+    B: Buf,
+{
+    body_stream
+        .map_ok(|mut chunk| chunk.copy_to_bytes(chunk.remaining()))
+        .boxed()
+}
+
+fn api_get_command_state_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+    commands: Arc<ArcSwap<Command>>,
+    report_state: Arc<AsyncRwLock<ReportState>>,
+) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
+    get_or_head()
+        .map(move || (cfg.clone(), commands.clone(), report_state.clone()))
+        .and(warp::path::tail())
+        .and(remote_address_filter())
+        .and(client_certificate_name_filter())
+        .and_then(
+            |state: (
+                Arc<ArcSwap<Cfg>>,
+                Arc<ArcSwap<Command>>,
+                Arc<AsyncRwLock<ReportState>>,
+            ),
+             tail: Tail,
+             addr: Option<SocketAddr>,
+             maybe_client_certificate_name: Option<String>| async move {
+                let addr = addr.unwrap();
+                match maybe_get_command_state(
+                    state.0,
+                    state.1,
+                    tail.as_str().to_string(),
+                    maybe_client_certificate_name,
+                    state.2,
+                    addr.to_string(),
+                )
+                .await
+                {
+                    Err(reason) => Err(warp::reject::custom(HTTPError::API(reason))),
+                    Ok(response) => Ok(response),
+                }
+            },
+        )
+}
+
+fn api_reload_commands_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+    commands: Arc<ArcSwap<Command>>,
+    reload_lock: ReloadLock,
+) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
+    warp::get()
+        .and(warp::path("commands"))
+        .and(warp::query::query::<HashMap<String, String>>())
+        .then(move |query: HashMap<String, String>| {
+            let cfg = cfg.clone();
+            let commands = commands.clone();
+            let reload_lock = reload_lock.clone();
+            async move {
+                let background = query
+                    .get("background")
+                    .map(|value| value == "true" || value == "1")
+                    .unwrap_or_default();
+                if !background {
+                    return make_api_response(
+                        reload_commands(cfg, commands, reload_lock)
+                            .await
+                            .map(|_| serde_json::Value::Null)
+                            .map_err(HTTPError::API),
+                    );
+                };
+                tokio::spawn(async move {
+                    match reload_commands(cfg, commands, reload_lock).await {
+                        Ok(_) => info!("Reloaded commands in background."),
+                        Err(reason) => error!(
+                            error = reason.to_string().as_str(),
+                            "Could not reload commands in background."
+                        ),
+                    }
+                });
+                make_api_response_from_body(
+                    json!({"ok": true, "result": null}),
+                    None,
+                    StatusCode::ACCEPTED,
+                )
+            }
+        })
+}
+
+/// Scans commands in a blocking task and stores the new tree only if it is loaded successfully.
+///
+/// Requests are served from the current tree until then.
+async fn reload_commands(
+    cfg: Arc<ArcSwap<Cfg>>,
+    commands: Arc<ArcSwap<Command>>,
+    reload_lock: ReloadLock,
+) -> Result<(), HTTPAPIError> {
+    let _reload_guard = reload_lock.lock().await;
+    let old_commands = commands.load_full();
+    check_commands_root_directory(&old_commands)?;
+    let cfg_instance = cfg.load().config_value.commands.clone();
+    let new_commands = tokio::task::spawn_blocking(move || {
+        let mut new_commands = old_commands.deref().clone();
+        new_commands.environment = cfg_instance.environment;
+        new_commands
+            .reload()
+            .and_then(|_| cmd::check_aliases(&cfg_instance.aliases, &new_commands))
+            .map(|_| new_commands)
+            .map_err(|error| error.to_string())
+    })
+    .await
+    .map_err(|error| error.to_string())
+    .and_then(|result| result)
+    .map_err(|message| HTTPAPIError::ReloadCommands { message })?;
+    commands.store(Arc::new(new_commands));
+    Ok(())
+}
+
+fn api_reload_config_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+    commands: Arc<ArcSwap<Command>>,
+    http_notify_channel: tokio::sync::mpsc::Sender<()>,
+    reload_lock: ReloadLock,
+) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
+    warp::get().and(warp::path("config")).then(move || {
+        let cfg = cfg.clone();
+        let commands = commands.clone();
+        let http_notify_channel = http_notify_channel.clone();
+        let reload_lock = reload_lock.clone();
+        async move {
+            // Configuration is changed by a password change or another reload after we load it:
+            let _reload_guard = reload_lock.lock().await;
+            let mut new_cfg = cfg.load().deref().deref().clone();
+            if let Err(reason) = new_cfg
+                .try_reload()
+                .map_err(|reason| reason.to_string())
+                .and_then(|_| {
+                    cmd::check_aliases(&new_cfg.config_value.commands.aliases, &commands.load())
+                        .map_err(|reason| reason.to_string())
+                })
+            {
+                return make_api_response(Err(HTTPError::API(HTTPAPIError::ReloadConfig {
+                    message: reason,
+                })));
+            };
+            cfg.store(Arc::new(new_cfg));
+            http_notify_channel.send(()).await.unwrap();
+            make_api_response_ok()
+        }
+    })
+}
+
+/// Reloads configuration and then commands (from maybe new `commands.root_directory`).
+///
+/// Nothing is stored unless both of them are reloaded successfully.
+fn api_reload_all_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+    commands: Arc<ArcSwap<Command>>,
+    http_notify_channel: tokio::sync::mpsc::Sender<()>,
+    reload_lock: ReloadLock,
+) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
+    warp::get().and(warp::path("all")).then(move || {
+        let cfg = cfg.clone();
+        let commands = commands.clone();
+        let http_notify_channel = http_notify_channel.clone();
+        let reload_lock = reload_lock.clone();
+        async move {
+            let _reload_guard = reload_lock.lock().await;
+            let old_cfg = cfg.load();
+            let mut new_cfg = old_cfg.deref().deref().clone();
+            if let Err(reason) = new_cfg.try_reload() {
+                return make_api_response(Err(HTTPError::API(HTTPAPIError::ReloadConfig {
+                    message: reason.to_string(),
+                })));
+            };
+            let root_directory = new_cfg.config_value.commands.root_directory.clone();
+            let http_base_path = PathBuf::from(new_cfg.config_value.server.http_base_path.clone())
+                .join(PathBuf::from(API_RUN_BASE_PATH).strip_prefix("/").unwrap());
+            let cfg_commands = new_cfg.config_value.commands.clone();
+            // Commands are scanned in a blocking task, So large trees do not block other requests:
+            let new_commands = match tokio::task::spawn_blocking(move || {
+                Command::new(
+                    &cfg_commands.root_directory,
+                    &http_base_path,
+                    cfg_commands.environment.clone(),
+                )
+                .and_then(|new_commands| {
+                    cmd::check_aliases(&cfg_commands.aliases, &new_commands).map(|_| new_commands)
+                })
+                .map_err(|reason| reason.to_string())
+            })
+            .await
+            .map_err(|reason| reason.to_string())
+            .and_then(|result| result)
+            {
+                Ok(new_commands) => new_commands,
+                Err(message) => {
+                    return make_api_response(Err(HTTPError::API(HTTPAPIError::ReloadCommands {
+                        message,
+                    })))
+                }
+            };
+            let root_directory_changed =
+                root_directory != old_cfg.config_value.commands.root_directory;
+            cfg.store(Arc::new(new_cfg));
+            commands.store(Arc::new(new_commands));
+            http_notify_channel.send(()).await.unwrap();
+            make_api_response_ok_with_result(json!({
+                "config": {
+                    "filename": cfg.load().filename,
+                    "override_filename_list": cfg.load().override_filename_list,
+                },
+                "commands": {
+                    "root_directory": root_directory,
+                    "root_directory_changed": root_directory_changed,
+                }
+            }))
+        }
+    })
+}
+
+fn api_report_filter(
+    _cfg: Arc<ArcSwap<Cfg>>,
+    report_state: Arc<AsyncRwLock<ReportState>>,
+) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
+    warp::post()
+        .and(warp::path("report"))
+        .map(move || report_state.clone())
+        .and(warp::body::json::<Report>())
+        .then(
+            |report_state: Arc<AsyncRwLock<ReportState>>, report: Report| async move {
+                let report_state_locked = report_state.read().await;
+                let search_result = match crate::report::search(
+                    report.from,
+                    report.before_time,
+                    report.after_time,
+                    report.context,
+                    report.limit,
+                    report_state_locked.clone(),
+                )
+                .await
+                {
+                    Ok(report_list) => {
+                        if report_list.is_empty() {
+                            make_api_response(Err(HTTPError::API(HTTPAPIError::ReportNotFound)))
+                        } else {
+                            let report_list = report_list
+                                .into_iter()
+                                .map(|report| serde_json::to_value(&report).unwrap())
+                                .collect();
+                            make_api_response_ok_with_result(report_list)
+                        }
+                    }
+                    Err(error) => make_api_response(Err(HTTPError::API(match error {
+                        ReportError::NotAvailable => HTTPAPIError::ReportNotAvailable {
+                            message: error.to_string(),
+                        },
+                        _ => HTTPAPIError::Report {
+                            message: error.to_string(),
+                        },
+                    }))),
+                };
+                drop(report_state_locked);
+                search_result
+            },
+        )
+}
+
+fn api_set_password_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+    reload_lock: ReloadLock,
+) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
+    warp::post()
+        .and(warp::path("setPassword"))
+        .map(move || (cfg.clone(), reload_lock.clone()))
+        .and(warp::body::json())
+        .then(
+            move |(cfg, reload_lock): (Arc<ArcSwap<Cfg>>, ReloadLock), password: SetPassword| async move {
+                // A reload that started before does not store the old password over it:
+                let _reload_guard = reload_lock.lock().await;
+                try_set_password(cfg, password)
+                    .map(|_| make_api_response_ok())
+                    .or_else::<Response<String>, _>(|error| {
+                        Ok(make_api_response(Err(HTTPError::API(error))))
+                    })
+                    .unwrap()
+            },
+        )
+}
+
+#[derive(Debug, Deserialize)]
+struct SetMaintenance {
+    enabled: bool,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// Returns number of responses and their body bytes for each command.
+fn api_stats_filter(
+    traffic: TrafficState,
+) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
+    get_or_head()
+        .and(warp::path("stats"))
+        .and(warp::path::end())
+        .map(move || {
+            let commands = traffic
+                .read()
+                .iter()
+                .map(|(http_path, command_traffic)| {
+                    (
+                        http_path.clone(),
+                        json!({
+                            "responses": command_traffic.responses,
+                            "response_bytes": command_traffic.response_bytes,
+                        }),
+                    )
+                })
+                .collect::<serde_json::Map<_, _>>();
+            make_api_response_ok_with_result(json!({ "commands": commands }))
+        })
+}
+
+/// Default number of lines of `/api/logs` responses.
+const DEFAULT_LOG_LINES: usize = 100;
+
+const MAX_LOG_LINES: usize = 10000;
+
+/// Maximum bytes that are read from the end of log file for `/api/logs` responses (and each
+/// check of followed logs), So huge log files are not loaded.
+const MAX_LOG_READ_SIZE: u64 = 4 * 1024 * 1024;
+
+const LOG_FOLLOW_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
+#[derive(Debug, Deserialize)]
+struct LogsQuery {
+    lines: Option<usize>,
+    #[serde(default)]
+    follow: bool,
+}
+
+/// Returns the last lines of current log file or streams them and new lines via server-sent
+/// events if `follow` is `true`.
+fn api_logs_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    warp::get()
+        .and(warp::path("logs"))
+        .and(warp::path::end())
+        .and(warp::query::query::<LogsQuery>())
+        .and_then(move |query: LogsQuery| {
+            let cfg = cfg.clone();
+            async move {
+                let logging_cfg = cfg.load().config_value.logging.clone();
+                let line_count = query.lines.unwrap_or(DEFAULT_LOG_LINES).min(MAX_LOG_LINES);
+                let (filename, line_list, offset) = read_last_log_lines(&logging_cfg, line_count)
+                    .await
+                    .map_err(|reason| warp::reject::custom(HTTPError::API(reason)))?;
+                if !query.follow {
+                    return Ok::<_, Rejection>(warp::Reply::into_response(
+                        make_api_response_ok_with_result(json!({
+                            "filename": filename,
+                            "lines": line_list,
+                        })),
+                    ));
+                };
+                let new_line_stream = futures_util::stream::unfold(
+                    (cfg, filename, offset, String::new()),
+                    |(cfg, mut filename, mut offset, mut partial_line)| async move {
+                        loop {
+                            tokio::time::sleep(LOG_FOLLOW_INTERVAL).await;
+                            // Daily log files are rotated:
+                            if let Some(current_filename) =
+                                logging::current_log_file(&cfg.load().config_value.logging)
+                            {
+                                if current_filename != filename {
+                                    filename = current_filename;
+                                    offset = 0;
+                                    partial_line.clear();
+                                };
+                            };
+                            let line_list =
+                                match read_new_log_lines(&filename, &mut offset, &mut partial_line)
+                                    .await
+                                {
+                                    Ok(line_list) => line_list,
+                                    Err(reason) => {
+                                        warn!(
+                                            filename = ?filename,
+                                            error = reason.to_string().as_str(),
+                                            "Could not read followed log file"
+                                        );
+                                        return None;
+                                    }
+                                };
+                            if !line_list.is_empty() {
+                                return Some((line_list, (cfg, filename, offset, partial_line)));
+                            };
+                        }
+                    },
+                )
+                .flat_map(futures_util::stream::iter);
+                let event_stream = futures_util::stream::iter(line_list)
+                    .chain(new_line_stream)
+                    .map(|line| Ok::<_, Infallible>(warp::sse::Event::default().data(line)));
+                Ok(warp::Reply::into_response(warp::sse::reply(
+                    warp::sse::keep_alive().stream(event_stream),
+                )))
+            }
+        })
+}
+
+async fn read_last_log_lines(
+    logging_cfg: &CfgLogging,
+    line_count: usize,
+) -> Result<(PathBuf, Vec<String>, u64), HTTPAPIError> {
+    if !logging_cfg.api_logs {
+        return Err(HTTPAPIError::LogsNotAllowed);
+    };
+    let filename = logging::current_log_file(logging_cfg).ok_or(HTTPAPIError::LogFileNotFound)?;
+    let make_error = |reason: std::io::Error| HTTPAPIError::ReadLogs {
+        filename: filename.clone(),
+        message: reason.to_string(),
+    };
+    let mut file = tokio::fs::File::open(&filename).await.map_err(make_error)?;
+    let size = file.metadata().await.map_err(make_error)?.len();
+    let start = size.saturating_sub(MAX_LOG_READ_SIZE);
+    file.seek(std::io::SeekFrom::Start(start))
+        .await
+        .map_err(make_error)?;
+    let mut buffer = Vec::new();
+    file.take(size - start)
+        .read_to_end(&mut buffer)
+        .await
+        .map_err(make_error)?;
+    let text = String::from_utf8_lossy(&buffer);
+    let mut line_list: Vec<String> = text.lines().map(|line| line.to_string()).collect();
+    // The first line is probably cut:
+    if start > 0 && !line_list.is_empty() {
+        line_list.remove(0);
+    };
+    let line_list = line_list.split_off(line_list.len().saturating_sub(line_count));
+    Ok((filename, line_list, start + buffer.len() as u64))
+}
+
+/// Reads complete lines that are written to log file after `offset`.
+async fn read_new_log_lines(
+    filename: &Path,
+    offset: &mut u64,
+    partial_line: &mut String,
+) -> std::io::Result<Vec<String>> {
+    let mut file = tokio::fs::File::open(filename).await?;
+    let size = file.metadata().await?.len();
+    // It is truncated:
+    if size < *offset {
+        *offset = 0;
+        partial_line.clear();
+    };
+    file.seek(std::io::SeekFrom::Start(*offset)).await?;
+    let mut buffer = Vec::new();
+    file.take(MAX_LOG_READ_SIZE)
+        .read_to_end(&mut buffer)
+        .await?;
+    *offset += buffer.len() as u64;
+    partial_line.push_str(&String::from_utf8_lossy(&buffer));
+    let mut line_list: Vec<String> = partial_line
+        .split('\n')
+        .map(|line| line.to_string())
+        .collect();
+    // The last one is empty or is not complete yet:
+    *partial_line = line_list.pop().unwrap_or_default();
+    Ok(line_list)
+}
+
+/// Reads (`GET`) or toggles (`POST`) maintenance mode.
+fn api_maintenance_filter(
+    maintenance: MaintenanceState,
+) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
+    let make_response = |maintenance: &MaintenanceState| {
+        let maybe_reason = maintenance.read().clone();
+        make_api_response_ok_with_result(json!({
+            "enabled": maybe_reason.is_some(),
+            "reason": maybe_reason,
+        }))
+    };
+    let get_maintenance = maintenance.clone();
+    warp::path("maintenance").and(warp::path::end()).and(
+        get_or_head()
+            .map(move || make_response(&get_maintenance))
+            .or(warp::post()
+                .and(warp::body::json())
+                .map(move |set_maintenance: SetMaintenance| {
+                    if set_maintenance.enabled {
+                        let reason = set_maintenance
+                            .reason
+                            .filter(|reason| !reason.is_empty())
+                            .unwrap_or_else(|| DEFAULT_MAINTENANCE_REASON.to_string());
+                        info!(reason = reason.as_str(), "Enabled maintenance mode");
+                        *maintenance.write() = Some(reason);
+                    } else {
+                        info!("Disabled maintenance mode");
+                        *maintenance.write() = None;
+                    };
+                    make_response(&maintenance)
+                }))
+            .unify(),
+    )
+}
+
+/// Static files, the web dashboard and redirects of `/` and `/favicon.ico` to them.
+#[cfg(feature = "www")]
+fn www_filter(cfg: Arc<ArcSwap<Cfg>>) -> BoxedFilter<(Response<Body>,)> {
+    warp::path("static")
+        .and(
+            static_external_filter(cfg.clone())
+                .or(static_internal_filter(cfg.clone()))
+                .unify(),
+        )
+        .or(redirect_root_to_index_html_filter(cfg.clone()))
+        .or(redirect_favicon_filter(cfg))
+        .map(warp::Reply::into_response)
+        .boxed()
+}
+
+#[cfg(feature = "www")]
+fn redirect_root_to_index_html_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
+    get_or_head().and(warp::path::end()).then(move || {
+        let cfg = cfg.clone();
+        async move {
+            let cfg_value = cfg.load().config_value.clone();
             if cfg_value.www.enabled {
                 Response::builder()
                     .status(StatusCode::MOVED_PERMANENTLY)
@@ -862,42 +2991,226 @@ fn redirect_root_to_index_html_filter(
     })
 }
 
-fn static_external_filter(
-    cfg: Arc<RwLock<Cfg>>,
-) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
-    let static_directory = cfg
-        .clone()
-        .read()
-        .unwrap()
-        .config_value
-        .www
-        .static_directory
-        .clone();
-    warp::get()
+#[cfg(feature = "www")]
+fn redirect_favicon_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
+    get_or_head()
+        .and(warp::path("favicon.ico"))
+        .and(warp::path::end())
         .and_then(move || {
             let cfg = cfg.clone();
             async move {
-                let www_cfg = cfg.read().unwrap().config_value.www.clone();
-                if www_cfg.enabled && www_cfg.static_directory.is_dir() {
-                    Ok(())
+                let cfg_value = cfg.load().config_value.clone();
+                if cfg_value.www.enabled {
+                    Ok(Response::builder()
+                        .status(StatusCode::MOVED_PERMANENTLY)
+                        .header(
+                            LOCATION,
+                            format!("{}static/favicon.ico", cfg_value.server.http_base_path),
+                        )
+                        .body(String::new())
+                        .unwrap())
                 } else {
                     Err(warp::reject::not_found())
                 }
             }
         })
-        .untuple_one()
-        .and(warp::fs::dir(static_directory))
-        .map(|file: File| file.into_response())
 }
 
+#[cfg(feature = "www")]
+fn static_external_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    get_or_head()
+        .and(warp::path::tail())
+        .and(warp::header::headers_cloned())
+        .and_then(move |tail_path: Tail, headers: HeaderMap| {
+            let cfg = cfg.clone();
+            async move {
+                // Read the directory on each request, so reloading configuration takes effect:
+                let www_cfg = cfg.load().config_value.www.clone();
+                if !www_cfg.enabled || !www_cfg.static_directory.is_dir() {
+                    return Err(warp::reject::not_found());
+                };
+                let mut filename = static_filename(&www_cfg.static_directory, tail_path.as_str())
+                    .ok_or_else(warp::reject::not_found)?;
+                if filename.is_dir() {
+                    let index_filename = filename.join("index.html");
+                    if !index_filename.is_file() && www_cfg.autoindex {
+                        let http_base_path = cfg.load().config_value.server.http_base_path.clone();
+                        return make_directory_listing(
+                            &filename,
+                            format!("{}static/{}", http_base_path, tail_path.as_str()),
+                        )
+                        .await
+                        .map_err(|_| warp::reject::not_found());
+                    };
+                    filename = index_filename;
+                };
+                // Files are served by `warp::fs::dir` for its `Last-Modified`, conditional and
+                // range requests support and streamed bodies. It only accepts `GET` requests and
+                // hyper drops the body of `HEAD` responses itself:
+                let mut request = Request::builder()
+                    .uri(format!("/{}", tail_path.as_str()))
+                    .body(Body::empty())
+                    .map_err(|_| warp::reject::not_found())?;
+                *request.headers_mut() = headers;
+                // warp does not allow calling a filter inside another filter's task:
+                let static_directory = www_cfg.static_directory.clone();
+                let mut response = tokio::spawn(async move {
+                    warp::service(warp::fs::dir(static_directory))
+                        .call(request)
+                        .await
+                })
+                .await
+                .map_err(|_| warp::reject::not_found())?
+                .unwrap();
+                if response.status() == StatusCode::NOT_FOUND {
+                    return Err(warp::reject::not_found());
+                };
+                if let Some(mime_type) = static_mime_type(&www_cfg.mime_types, &filename, None)
+                    .and_then(|mime_type| HeaderValue::from_str(&mime_type).ok())
+                {
+                    response.headers_mut().insert(CONTENT_TYPE, mime_type);
+                };
+                Ok(response)
+            }
+        })
+}
+
+/// Returns configured `www.mime_types` content-type for `filename` extension or `default`.
+#[cfg(feature = "www")]
+fn static_mime_type(
+    mime_types: &HashMap<String, String>,
+    filename: &Path,
+    default: Option<String>,
+) -> Option<String> {
+    filename
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(|extension| {
+            mime_types
+                .iter()
+                .find_map(|(configured_extension, mime_type)| {
+                    if configured_extension
+                        .trim_start_matches('.')
+                        .eq_ignore_ascii_case(extension)
+                    {
+                        Some(mime_type.clone())
+                    } else {
+                        None
+                    }
+                })
+        })
+        .or(default)
+}
+
+/// Characters that are percent-encoded in directory listing links.
+#[cfg(feature = "www")]
+const DIRECTORY_LISTING_LINK_ENCODE_SET: &percent_encoding::AsciiSet = &percent_encoding::CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+#[cfg(feature = "www")]
+async fn make_directory_listing(
+    directory: &Path,
+    http_path: String,
+) -> Result<Response<Body>, std::io::Error> {
+    let http_path = if http_path.ends_with('/') {
+        http_path
+    } else {
+        format!("{}/", http_path)
+    };
+    let mut entry_list = Vec::new();
+    let mut directory_entries = tokio::fs::read_dir(directory).await?;
+    while let Some(entry) = directory_entries.next_entry().await? {
+        let mut name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type().await?.is_dir() {
+            name.push('/');
+        };
+        entry_list.push(name);
+    }
+    entry_list.sort();
+    let title = escape_html(
+        percent_encoding::percent_decode_str(&http_path)
+            .decode_utf8_lossy()
+            .as_ref(),
+    );
+    let body = entry_list.iter().fold(
+        format!(
+            "<html><head><title>Index of {title}</title></head><body><h1>Index of {title}</h1><hr><ul><li><a href=\"{}..\">../</a></li>",
+            http_path,
+            title = title
+        ),
+        |mut body, name| {
+            body.push_str(
+                format!(
+                    "<li><a href=\"{}{}\">{}</a></li>",
+                    http_path,
+                    percent_encoding::utf8_percent_encode(name, DIRECTORY_LISTING_LINK_ENCODE_SET),
+                    escape_html(name)
+                )
+                .as_str(),
+            );
+            body
+        },
+    ) + "</ul><hr></body></html>";
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(Body::from(body))
+        .unwrap())
+}
+
+#[cfg(feature = "www")]
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Joins percent-decoded `tail_path` to `directory` and rejects paths that may point outside it.
+#[cfg(feature = "www")]
+fn static_filename(directory: &Path, tail_path: &str) -> Option<PathBuf> {
+    let tail_path = percent_encoding::percent_decode_str(tail_path)
+        .decode_utf8()
+        .ok()?;
+    let mut filename = directory.to_path_buf();
+    for segment in tail_path.split('/') {
+        if segment.starts_with("..") || segment.contains('\\') {
+            warn!(path = tail_path.as_ref(), "Rejected static file path.");
+            return None;
+        };
+        filename.push(segment);
+    }
+    Some(filename)
+}
+
+/// Embedded files only change with a new build, so browsers may cache them for a while.
+#[cfg(feature = "www")]
+const STATIC_INTERNAL_CACHE_CONTROL: &str = "public, max-age=3600";
+
+#[cfg(feature = "www")]
 fn static_internal_filter(
-    cfg: Arc<RwLock<Cfg>>,
+    cfg: Arc<ArcSwap<Cfg>>,
 ) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
-    warp::get()
+    let cfg2 = cfg.clone();
+    get_or_head()
         .and_then(move || {
             let cfg = cfg.clone();
             async move {
-                if cfg.read().unwrap().config_value.www.enabled {
+                if cfg.load().config_value.www.enabled {
                     Ok(())
                 } else {
                     Err(warp::reject::not_found())
@@ -906,12 +3219,19 @@ fn static_internal_filter(
         })
         .untuple_one()
         .and(warp::path::tail())
-        .and_then(|tail_path: Tail| async move {
+        .and(warp::any().map(move || cfg2.clone()))
+        .and_then(|tail_path: Tail, cfg: Arc<ArcSwap<Cfg>>| async move {
             if let Some((bytes, maybe_mime_type)) =
                 www::handle_static(tail_path.as_str().to_string())
             {
-                let mut response = Response::builder().status(StatusCode::OK);
-                if let Some(mime_type) = maybe_mime_type {
+                let mut response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CACHE_CONTROL, STATIC_INTERNAL_CACHE_CONTROL);
+                if let Some(mime_type) = static_mime_type(
+                    &cfg.load().config_value.www.mime_types,
+                    Path::new(tail_path.as_str()),
+                    maybe_mime_type,
+                ) {
                     response = response.header(warp::http::header::CONTENT_TYPE, mime_type);
                 }
                 Ok(response.body(Body::from(bytes)).unwrap())
@@ -926,7 +3246,7 @@ fn api_captcha_filter(
 ) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
     warp::get().and(warp::path("captcha")).map(move || {
         if let Some(captcha) = maybe_captcha.clone() {
-            let (id, _, png_image) = captcha.write().unwrap().generate(true);
+            let (id, _, png_image) = captcha.write().generate(true);
             make_api_response_ok_with_result(serde_json::json!({"id": id, "image": png_image}))
         } else {
             make_api_response(Err(HTTPError::Authentication(
@@ -935,207 +3255,1188 @@ fn api_captcha_filter(
                 ),
             )))
         }
-    })
+    })
+}
+
+fn api_configuration_filter(
+    cfg: Arc<ArcSwap<Cfg>>,
+) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
+    warp::path("configuration").map(move || {
+        make_api_response_ok_with_result(serde_json::Value::Object(
+            cfg.load()
+                .config_value
+                .www
+                .configuration
+                .clone()
+                .into_iter()
+                .fold(serde_json::Map::new(), |mut acc, item| {
+                    acc.insert(item.0, serde_json::Value::String(item.1));
+                    acc
+                }),
+        ))
+    })
+}
+
+fn check_ip_address(
+    cfg: Arc<ArcSwap<Cfg>>,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    remote_address_filter()
+        .and_then(move |maybe_address: Option<SocketAddr>| {
+            let cfg = cfg.clone();
+            async move {
+                let ip_whitelist = cfg.load().config_value.server.ip_whitelist.clone();
+                if ip_whitelist.is_empty() {
+                    return Ok(());
+                }
+                let ip = maybe_address
+                    .map(|address| address.ip().to_string())
+                    .unwrap_or_default();
+                for wildcard_ip in ip_whitelist {
+                    if WildMatch::new(wildcard_ip.as_str()).matches(ip.as_str()) {
+                        return Ok(());
+                    }
+                }
+                Err(warp::reject::custom(HTTPError::Authentication(
+                    HTTPAuthenticationError::InvalidIP(ip),
+                )))
+            }
+        })
+        .untuple_one()
+}
+
+/// Authenticates a client with its basic authentication credentials (and CAPTCHA if configured)
+/// and returns its username and API path prefixes that its new token is limited to.
+async fn authentication_with_basic(
+    cfg: Arc<ArcSwap<Cfg>>,
+    maybe_captcha: Option<Arc<RwLock<captcha::Captcha>>>,
+    authorization_value: String,
+    form: HashMap<String, String>,
+) -> Result<(Option<String>, Option<Vec<String>>), HTTPAuthenticationError> {
+    let cfg_value = cfg.load().config_value.clone();
+    let server_cfg = cfg_value.server.clone();
+    if server_cfg.auth_command.is_none() {
+        if server_cfg.password_sha512.is_empty() && server_cfg.username.is_empty() {
+            return Ok((None, None));
+        };
+        if server_cfg.password_sha512.is_empty() || server_cfg.username.is_empty() {
+            return Err(HTTPAuthenticationError::UsernameOrPasswordIsNotSet);
+        };
+    };
+    let (username, password) = decode_basic_authentication(authorization_value)?;
+    let maybe_scopes = if let Some(auth_command) = server_cfg.auth_command.clone() {
+        run_auth_command(auth_command, username.clone(), password, &cfg_value).await?
+    } else {
+        if username != server_cfg.username {
+            debug!(
+                username = username.as_str(),
+                "Client authenticated with unknown username."
+            );
+            return Err(HTTPAuthenticationError::InvalidUsernameOrPassword);
+        };
+        let password_sha512 = utils::to_sha512(password);
+        trace!(
+            username = username.as_str(),
+            password_sha512 = password_sha512.as_str(),
+            "New client provided credentials.",
+        );
+        if !utils::constant_time_eq(&server_cfg.password_sha512, &password_sha512) {
+            return Err(HTTPAuthenticationError::InvalidUsernameOrPassword);
+        };
+        None
+    };
+    if maybe_captcha.is_none() {
+        return Ok((Some(username), maybe_scopes));
+    };
+    if form.len() != 1 {
+        return Err(HTTPAuthenticationError::InvalidCaptchaForm {});
+    };
+    let (key, value) = form
+        .into_iter()
+        .fold(None, |_, key_value| Some(key_value.clone()))
+        .unwrap()
+        .clone();
+    if maybe_captcha.unwrap().write().compare_and_update(
+        key.to_string(),
+        value,
+        server_cfg.captcha_case_sensitive,
+    ) {
+        Ok((Some(username), maybe_scopes))
+    } else {
+        Err(HTTPAuthenticationError::InvalidCaptcha {})
+    }
+}
+
+/// Decodes username and password of a basic `Authorization` header value.
+fn decode_basic_authentication(
+    authorization_value: String,
+) -> Result<(String, String), HTTPAuthenticationError> {
+    match authorization_value
+        .as_str()
+        .splitn(2, ' ')
+        .collect::<Vec<&str>>()[..]
+    {
+        ["Basic", username_password] => {
+            let decoded_username_password =
+                base64::decode(username_password).map_err(|reason| {
+                    HTTPAuthenticationError::Base64Decode {
+                        data: username_password.to_string(),
+                        source: reason,
+                    }
+                })?;
+            match String::from_utf8(decoded_username_password)
+                .unwrap()
+                .splitn(2, ':')
+                .collect::<Vec<&str>>()[..]
+            {
+                [username, password] => Ok((username.to_string(), password.to_string())),
+                [value] => Err(HTTPAuthenticationError::UsernameOrPasswordIsNotFound {
+                    data: value.to_string(),
+                }),
+                _ => Err(HTTPAuthenticationError::UsernameOrPasswordIsNotFound {
+                    data: "".to_string(),
+                }),
+            }
+        }
+        [unknown_method, _] => Err(HTTPAuthenticationError::UnknownMethod {
+            method: unknown_method.to_string(),
+        }),
+        _ => Err(HTTPAuthenticationError::InvalidBasicAuthentication {
+            header_value: authorization_value,
+        }),
+    }
+}
+
+/// Runs `auth_command` with the client credentials in its stdin.
+///
+/// Exit code 0 means the client is authenticated. Its stdout can be a JSON list of API path
+/// prefixes that the new token is limited to.
+async fn run_auth_command(
+    auth_command: PathBuf,
+    username: String,
+    password: String,
+    cfg_value: &CfgValue,
+) -> Result<Option<Vec<String>>, HTTPAuthenticationError> {
+    let settings = cfg_value
+        .commands
+        .run_settings(&cfg_value.logging, &cfg_value.server);
+    let input = CommandInput {
+        options: HashMap::from([
+            (
+                "username".to_string(),
+                CommandOptionValue::String(username.clone()),
+            ),
+            ("password".to_string(), CommandOptionValue::String(password)),
+        ]),
+        ..Default::default()
+    };
+    let env_map = HashMap::from([("RESTCOMMANDER_AUTH_USERNAME".to_string(), username.clone())]);
+    let command = auth_command.clone();
+    let output = tokio::task::spawn_blocking(move || {
+        cmd::runner::run_command(
+            &command,
+            Vec::new(),
+            Some(&input),
+            true,
+            env_map,
+            &settings,
+            None,
+        )
+    })
+    .await
+    .map_err(|reason| HTTPAuthenticationError::AuthCommand(reason.to_string()))?
+    .map_err(|reason| HTTPAuthenticationError::AuthCommand(reason.to_string()))?;
+    if output.exit_code != 0 || output.signal.is_some() {
+        debug!(
+            username = username.as_str(),
+            exit_code = output.exit_code,
+            "Auth command rejected client credentials."
+        );
+        return Err(HTTPAuthenticationError::InvalidUsernameOrPassword);
+    };
+    let stdout = output.stdout.trim();
+    if stdout.is_empty() {
+        return Ok(None);
+    };
+    serde_json::from_str::<Vec<String>>(stdout)
+        .map(|scopes| {
+            Some(
+                scopes
+                    .into_iter()
+                    .map(|scope| scope.trim_matches('/').to_string())
+                    .collect(),
+            )
+        })
+        .map_err(|reason| {
+            error!(
+                auth_command = ?auth_command,
+                error = reason.to_string().as_str(),
+                "Could not decode auth command output to a JSON list of API paths"
+            );
+            HTTPAuthenticationError::AuthCommand(format!(
+                "could not decode its output to a JSON list of API paths: {}",
+                reason
+            ))
+        })
+}
+
+/// Checks `token` and whether it is allowed to access API `path` (e.g. `run/foo/bar`).
+///
+/// Empty `path` is always allowed (e.g. `/api/auth/test`).
+fn authentication_with_token(
+    tokens: AuthTokens,
+    token: String,
+    path: &str,
+    cfg: Arc<ArcSwap<Cfg>>,
+) -> Result<(), HTTPAuthenticationError> {
+    let cfg = cfg.load().config_value.clone();
+    if !cfg.server.authentication_is_enabled() {
+        return Ok(());
+    }
+    if token.is_empty() {
+        return Err(HTTPAuthenticationError::TokenNotFound);
+    };
+    if let Some(ref api_token) = cfg.server.api_token {
+        if &token == api_token {
+            return Ok(());
+        }
+    }
+    return if let Some(auth_token) = tokens.read().get(token.as_str()) {
+        if auth_token.expire_time
+            <= time::SystemTime::now()
+                .duration_since(time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as usize
+        {
+            return Err(HTTPAuthenticationError::TokenExpired);
+        };
+        let path = path.trim_end_matches('/');
+        match auth_token.maybe_scopes {
+            Some(ref scopes)
+                if !path.is_empty() && !scopes.iter().any(|scope| path_has_prefix(path, scope)) =>
+            {
+                Err(HTTPAuthenticationError::OutOfScope {
+                    path: path.to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    } else {
+        Err(HTTPAuthenticationError::InvalidToken)
+    };
+}
+
+/// Makes `result` of command response from its decoded stdout.
+///
+/// Undecodable stdout is returned as string unless the command declares an `output_format`.
+fn make_command_response_body(
+    command: &Command,
+    command_output: &CommandOutput,
+) -> Result<serde_json::Value, HTTPAPIError> {
+    if command_output.stdout.is_empty() {
+        return Ok(serde_json::Value::Null);
+    };
+    match (&command_output.decoded_stdout, cmd::output_format(command)) {
+        (Ok(value), _) => Ok(value.clone()),
+        (Err(message), Some(format)) => Err(HTTPAPIError::DecodeOutput {
+            format: format!("{:?}", format).to_lowercase(),
+            message: message.clone(),
+            output: command_output.stdout.clone(),
+        }),
+        (Err(_), None) => Ok(serde_json::Value::String(command_output.stdout.clone())),
+    }
+}
+
+/// Returns command's `output_content_type` and its output if the output is non-JSON text (or its
+/// `output_format` is `raw`).
+fn maybe_command_text_output<'a>(
+    command: &'a Command,
+    command_output: &'a CommandOutput,
+) -> Option<(&'a str, &'a str)> {
+    let content_type = command.info.as_ref()?.output_content_type.as_ref()?;
+    let is_text = match cmd::output_format(command) {
+        Some(CommandInfoOutputFormat::Raw) => true,
+        None => command_output.decoded_stdout.is_err(),
+        Some(_) => false,
+    };
+    if is_text && !command_output.stdout.is_empty() {
+        Some((content_type.as_str(), command_output.stdout.as_str()))
+    } else {
+        None
+    }
+}
+
+fn make_command_event(
+    maybe_requester: Option<String>,
+    command: &Command,
+    maybe_command_output: &Result<CommandOutput, CommandError>,
+    duration: time::Duration,
+) -> CommandEvent {
+    let (maybe_exit_code, maybe_error, status_code) = match maybe_command_output {
+        Ok(command_output) if command_output.signal.is_some() => (
+            None,
+            command_output
+                .signal
+                .as_ref()
+                .map(|signal| format!("terminated by signal {}", signal.name)),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+        Ok(command_output) if command_output.exit_code == 0 => (Some(0), None, StatusCode::OK),
+        Ok(command_output) => (
+            Some(command_output.exit_code),
+            Some(format!("exited with code {}", command_output.exit_code)),
+            exit_code_to_status_code(command_output.exit_code),
+        ),
+        Err(reason) => (
+            None,
+            Some(reason.to_string()),
+            match reason {
+                CommandError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+                // There is no client to see it:
+                CommandError::Cancelled { .. } => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+        ),
+    };
+    CommandEvent {
+        http_path: command.http_path.to_str().unwrap().to_string(),
+        maybe_username: maybe_requester,
+        maybe_exit_code,
+        maybe_error,
+        severity: CfgNotificationsSeverity::from_status_code(status_code),
+        duration,
+    }
+}
+
+fn make_command_error(reason: CommandError) -> HTTPAPIError {
+    match reason {
+        CommandError::Timeout { .. } => HTTPAPIError::CommandTimeout {
+            message: reason.to_string(),
+        },
+        _ => HTTPAPIError::InitializeCommand {
+            message: reason.to_string(),
+        },
+    }
+}
+
+/// Makes command description and its input options definitions for verbose `CheckInput` errors.
+fn make_command_usage(command: &Command) -> Option<serde_json::Value> {
+    command.info.as_ref().map(|info| {
+        json!({
+            "http_path": command.http_path,
+            "description": info.description,
+            "options": info.options,
+        })
+    })
+}
+
+/// Rejects `.`, `..` and absolute segments (even percent-encoded ones) instead of relying on
+/// `PathBuf` normalization to keep the command path inside commands tree.
+fn check_command_path_segments(command_path: &str) -> Result<(), HTTPAPIError> {
+    let invalid_segment = |segment: &str| {
+        let segment = percent_encoding::percent_decode_str(segment).decode_utf8_lossy();
+        segment.contains('\\')
+            || Path::new(segment.as_ref())
+                .components()
+                .any(|component| !matches!(component, Component::Normal(_)))
+    };
+    if command_path.starts_with('/') || command_path.split('/').any(invalid_segment) {
+        warn!(path = command_path, "Rejected command path.");
+        Err(HTTPAPIError::InvalidCommandPath {
+            message: format!(
+                "command path {:?} should not contain `.`, `..` or absolute segments",
+                command_path
+            ),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Rejects too deep command paths before building and searching their `PathBuf`.
+fn check_command_path_depth(command_path: &str, max_path_depth: usize) -> Result<(), HTTPAPIError> {
+    let depth = command_path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .count();
+    if max_path_depth > 0 && depth > max_path_depth {
+        Err(HTTPAPIError::CommandNotFound {
+            message: format!(
+                "command path has {} segments and maximum allowed is {}",
+                depth, max_path_depth
+            ),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Detects commands root directory that is deleted or unmounted after loading commands.
+fn check_commands_root_directory(root_command: &Command) -> Result<(), HTTPAPIError> {
+    if root_command.file_path.is_dir() {
+        Ok(())
+    } else {
+        warn!(
+            root_directory = ?root_command.file_path,
+            "Commands root directory is not available."
+        );
+        Err(HTTPAPIError::CommandsRootUnavailable {
+            root_directory: root_command.file_path.clone(),
+        })
+    }
+}
+
+async fn maybe_run_command(
+    cfg: Arc<ArcSwap<Cfg>>,
+    commands: Arc<ArcSwap<Command>>,
+    command_path: String,
+    request: CommandRequest,
+    report_state: Arc<AsyncRwLock<ReportState>>,
+    maintenance: MaintenanceState,
+    command_semaphores: CommandSemaphores,
+    drain_state: DrainState,
+    command_rate_limits: CommandRateLimits,
+    address: String,
+) -> Result<Response<String>, HTTPAPIError> {
+    check_maintenance(&maintenance)?;
+    let statistics = request.input.statistics;
+    // Invalid expressions are rejected before running the command:
+    let maybe_select = request
+        .maybe_select
+        .as_deref()
+        .map(Select::from_str)
+        .transpose()
+        .map_err(|message| HTTPAPIError::InvalidSelect { message })?;
+    let base64_encoding = match request.maybe_encoding.as_deref() {
+        None | Some("raw") => false,
+        Some("base64") => true,
+        Some(encoding) => {
+            return Err(HTTPAPIError::UnknownOutputEncoding {
+                encoding: encoding.to_string(),
+            })
+        }
+    };
+    if request.debug && !cfg.load().config_value.commands.allow_debug {
+        return Err(HTTPAPIError::DebugNotAllowed);
+    };
+    let (command, command_output, maybe_debug) = run_command_request(
+        cfg.clone(),
+        commands,
+        command_path,
+        request,
+        report_state,
+        command_semaphores,
+        drain_state,
+        command_rate_limits,
+        address,
+    )
+    .await?;
+    let http_status_code = exit_code_to_status_code(command_output.exit_code);
+    let always_ok_status = command
+        .info
+        .as_ref()
+        .and_then(|info| info.always_ok_status)
+        .unwrap_or(cfg.load().config_value.commands.always_ok_status);
+    let mut response_status_code = if always_ok_status {
+        StatusCode::OK
+    } else {
+        http_status_code
+    };
+    let maybe_text_output = maybe_command_text_output(&command, &command_output);
+    if let Some((content_type, text)) = maybe_text_output.filter(|_| !base64_encoding) {
+        let mut response = Response::new(text.to_string());
+        *response.status_mut() = response_status_code;
+        if let Some(headers) = make_command_response_headers(
+            cfg,
+            response_status_code,
+            &command,
+            &command_output.instruction_list,
+        ) {
+            response.headers_mut().extend(headers);
+        };
+        // It is checked when commands are loaded:
+        if let Ok(content_type) = HeaderValue::from_str(content_type) {
+            response.headers_mut().insert(CONTENT_TYPE, content_type);
+        };
+        insert_command_exit_code_header(&mut response, command_output.exit_code);
+        insert_command_cache_control_header(&mut response, &command, http_status_code);
+        insert_command_traffic(&mut response, &command.http_path);
+        return Ok(response);
+    };
+    let no_content_on_empty_output = command
+        .info
+        .as_ref()
+        .and_then(|info| info.no_content_on_empty_output)
+        .unwrap_or(cfg.load().config_value.commands.no_content_on_empty_output);
+    // Requested statistics and debug information are still sent in a body:
+    if no_content_on_empty_output
+        && http_status_code == StatusCode::OK
+        && command_output.stdout.is_empty()
+        && !statistics
+        && maybe_debug.is_none()
+    {
+        let mut response = Response::new(String::new());
+        *response.status_mut() = StatusCode::NO_CONTENT;
+        if let Some(headers) = make_command_response_headers(
+            cfg,
+            StatusCode::NO_CONTENT,
+            &command,
+            &command_output.instruction_list,
+        ) {
+            response.headers_mut().extend(headers);
+        };
+        insert_command_exit_code_header(&mut response, command_output.exit_code);
+        insert_command_cache_control_header(&mut response, &command, http_status_code);
+        insert_command_traffic(&mut response, &command.http_path);
+        return Ok(response);
+    };
+    let (maybe_signal, mut maybe_exit_code) = command_exit_details(&command_output);
+    // Failure is only reported in response body:
+    if always_ok_status && http_status_code != StatusCode::OK {
+        maybe_exit_code = Some(command_output.exit_code);
+    };
+    // JSON-only clients get the output that is otherwise sent as it is inside response object:
+    let mut http_response_body = if let Some((_, text)) = maybe_text_output {
+        serde_json::Value::String(base64::encode(text))
+    } else {
+        make_command_response_body(&command, &command_output)?
+    };
+    let maybe_location = maybe_created_location(&command, &http_response_body)
+        .filter(|_| http_status_code == StatusCode::OK);
+    if maybe_location.is_some() && !always_ok_status {
+        response_status_code = StatusCode::CREATED;
+    };
+    // Failure results (e.g. error messages) are kept as they are:
+    if let Some(select) =
+        maybe_select.filter(|_| http_status_code == StatusCode::OK && maybe_text_output.is_none())
+    {
+        http_response_body = select.apply(&http_response_body);
+    };
+    let maybe_statistics = if statistics {
+        Some(make_statistics(
+            &command_output.stats,
+            &cfg.load().config_value.commands.statistics_fields,
+        ))
+    } else {
+        None
+    };
+    let mut body = make_api_response_body(
+        Ok(http_response_body),
+        maybe_statistics,
+        maybe_signal,
+        maybe_exit_code,
+        Some(http_status_code),
+    );
+    if let Some((content_type, _)) = maybe_text_output {
+        let body = body.as_object_mut().unwrap();
+        body.insert("content_type".to_string(), json!(content_type));
+        body.insert("encoding".to_string(), json!("base64"));
+    };
+    if let Some(debug) = maybe_debug {
+        body.as_object_mut()
+            .unwrap()
+            .insert("debug".to_string(), debug);
+    };
+    let mut response = make_api_response_from_body(
+        body,
+        make_command_response_headers(
+            cfg,
+            response_status_code,
+            &command,
+            &command_output.instruction_list,
+        ),
+        response_status_code,
+    );
+    if let Some(location) = maybe_location {
+        response.headers_mut().insert(LOCATION, location);
+    };
+    insert_command_exit_code_header(&mut response, command_output.exit_code);
+    insert_command_cache_control_header(&mut response, &command, http_status_code);
+    insert_command_traffic(&mut response, &command.http_path);
+    Ok(response)
+}
+
+/// Selects `Location` header of a created resource from JSON output of the command via its
+/// configured `created_location`.
+fn maybe_created_location(command: &Command, body: &serde_json::Value) -> Option<HeaderValue> {
+    let created_location = command.info.as_ref()?.created_location.as_ref()?;
+    // It is checked when commands are loaded:
+    match Select::from_str(created_location).ok()?.apply(body) {
+        serde_json::Value::String(location) if !location.is_empty() => {
+            HeaderValue::from_str(&location).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Lets clients check the raw exit-status without parsing response body (e.g. via `curl -I`).
+/// It replaces the same header if it is set by the command itself.
+fn insert_command_exit_code_header(response: &mut Response<String>, exit_code: i32) {
+    response
+        .headers_mut()
+        .insert(COMMAND_EXIT_CODE_HEADER, HeaderValue::from(exit_code));
+}
+
+/// Sets configured `cache_control` of the command for successful runs and `no-store` for others
+/// (including successful runs of commands without it), Unless the command has set it itself.
+/// Status-code of the run is used, So failures are not cached with `always_ok_status`.
+fn insert_command_cache_control_header(
+    response: &mut Response<String>,
+    command: &Command,
+    http_status_code: StatusCode,
+) {
+    if response.headers().contains_key(CACHE_CONTROL) {
+        return;
+    };
+    let cache_control = command
+        .info
+        .as_ref()
+        .and_then(|info| info.cache_control.as_ref())
+        .filter(|_| http_status_code.is_success())
+        // It is checked when commands are loaded:
+        .and_then(|cache_control| HeaderValue::from_str(cache_control).ok())
+        .unwrap_or_else(|| HeaderValue::from_static(NO_STORE_CACHE_CONTROL));
+    response.headers_mut().insert(CACHE_CONTROL, cache_control);
+}
+
+/// Runs the command once for each input of the request and returns their results in the same
+/// order as inputs.
+///
+/// At most `commands.map_concurrency` command processes run at the same time.
+async fn maybe_map_command(
+    cfg: Arc<ArcSwap<Cfg>>,
+    commands: Arc<ArcSwap<Command>>,
+    command_path: String,
+    request: CommandMapRequest,
+    report_state: Arc<AsyncRwLock<ReportState>>,
+    maintenance: MaintenanceState,
+    command_semaphores: CommandSemaphores,
+    drain_state: DrainState,
+    command_rate_limits: CommandRateLimits,
+    address: String,
+) -> Result<Response<String>, HTTPAPIError> {
+    check_maintenance(&maintenance)?;
+    check_commands_root_directory(&commands.load_full())?;
+    let statistics = request.statistics;
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(
+        cfg.load().config_value.commands.map_concurrency.max(1),
+    ));
+    let statistics_fields = cfg.load().config_value.commands.statistics_fields.clone();
+    let start_time = time::Instant::now();
+    let result_list = futures_util::future::join_all(request.input_list.into_iter().map(|input| {
+        let statistics_fields = statistics_fields.clone();
+        let cfg = cfg.clone();
+        let commands = commands.clone();
+        let command_path = command_path.clone();
+        let media_types = request.media_types.clone();
+        let maybe_client_certificate_name = request.maybe_client_certificate_name.clone();
+        let maybe_username = request.maybe_username.clone();
+        let report_state = report_state.clone();
+        let command_semaphores = command_semaphores.clone();
+        let drain_state = drain_state.clone();
+        let command_rate_limits = command_rate_limits.clone();
+        let address = address.clone();
+        let semaphore = semaphore.clone();
+        async move {
+            // The semaphore is never closed:
+            let _permit = semaphore.acquire().await.unwrap();
+            let maybe_command_output = run_command_request(
+                cfg,
+                commands,
+                command_path,
+                CommandRequest {
+                    input,
+                    media_types,
+                    maybe_body_stream: None,
+                    maybe_client_certificate_name,
+                    maybe_username,
+                    maybe_select: None,
+                    maybe_encoding: None,
+                    debug: false,
+                },
+                report_state,
+                command_semaphores,
+                drain_state,
+                command_rate_limits,
+                address,
+            )
+            .await;
+            let maybe_command_http_path = maybe_command_output
+                .as_ref()
+                .ok()
+                .map(|(command, _, _)| command.http_path.clone());
+            let (result, maybe_stats, maybe_signal, maybe_exit_code, http_status_code) =
+                match maybe_command_output {
+                    Ok((command, command_output, _)) => {
+                        let http_status_code = exit_code_to_status_code(command_output.exit_code);
+                        let (maybe_signal, maybe_exit_code) = command_exit_details(&command_output);
+                        match make_command_response_body(&command, &command_output) {
+                            Ok(body) => (
+                                Ok(body),
+                                Some(command_output.stats),
+                                maybe_signal,
+                                maybe_exit_code,
+                                http_status_code,
+                            ),
+                            Err(error) => (
+                                Err(HTTPError::API(error.clone())),
+                                Some(command_output.stats),
+                                command_output.signal,
+                                None,
+                                error.http_status_code(),
+                            ),
+                        }
+                    }
+                    Err(error) => {
+                        let http_status_code = error.http_status_code();
+                        (
+                            Err(HTTPError::API(error)),
+                            None,
+                            None,
+                            None,
+                            http_status_code,
+                        )
+                    }
+                };
+            let mut body = make_api_response_body(
+                result,
+                if statistics {
+                    maybe_stats.map(|stats| make_statistics(&stats, &statistics_fields))
+                } else {
+                    None
+                },
+                maybe_signal,
+                maybe_exit_code,
+                Some(http_status_code),
+            );
+            body.as_object_mut().unwrap().insert(
+                "status".to_string(),
+                serde_json::Value::Number(serde_json::Number::from(http_status_code.as_u16())),
+            );
+            (body, maybe_command_http_path)
+        }
+    }))
+    .await;
+    // All inputs are run by the same command:
+    let maybe_command_http_path = result_list
+        .iter()
+        .find_map(|(_, maybe_command_http_path)| maybe_command_http_path.clone());
+    let result_list: Vec<serde_json::Value> =
+        result_list.into_iter().map(|(body, _)| body).collect();
+    let mut body = make_api_response_body(
+        Ok(serde_json::Value::Array(result_list.clone())),
+        None,
+        None,
+        None,
+        None,
+    );
+    if statistics {
+        body.as_object_mut().unwrap().insert(
+            "statistics".to_string(),
+            json!({
+                "count": result_list.len(),
+                "ok_count": result_list
+                    .iter()
+                    .filter(|result| result.get("ok") == Some(&serde_json::Value::Bool(true)))
+                    .count(),
+                "duration": {
+                    "total": start_time.elapsed().as_micros() as u64,
+                },
+            }),
+        );
+    };
+    let mut response = make_api_response_from_body(body, None, StatusCode::OK);
+    if let Some(command_http_path) = maybe_command_http_path {
+        insert_command_traffic(&mut response, &command_http_path);
+    };
+    Ok(response)
 }
 
-fn api_configuration_filter(
-    cfg: Arc<RwLock<Cfg>>,
-) -> impl Filter<Extract = (Response<String>,), Error = Rejection> + Clone {
-    warp::path("configuration").map(move || {
-        make_api_response_ok_with_result(serde_json::Value::Object(
-            cfg.clone()
-                .read()
-                .unwrap()
-                .config_value
-                .www
-                .configuration
-                .clone()
-                .into_iter()
-                .fold(serde_json::Map::new(), |mut acc, item| {
-                    acc.insert(item.0, serde_json::Value::String(item.1));
-                    acc
-                }),
-        ))
-    })
+/// New command runs are rejected in maintenance mode.
+fn check_maintenance(maintenance: &MaintenanceState) -> Result<(), HTTPAPIError> {
+    match maintenance.read().as_ref() {
+        Some(reason) => Err(HTTPAPIError::Maintenance {
+            reason: reason.clone(),
+        }),
+        None => Ok(()),
+    }
 }
 
-fn check_ip_address(cfg: Arc<RwLock<Cfg>>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
-    warp::addr::remote()
-        .and_then(move |maybe_address: Option<SocketAddr>| {
-            let cfg = cfg.clone();
-            async move {
-                let ip_whitelist = cfg
-                    .clone()
-                    .read()
-                    .unwrap()
-                    .config_value
-                    .server
-                    .ip_whitelist
-                    .clone();
-                if ip_whitelist.is_empty() {
-                    return Ok(());
-                }
-                let ip = maybe_address.unwrap().ip().to_string();
-                for wildcard_ip in ip_whitelist {
-                    if WildMatch::new(wildcard_ip.as_str()).matches(ip.as_str()) {
-                        return Ok(());
-                    }
-                }
-                Err(warp::reject::custom(HTTPError::Authentication(
-                    HTTPAuthenticationError::InvalidIP(ip),
-                )))
-            }
+/// Checks the command's own `auth` requirement after the general API authentication.
+fn check_command_auth(
+    command: &Command,
+    maybe_client_certificate_name: Option<String>,
+) -> Result<(), HTTPAPIError> {
+    let auth = match command.info.as_ref().and_then(|info| info.auth.as_ref()) {
+        Some(auth) => auth,
+        None => return Ok(()),
+    };
+    let name = maybe_client_certificate_name.ok_or_else(|| HTTPAPIError::CommandForbidden {
+        message: "Command requires a TLS client certificate".to_string(),
+    })?;
+    if auth
+        .client_certificate_names
+        .iter()
+        .any(|wildcard_name| WildMatch::new(wildcard_name).matches(name.as_str()))
+    {
+        Ok(())
+    } else {
+        Err(HTTPAPIError::CommandForbidden {
+            message: format!("TLS client certificate {:?} can not run command", name),
         })
-        .untuple_one()
+    }
 }
 
-fn authentication_with_basic(
-    cfg: Arc<RwLock<Cfg>>,
-    maybe_captcha: Option<Arc<RwLock<captcha::Captcha>>>,
-    authorization_value: String,
-    form: HashMap<String, String>,
-) -> Result<(), HTTPAuthenticationError> {
-    let server_cfg = cfg.read().unwrap().config_value.server.clone();
-    if server_cfg.password_sha512.is_empty() && server_cfg.username.is_empty() {
-        return Ok(());
+/// Takes a token from the bucket of the command if it has configured `rate_limit`.
+fn check_command_rate_limit(
+    command_rate_limits: &CommandRateLimits,
+    command: &Command,
+) -> Result<(), HTTPAPIError> {
+    let rate_limit = match command
+        .info
+        .as_ref()
+        .and_then(|info| info.rate_limit.as_ref())
+    {
+        Some(rate_limit) => rate_limit,
+        None => return Ok(()),
     };
-    if server_cfg.password_sha512.is_empty() || server_cfg.username.is_empty() {
-        return Err(HTTPAuthenticationError::UsernameOrPasswordIsNotSet);
+    let burst = rate_limit.burst() as f64;
+    let now = time::Instant::now();
+    let mut command_rate_limits = command_rate_limits.write();
+    let bucket = command_rate_limits
+        .entry(command.http_path.clone())
+        .or_insert_with(|| CommandRateLimitBucket {
+            rate_limit: rate_limit.clone(),
+            tokens: burst,
+            updated: now,
+        });
+    // `rate_limit` is changed after reloading commands:
+    if &bucket.rate_limit != rate_limit {
+        *bucket = CommandRateLimitBucket {
+            rate_limit: rate_limit.clone(),
+            tokens: burst,
+            updated: now,
+        };
     };
-    match authorization_value
-        .as_str()
-        .splitn(2, ' ')
-        .collect::<Vec<&str>>()[..]
-    {
-        ["Basic", username_password] => {
-            let decoded_username_password =
-                base64::decode(username_password).map_err(|reason| {
-                    HTTPAuthenticationError::Base64Decode {
-                        data: username_password.to_string(),
-                        source: reason,
-                    }
-                })?;
-            match String::from_utf8(decoded_username_password)
-                .unwrap()
-                .splitn(2, ':')
-                .collect::<Vec<&str>>()[..]
-            {
-                [username, password] => {
-                    if username == server_cfg.username {
-                        let password_sha512 = utils::to_sha512(password);
-                        trace!(
-                            username = username,
-                            password_sha512 = password_sha512.as_str(),
-                            "New client provided credentials.",
-                        );
-                        if server_cfg.password_sha512 == password_sha512 {
-                            if maybe_captcha.is_none() {
-                                return Ok(());
-                            };
-                            return if form.len() == 1 {
-                                let (key, value) = form
-                                    .into_iter()
-                                    .fold(None, |_, key_value| Some(key_value.clone()))
-                                    .unwrap()
-                                    .clone();
-                                if maybe_captcha.unwrap().write().unwrap().compare_and_update(
-                                    key.to_string(),
-                                    value,
-                                    server_cfg.captcha_case_sensitive,
-                                ) {
-                                    Ok(())
-                                } else {
-                                    Err(HTTPAuthenticationError::InvalidCaptcha {})
-                                }
-                            } else {
-                                Err(HTTPAuthenticationError::InvalidCaptchaForm {})
-                            };
-                        };
-                    } else {
-                        debug!(
-                            username = username,
-                            "Client authenticated with unknown username."
-                        );
-                    };
-                    return Err(HTTPAuthenticationError::InvalidUsernameOrPassword);
-                }
-                [value] => Err(HTTPAuthenticationError::UsernameOrPasswordIsNotFound {
-                    data: value.to_string(),
-                }),
-                _ => Err(HTTPAuthenticationError::UsernameOrPasswordIsNotFound {
-                    data: "".to_string(),
-                }),
-            }
-        }
-        [unknown_method, _] => Err(HTTPAuthenticationError::UnknownMethod {
-            method: unknown_method.to_string(),
-        }),
-        _ => Err(HTTPAuthenticationError::InvalidBasicAuthentication {
-            header_value: authorization_value,
-        }),
+    bucket.tokens = (bucket.tokens
+        + now.duration_since(bucket.updated).as_secs_f64() * rate_limit.requests_per_second)
+        .min(burst);
+    bucket.updated = now;
+    if bucket.tokens >= 1.0 {
+        bucket.tokens -= 1.0;
+        Ok(())
+    } else {
+        Err(HTTPAPIError::CommandRateLimited {
+            retry_after: ((1.0 - bucket.tokens) / rate_limit.requests_per_second).ceil() as u64,
+        })
     }
 }
 
-fn authentication_with_token(
-    tokens: Arc<RwLock<HashMap<String, usize>>>,
-    token: String,
-    cfg: Arc<RwLock<Cfg>>,
-) -> Result<(), HTTPAuthenticationError> {
-    let cfg = cfg.clone().read().unwrap().config_value.clone();
-    if cfg.server.password_sha512.is_empty() {
+/// Waits for a free slot of the command if it has configured `max_concurrency`.
+///
+/// The command is rejected if it does not get a slot in its `concurrency_wait_timeout`.
+async fn acquire_command_permit(
+    command_semaphores: &CommandSemaphores,
+    command: &Command,
+) -> Result<Option<tokio::sync::OwnedSemaphorePermit>, HTTPAPIError> {
+    let (max_concurrency, maybe_wait_timeout) = match command.info.as_ref().and_then(|info| {
+        info.max_concurrency
+            .map(|max_concurrency| (max_concurrency, info.concurrency_wait_timeout))
+    }) {
+        Some(concurrency) => concurrency,
+        None => return Ok(None),
+    };
+    let semaphore = {
+        let mut command_semaphores = command_semaphores.write();
+        let (semaphore_max_concurrency, semaphore) = command_semaphores
+            .entry(command.http_path.clone())
+            .or_insert_with(|| {
+                (
+                    max_concurrency,
+                    Arc::new(tokio::sync::Semaphore::new(max_concurrency)),
+                )
+            });
+        // `max_concurrency` is changed after reloading commands:
+        if *semaphore_max_concurrency != max_concurrency {
+            *semaphore_max_concurrency = max_concurrency;
+            *semaphore = Arc::new(tokio::sync::Semaphore::new(max_concurrency));
+        };
+        semaphore.clone()
+    };
+    // The semaphore is never closed:
+    let maybe_permit = match maybe_wait_timeout.filter(|wait_timeout| *wait_timeout > 0) {
+        Some(wait_timeout) => tokio::time::timeout(
+            time::Duration::from_secs(wait_timeout),
+            semaphore.acquire_owned(),
+        )
+        .await
+        .ok()
+        .map(|maybe_permit| maybe_permit.unwrap()),
+        None => semaphore.try_acquire_owned().ok(),
+    };
+    maybe_permit
+        .map(Some)
+        .ok_or(HTTPAPIError::CommandBusy { max_concurrency })
+}
+
+/// Runs configured pre-run hook and rejects the command if the hook does not exit with code 0.
+///
+/// The hook's stdout (if any) is used as the reason.
+async fn run_pre_run_hook(
+    hook: PathBuf,
+    env_map: HashMap<String, String>,
+    settings: CommandRunSettings,
+) -> Result<(), HTTPAPIError> {
+    let hook_output = tokio::task::spawn_blocking(move || {
+        cmd::runner::run_command(&hook, Vec::new(), None, true, env_map, &settings, None)
+    })
+    .await
+    .map_err(|reason| HTTPAPIError::InitializeCommand {
+        message: reason.to_string(),
+    })?
+    .map_err(|reason| HTTPAPIError::InitializeCommand {
+        message: format!("could not run pre-run hook: {}", reason),
+    })?;
+    if hook_output.exit_code == 0 && hook_output.signal.is_none() {
         return Ok(());
-    }
-    if token.is_empty() {
-        return Err(HTTPAuthenticationError::TokenNotFound);
     };
-    if let Some(ref api_token) = cfg.server.api_token {
-        if &token == api_token {
-            return Ok(());
+    Err(HTTPAPIError::PreRunHookRejected {
+        message: if hook_output.stdout.is_empty() {
+            format!("exited with code {}", hook_output.exit_code)
+        } else {
+            hook_output.stdout
+        },
+    })
+}
+
+/// Runs configured post-run hook in background and just logs its failures.
+fn spawn_post_run_hook(
+    hook: PathBuf,
+    env_map: HashMap<String, String>,
+    settings: CommandRunSettings,
+) {
+    tokio::task::spawn_blocking(move || {
+        match cmd::runner::run_command(&hook, Vec::new(), None, true, env_map, &settings, None) {
+            Ok(hook_output) if hook_output.exit_code == 0 => (),
+            Ok(hook_output) => warn!(
+                hook = ?hook,
+                exit_code = hook_output.exit_code,
+                "Post-run hook failed"
+            ),
+            Err(reason) => warn!(
+                hook = ?hook,
+                error = reason.to_string().as_str(),
+                "Could not run post-run hook"
+            ),
         }
+    });
+}
+
+/// Environment variables of hooks that contain the command's options (including the client and
+/// configuration ones) and the command path.
+fn make_hook_environment_variables(
+    hook: &str,
+    command_env_map: &HashMap<String, String>,
+    command: &Command,
+) -> HashMap<String, String> {
+    let mut env_map = command_env_map.clone();
+    env_map.extend([
+        ("RESTCOMMANDER_HOOK".to_string(), hook.to_string()),
+        (
+            "RESTCOMMANDER_COMMAND".to_string(),
+            command.http_path.to_str().unwrap().to_string(),
+        ),
+    ]);
+    env_map
+}
+
+/// Environment variables of post-run hook that contain the command result.
+fn make_hook_result_environment_variables(event: &CommandEvent) -> HashMap<String, String> {
+    HashMap::from([
+        ("RESTCOMMANDER_OK".to_string(), event.is_ok().to_string()),
+        (
+            "RESTCOMMANDER_EXIT_CODE".to_string(),
+            event
+                .maybe_exit_code
+                .map(|exit_code| exit_code.to_string())
+                .unwrap_or_default(),
+        ),
+        (
+            "RESTCOMMANDER_ERROR".to_string(),
+            event.maybe_error.clone().unwrap_or_default(),
+        ),
+        (
+            "RESTCOMMANDER_SEVERITY".to_string(),
+            event.severity.as_str().to_string(),
+        ),
+        (
+            "RESTCOMMANDER_DURATION".to_string(),
+            (event.duration.as_micros() as u64).to_string(),
+        ),
+    ])
+}
+
+/// Sets the flag when it is dropped.
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
     }
-    return if let Some(expire_time) = tokens.clone().read().unwrap().get(token.as_str()) {
-        if expire_time
-            > &(time::SystemTime::now()
-                .duration_since(time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as usize)
-        {
-            return Ok(());
-        };
-        Err(HTTPAuthenticationError::TokenExpired)
-    } else {
-        Err(HTTPAuthenticationError::InvalidToken)
-    };
 }
 
-async fn maybe_run_command(
-    commands: Arc<RwLock<Command>>,
+/// Finds the command, checks its input and runs it.
+///
+/// Also handles command's `REPORT` instructions.
+async fn run_command_request(
+    cfg: Arc<ArcSwap<Cfg>>,
+    commands: Arc<ArcSwap<Command>>,
     command_path: String,
-    command_input: CommandInput,
+    request: CommandRequest,
     report_state: Arc<AsyncRwLock<ReportState>>,
+    command_semaphores: CommandSemaphores,
+    drain_state: DrainState,
+    command_rate_limits: CommandRateLimits,
     address: String,
-) -> Result<Response<String>, HTTPAPIError> {
-    let root_command = commands.read().unwrap().clone();
+) -> Result<(Command, CommandOutput, Option<serde_json::Value>), HTTPAPIError> {
+    let CommandRequest {
+        input: mut command_input,
+        media_types,
+        maybe_body_stream,
+        maybe_client_certificate_name,
+        maybe_username,
+        debug,
+        ..
+    } = request;
+    // Clients that are authorized by their TLS certificate are reported by its name:
+    let maybe_requester = maybe_username.or_else(|| maybe_client_certificate_name.clone());
+    let root_command = commands.load_full();
+    check_commands_root_directory(&root_command)?;
+    let command_path = cmd::resolve_alias(&cfg.load().config_value.commands.aliases, &command_path);
+    check_command_path_segments(&command_path)?;
+    check_command_path_depth(
+        &command_path,
+        cfg.load().config_value.commands.max_path_depth,
+    )?;
     let command_path_list: Vec<String> = PathBuf::from(root_command.name.clone())
         .join(PathBuf::from(command_path))
         .components()
         .map(|x| x.as_os_str().to_str().unwrap().to_string())
         .collect();
-    let command = cmd::search_for_command(&command_path_list, &root_command).map_err(|reason| {
-        HTTPAPIError::CommandNotFound {
+    let (command, path_variables) = cmd::search_for_command(&command_path_list, &root_command)
+        .map_err(|reason| HTTPAPIError::CommandNotFound {
             message: reason.to_string(),
-        }
-    })?;
+        })?;
+    check_command_auth(&command, maybe_client_certificate_name)?;
+    media_types.check(&command)?;
+    command_input
+        .options
+        .extend(make_options_from_path_variables(path_variables));
     let input =
         cmd::check_input(&command, &command_input).map_err(|reason| HTTPAPIError::CheckInput {
-            message: reason.to_string(),
+            message: reason.message,
+            fields: reason.fields,
+            maybe_usage: if command_input.verbose_errors {
+                make_command_usage(&command)
+            } else {
+                None
+            },
         })?;
+    check_command_rate_limit(&command_rate_limits, &command)?;
+    // Kept until the command exits:
+    let _command_permit = acquire_command_permit(&command_semaphores, &command).await?;
     let env_map = make_environment_variables_map_from_options(input.options.clone());
-    let command_output = cmd::run_command(&command, &input, env_map).map_err(|reason| {
-        HTTPAPIError::InitializeCommand {
-            message: reason.to_string(),
-        }
+    let cfg_value = cfg.load().config_value.clone();
+    let mut run_settings = cfg_value
+        .commands
+        .run_settings(&cfg_value.logging, &cfg_value.server);
+    // Hooks are not killed with the command:
+    let hook_settings = run_settings.clone();
+    if let Some(pre_run_hook) = cfg_value.commands.pre_run_hook.clone() {
+        run_pre_run_hook(
+            pre_run_hook,
+            make_hook_environment_variables("pre", &env_map, &command),
+            hook_settings.clone(),
+        )
+        .await?;
+    };
+    // The command is killed if it is still running after shutdown drain timeout:
+    let cancel = Arc::new(AtomicBool::new(false));
+    run_settings.maybe_cancel = Some(cancel.clone());
+    let _running_command = drain_state.add_command(command.http_path.clone(), cancel.clone());
+    // Hyper drops this future if client disconnects before the response is sent:
+    let _kill_on_disconnect = if cfg_value.commands.kill_on_disconnect {
+        Some(CancelOnDrop(cancel.clone()))
+    } else {
+        None
+    };
+    // The command is killed if request body could not be read, So it does not finish with a
+    // partial input:
+    let body_read_error = Arc::new(RwLock::new(None));
+    let maybe_stdin_stream = maybe_body_stream.map(|mut body_stream| {
+        let (chunk_sender, chunk_receiver) = tokio::sync::mpsc::channel(STDIN_STREAM_BUFFER_SIZE);
+        let body_read_error = body_read_error.clone();
+        let cancel = cancel.clone();
+        tokio::spawn(async move {
+            while let Some(maybe_chunk) = body_stream.next().await {
+                match maybe_chunk {
+                    Ok(chunk) => {
+                        // Command does not read its stdin anymore:
+                        if chunk_sender.send(chunk.to_vec()).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(reason) => {
+                        warn!(
+                            error = reason.to_string().as_str(),
+                            "Could not read request body"
+                        );
+                        *body_read_error.write() = Some(reason.to_string());
+                        cancel.store(true, Ordering::SeqCst);
+                        break;
+                    }
+                }
+            }
+        });
+        chunk_receiver
+    });
+    // It does not change the run and only reports what the command is run with:
+    let maybe_debug = if debug {
+        Some(make_command_debug(&command, &input, &env_map))
+    } else {
+        None
+    };
+    let command_to_run = command.clone();
+    let command_env_map = env_map.clone();
+    let start_time = time::Instant::now();
+    let maybe_command_output = tokio::task::spawn_blocking(move || {
+        cmd::run_command(
+            &command_to_run,
+            &input,
+            command_env_map,
+            &run_settings,
+            maybe_stdin_stream,
+        )
+    })
+    .await
+    .map_err(|reason| HTTPAPIError::InitializeCommand {
+        message: reason.to_string(),
     })?;
+    let command_event = make_command_event(
+        maybe_requester,
+        &command,
+        &maybe_command_output,
+        start_time.elapsed(),
+    );
+    if let Some(post_run_hook) = cfg_value.commands.post_run_hook.clone() {
+        let mut hook_env_map = make_hook_environment_variables("post", &env_map, &command);
+        hook_env_map.extend(make_hook_result_environment_variables(&command_event));
+        spawn_post_run_hook(post_run_hook, hook_env_map, hook_settings);
+    };
+    notification::notify(&cfg_value.notifications, command_event);
+    if let Some(message) = body_read_error.read().clone() {
+        return Err(HTTPAPIError::ReadRequestBody { message });
+    };
+    let command_output = maybe_command_output.map_err(make_command_error)?;
     for instruction in command_output.instruction_list.clone() {
         let report_state_locked = report_state.read().await;
         match instruction {
@@ -1154,51 +4455,56 @@ async fn maybe_run_command(
         };
         drop(report_state_locked)
     }
-    let http_status_code = exit_code_to_status_code(command_output.exit_code);
-    let http_response_body = if command_output.stdout.is_empty() {
-        serde_json::Value::Null
-    } else if command_output.decoded_stdout.is_err() {
-        serde_json::Value::String(command_output.stdout)
-    } else {
-        command_output.decoded_stdout.unwrap()
-    };
-    Ok(make_api_response_with_header_and_stats(
-        Ok(http_response_body),
-        None,
-        if command_input.statistics {
-            Some(command_output.stats)
-        } else {
-            None
-        },
-        Some(http_status_code),
-    ))
+    Ok((command, command_output, maybe_debug))
 }
 
 async fn maybe_get_command_state(
-    cfg: Arc<RwLock<Cfg>>,
-    commands: Arc<RwLock<Command>>,
+    cfg: Arc<ArcSwap<Cfg>>,
+    commands: Arc<ArcSwap<Command>>,
     command_path: String,
+    maybe_client_certificate_name: Option<String>,
     report_state: Arc<AsyncRwLock<ReportState>>,
     address: String,
 ) -> Result<Response<String>, HTTPAPIError> {
-    let root_command = commands.read().unwrap().clone();
+    let root_command = commands.load_full();
+    check_commands_root_directory(&root_command)?;
+    let command_path = cmd::resolve_alias(&cfg.load().config_value.commands.aliases, &command_path);
+    check_command_path_segments(&command_path)?;
+    check_command_path_depth(
+        &command_path,
+        cfg.load().config_value.commands.max_path_depth,
+    )?;
     let command_path_list: Vec<String> = PathBuf::from(root_command.name.clone())
         .join(PathBuf::from(command_path))
         .components()
         .map(|x| x.as_os_str().to_str().unwrap().to_string())
         .collect();
-    let command = cmd::search_for_command(&command_path_list, &root_command).map_err(|reason| {
-        HTTPAPIError::CommandNotFound {
+    let (command, path_variables) = cmd::search_for_command(&command_path_list, &root_command)
+        .map_err(|reason| HTTPAPIError::CommandNotFound {
             message: reason.to_string(),
-        }
-    })?;
-    let command_output = cmd::get_state(
-        &command,
-        make_environment_variables_map_from_options(add_configuration_to_options(cfg.clone())),
-    )
+        })?;
+    // State is fetched by running the same script:
+    check_command_auth(&command, maybe_client_certificate_name)?;
+    let mut options = add_configuration_to_options(cfg.clone());
+    options.extend(make_options_from_path_variables(path_variables));
+    let cfg_value = cfg.load().config_value.clone();
+    let run_settings = cfg_value
+        .commands
+        .run_settings(&cfg_value.logging, &cfg_value.server);
+    // Like runs, It may sleep between spawn retries or wait for its process to be killed:
+    let command_to_run = command.clone();
+    let command_output = tokio::task::spawn_blocking(move || {
+        cmd::get_state(
+            &command_to_run,
+            make_environment_variables_map_from_options(options),
+            &run_settings,
+        )
+    })
+    .await
     .map_err(|reason| HTTPAPIError::InitializeCommand {
         message: reason.to_string(),
-    })?;
+    })?
+    .map_err(make_command_error)?;
     for instruction in command_output.instruction_list.clone() {
         let report_state_locked = report_state.read().await;
         match instruction {
@@ -1213,24 +4519,198 @@ async fn maybe_get_command_state(
                 )
                 .await;
             }
-            _ => {}
+            _ => {}
+        };
+        drop(report_state_locked)
+    }
+    let http_status_code = exit_code_to_status_code(command_output.exit_code);
+    let (maybe_signal, maybe_exit_code) = command_exit_details(&command_output);
+    let http_response_body = make_command_response_body(&command, &command_output)?;
+    Ok(make_api_response_with_header_and_stats(
+        Ok(http_response_body),
+        make_command_response_headers(
+            cfg,
+            http_status_code,
+            &command,
+            &command_output.instruction_list,
+        ),
+        None, // TODO
+        maybe_signal,
+        maybe_exit_code,
+        Some(http_status_code),
+    ))
+}
+
+/// Response header of `/api/run/...` responses with the exit code of the command.
+const COMMAND_EXIT_CODE_HEADER: &str = "x-command-exit-code";
+
+/// `Cache-Control` of `/api/run/...` responses of commands without configured `cache_control`.
+const NO_STORE_CACHE_CONTROL: &str = "no-store";
+
+/// Query-string parameter of `/api/run/...` requests with a [Select] expression.
+const SELECT_QUERY_PARAMETER: &str = "select";
+
+/// Query-string parameter of `/api/run/...` requests with encoding (`raw` or `base64`) of outputs
+/// that are sent as they are.
+const ENCODING_QUERY_PARAMETER: &str = "encoding";
+
+/// Query-string parameter of `/api/run/...` requests that includes debug information of the run
+/// in response (if `commands.allow_debug` is enabled).
+const DEBUG_QUERY_PARAMETER: &str = "debug";
+
+/// Query-string parameter of API requests that works like `X-RESTCOMMANDER-VERBOSE-ERRORS`
+/// header if it is `true`.
+const VERBOSE_ERRORS_QUERY_PARAMETER: &str = "verbose_errors";
+
+/// Whether a query-string parameter value is `true`.
+fn is_true_query_value(value: &CommandOptionValue) -> bool {
+    matches!(value, CommandOptionValue::Bool(true))
+        || value == &CommandOptionValue::String("true".to_string())
+}
+
+/// Environment variables that contain one of these (case-insensitive) are redacted in debug
+/// information.
+const SECRET_ENVIRONMENT_VARIABLE_NAME_PARTS: [&str; 7] = [
+    "PASSWORD",
+    "SECRET",
+    "TOKEN",
+    "API_KEY",
+    "PRIVATE_KEY",
+    "AUTHORIZATION",
+    "COOKIE",
+];
+
+const REDACTED_VALUE: &str = "<REDACTED>";
+
+/// Response headers that commands are allowed to set via `HEADER <NAME>: <VALUE>` stderr lines.
+///
+/// Other headers (e.g. `Content-Type` of JSON response or `Set-Cookie`) are ignored. Headers
+/// starting with `X-` are allowed too.
+const ALLOWED_COMMAND_RESPONSE_HEADERS: [HeaderName; 8] = [
+    CACHE_CONTROL,
+    CONTENT_DISPOSITION,
+    ETAG,
+    EXPIRES,
+    LAST_MODIFIED,
+    LINK,
+    LOCATION,
+    VARY,
+];
+
+/// Makes response headers from command's `HEADER` instructions and `Retry-After` header.
+fn make_command_response_headers(
+    cfg: Arc<ArcSwap<Cfg>>,
+    status_code: StatusCode,
+    command: &Command,
+    instruction_list: &[CommandInstruction],
+) -> Option<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for instruction in instruction_list {
+        if let CommandInstruction::Header(name, value) = instruction {
+            match (
+                HeaderName::try_from(name.as_str()),
+                HeaderValue::try_from(value.as_str()),
+            ) {
+                (Ok(name), Ok(value))
+                    if ALLOWED_COMMAND_RESPONSE_HEADERS.contains(&name)
+                        || name.as_str().starts_with("x-") =>
+                {
+                    headers.append(name, value);
+                }
+                _ => warn!(
+                    command = ?command.file_path,
+                    header = name.as_str(),
+                    "Ignored response header set by command."
+                ),
+            }
         };
-        drop(report_state_locked)
     }
-    let http_status_code = exit_code_to_status_code(command_output.exit_code);
-    let http_response_body = if command_output.stdout.is_empty() {
-        serde_json::Value::Null
-    } else if command_output.decoded_stdout.is_err() {
-        serde_json::Value::String(command_output.stdout)
+    if let Some(retry_after_headers) = maybe_retry_after_headers(cfg, status_code) {
+        headers.extend(retry_after_headers);
+    };
+    if headers.is_empty() {
+        None
     } else {
-        command_output.decoded_stdout.unwrap()
+        Some(headers)
+    }
+}
+
+/// Makes `Retry-After` header for `503` and `429` responses if `server.retry_after` is configured.
+fn maybe_retry_after_headers(cfg: Arc<ArcSwap<Cfg>>, status_code: StatusCode) -> Option<HeaderMap> {
+    let retry_after = cfg.load().config_value.server.retry_after;
+    if retry_after == 0
+        || (status_code != StatusCode::SERVICE_UNAVAILABLE
+            && status_code != StatusCode::TOO_MANY_REQUESTS)
+    {
+        return None;
     };
-    Ok(make_api_response_with_header_and_stats(
-        Ok(http_response_body),
-        None,
-        None, // TODO
-        Some(http_status_code),
-    ))
+    let mut headers = HeaderMap::new();
+    headers.insert(RETRY_AFTER, HeaderValue::from(retry_after));
+    Some(headers)
+}
+
+/// Resolved arguments, working directory and environment variables that the command is run with.
+///
+/// Values of options with `secret` and environment variables with secret names (e.g. API token of
+/// server) are redacted everywhere.
+fn make_command_debug(
+    command: &Command,
+    input: &CommandInput,
+    env_map: &HashMap<String, String>,
+) -> serde_json::Value {
+    let secret_option_list: Vec<&String> = command
+        .info
+        .as_ref()
+        .map(|info| {
+            info.options
+                .iter()
+                .filter(|(_, definition)| definition.secret)
+                .map(|(name, _)| name)
+                .collect()
+        })
+        .unwrap_or_default();
+    let is_secret = |name: &String| {
+        let upper_name = name.to_uppercase();
+        secret_option_list.contains(&name)
+            || SECRET_ENVIRONMENT_VARIABLE_NAME_PARTS
+                .iter()
+                .any(|part| upper_name.contains(part))
+    };
+    let secret_value_list: Vec<&String> = env_map
+        .iter()
+        .filter(|(name, value)| !value.is_empty() && is_secret(name))
+        .map(|(_, value)| value)
+        .collect();
+    let redact = |value: &String| {
+        secret_value_list
+            .iter()
+            .fold(value.clone(), |value, secret_value| {
+                value.replace(secret_value.as_str(), REDACTED_VALUE)
+            })
+    };
+    let argument_list: Vec<String> = [command.file_path.to_str().unwrap_or_default().to_string()]
+        .into_iter()
+        .chain(cmd::command_arguments(command, input).unwrap_or_default())
+        .map(|argument| redact(&argument))
+        .collect();
+    let environment: BTreeMap<&String, String> = env_map
+        .iter()
+        .map(|(name, value)| {
+            (
+                name,
+                if is_secret(name) && !value.is_empty() {
+                    REDACTED_VALUE.to_string()
+                } else {
+                    redact(value)
+                },
+            )
+        })
+        .collect();
+    json!({
+        "argv": argument_list,
+        "working_directory": std::env::current_dir().ok(),
+        "environment": environment,
+    })
 }
 
 fn make_environment_variables_map_from_options(
@@ -1244,17 +4724,40 @@ fn make_environment_variables_map_from_options(
                 match value {
                     CommandOptionValue::Bool(x) => x.to_string(),
                     CommandOptionValue::Integer(x) => x.to_string(),
+                    CommandOptionValue::BigInteger(x) => x.to_string(),
                     CommandOptionValue::Float(x) => x.to_string(),
                     CommandOptionValue::None => "".to_string(),
                     CommandOptionValue::String(x) => x,
+                    CommandOptionValue::Array(_) | CommandOptionValue::Object(_) => {
+                        serde_json::to_string(&value).unwrap_or_default()
+                    }
                 },
             );
             env_map
         })
 }
 
-fn add_configuration_to_options(cfg: Arc<RwLock<Cfg>>) -> CommandOptionsValue {
-    let cfg_instance = cfg.read().unwrap().config_value.clone();
+/// Decodes captured `{NAME}` path segments to options the same way header values are decoded.
+fn make_options_from_path_variables(
+    path_variables: HashMap<String, String>,
+) -> CommandOptionsValue {
+    path_variables
+        .into_iter()
+        .map(|(name, value)| {
+            let value = percent_encoding::percent_decode_str(&value)
+                .decode_utf8_lossy()
+                .to_string();
+            (
+                name,
+                serde_json::from_str::<CommandOptionValue>(&value)
+                    .unwrap_or(CommandOptionValue::String(value)),
+            )
+        })
+        .collect()
+}
+
+fn add_configuration_to_options(cfg: Arc<ArcSwap<Cfg>>) -> CommandOptionsValue {
+    let cfg_instance = cfg.load().config_value.clone();
     let mut options = CommandOptionsValue::from([
         (
             "RESTCOMMANDER_CONFIG_SERVER_HOST".to_string(),
@@ -1314,8 +4817,7 @@ fn add_configuration_to_options(cfg: Arc<RwLock<Cfg>>) -> CommandOptionsValue {
         (
             "RESTCOMMANDER_CONFIGURATION_FILENAME".to_string(),
             CommandOptionValue::String(
-                cfg.read()
-                    .unwrap()
+                cfg.load()
                     .filename
                     .as_ref()
                     .or(Some(&PathBuf::new()))
@@ -1333,32 +4835,74 @@ fn add_configuration_to_options(cfg: Arc<RwLock<Cfg>>) -> CommandOptionsValue {
 }
 
 fn try_set_password(
-    cfg: Arc<RwLock<Cfg>>,
+    cfg: Arc<ArcSwap<Cfg>>,
     password: SetPassword,
 ) -> Result<Response<String>, HTTPAPIError> {
+    if !cfg.load().config_value.server.allow_set_password {
+        return Err(HTTPAPIError::SetPasswordNotAllowed);
+    };
+    if cfg.load().config_value.server.require_old_password {
+        let old_password_sha512 = utils::to_sha512(password.old_password.unwrap_or_default());
+        if !utils::constant_time_eq(
+            &old_password_sha512,
+            &cfg.load().config_value.server.password_sha512,
+        ) {
+            return Err(HTTPAPIError::InvalidOldPassword);
+        };
+    };
     if password.password.is_empty() {
         return Err(HTTPAPIError::EmptyPassword);
     };
-    let password_file = cfg
-        .read()
-        .unwrap()
-        .config_value
-        .server
-        .password_file
-        .clone();
+    check_password_complexity(cfg.clone(), &password.password)?;
+    let password_file = cfg.load().config_value.server.password_file.clone();
     if password_file.to_str().unwrap().is_empty() {
         return Err(HTTPAPIError::NoPasswordFile);
     };
     let password_sha512 = utils::to_sha512(password.password);
-    std::fs::write(password_file, password_sha512.clone()).map_err(|reason| {
+    utils::write_file_atomically(&password_file, password_sha512.clone()).map_err(|reason| {
         HTTPAPIError::SaveNewPassword {
             message: reason.to_string(),
         }
     })?;
-    cfg.write().unwrap().config_value.server.password_sha512 = password_sha512;
+    cfg.rcu(|cfg| {
+        let mut cfg = cfg.deref().clone();
+        cfg.config_value.server.password_sha512 = password_sha512.clone();
+        cfg
+    });
     Ok(make_api_response_ok())
 }
 
+fn check_password_complexity(cfg: Arc<ArcSwap<Cfg>>, password: &str) -> Result<(), HTTPAPIError> {
+    let server = &cfg.load().config_value.server;
+    let length = password.chars().count();
+    if length < server.password_min_length {
+        return Err(HTTPAPIError::WeakPassword {
+            message: format!(
+                "Password should contain at least {} characters",
+                server.password_min_length
+            ),
+        });
+    };
+    let character_classes = [
+        password.chars().any(|x| x.is_lowercase()),
+        password.chars().any(|x| x.is_uppercase()),
+        password.chars().any(|x| x.is_numeric()),
+        password.chars().any(|x| !x.is_alphanumeric()),
+    ]
+    .iter()
+    .filter(|x| **x)
+    .count();
+    if character_classes < server.password_character_classes {
+        return Err(HTTPAPIError::WeakPassword {
+            message: format!(
+                "Password should contain at least {} of lowercase letters, uppercase letters, digits and other characters",
+                server.password_character_classes
+            ),
+        });
+    };
+    Ok(())
+}
+
 fn unify_options(options_list: Vec<CommandOptionsValue>) -> CommandOptionsValue {
     let mut options = CommandOptionsValue::new();
     for options_list_item in options_list {
@@ -1377,30 +4921,80 @@ fn unify_options(options_list: Vec<CommandOptionsValue>) -> CommandOptionsValue
 }
 
 fn make_api_response_ok() -> Response<String> {
-    make_api_response_with_header_and_stats(Ok(serde_json::Value::Null), None, None, None)
+    make_api_response_with_header_and_stats(
+        Ok(serde_json::Value::Null),
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
 }
 
 fn make_api_response_ok_with_result(result: serde_json::Value) -> Response<String> {
-    make_api_response_with_header_and_stats(Ok(result), None, None, None)
+    make_api_response_with_header_and_stats(Ok(result), None, None, None, None, None)
 }
 
 fn make_api_response(result: Result<serde_json::Value, HTTPError>) -> Response<String> {
-    make_api_response_with_header_and_stats(result, None, None, None)
+    make_api_response_with_header_and_stats(result, None, None, None, None, None)
+}
+
+/// Makes a `quoted-string` (RFC 7230) of `value` for header parameters (e.g. `realm`).
+fn quoted_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for character in value.chars() {
+        if character == '"' || character == '\\' {
+            quoted.push('\\');
+        };
+        quoted.push(character);
+    }
+    quoted.push('"');
+    quoted
 }
 
 fn make_api_response_with_headers(
     result: Result<serde_json::Value, HTTPError>,
     maybe_headers: Option<HeaderMap>,
 ) -> Response<String> {
-    make_api_response_with_header_and_stats(result, maybe_headers, None, None)
+    make_api_response_with_header_and_stats(result, maybe_headers, None, None, None, None)
 }
 
 fn make_api_response_with_header_and_stats(
     result: Result<serde_json::Value, HTTPError>,
     maybe_headers: Option<HeaderMap>,
-    maybe_statistics: Option<CommandStats>,
+    maybe_statistics: Option<serde_json::Value>,
+    maybe_signal: Option<CommandSignal>,
+    maybe_exit_code: Option<i32>,
     maybe_status_code: Option<StatusCode>,
 ) -> Response<String> {
+    let status_code = if let Some(status_code) = maybe_status_code {
+        status_code
+    } else if let Err(ref error) = result {
+        error.http_status_code()
+    } else {
+        StatusCode::OK
+    };
+    make_api_response_from_body(
+        make_api_response_body(
+            result,
+            maybe_statistics,
+            maybe_signal,
+            maybe_exit_code,
+            maybe_status_code,
+        ),
+        maybe_headers,
+        status_code,
+    )
+}
+
+fn make_api_response_body(
+    result: Result<serde_json::Value, HTTPError>,
+    maybe_statistics: Option<serde_json::Value>,
+    maybe_signal: Option<CommandSignal>,
+    maybe_exit_code: Option<i32>,
+    maybe_status_code: Option<StatusCode>,
+) -> serde_json::Value {
     let mut body = json!(
         {
             "ok": if let Some(ref status_code) = maybe_status_code {
@@ -1429,30 +5023,102 @@ fn make_api_response_with_header_and_stats(
     //     body.as_object_mut().unwrap().remove("result");
     // }
     if let Some(statistics) = maybe_statistics {
+        body.as_object_mut()
+            .unwrap()
+            .insert("statistics".to_string(), statistics);
+    };
+    if let Some(signal) = maybe_signal {
+        body.as_object_mut()
+            .unwrap()
+            .insert("signal".to_string(), serde_json::to_value(&signal).unwrap());
+    };
+    if let Some(exit_code) = maybe_exit_code {
         body.as_object_mut().unwrap().insert(
-            "statistics".to_string(),
-            serde_json::to_value(&statistics).unwrap(),
+            "exit_code".to_string(),
+            serde_json::Value::Number(serde_json::Number::from(exit_code)),
         );
     };
-    let mut response =
-        warp::http::Response::builder().status(if let Some(status_code) = maybe_status_code {
-            status_code
-        } else if let Err(ref error) = result {
-            error.http_status_code()
-        } else {
-            StatusCode::OK
-        });
-    let headers_mut = response.headers_mut().unwrap();
-    if let Some(headers) = maybe_headers {
-        for (header, header_value) in headers.iter() {
-            headers_mut.insert(header.clone(), header_value.clone());
-        }
-    };
     if let Err(error) = result {
         body.as_object_mut().unwrap().insert(
             "code".to_string(),
             serde_json::Value::Number(serde_json::Number::from(error.http_error_code())),
         );
+        // Attach raw command output, so broken commands are easy to debug:
+        if let HTTPError::API(HTTPAPIError::DecodeOutput { output, .. }) = error {
+            body.as_object_mut()
+                .unwrap()
+                .insert("output".to_string(), serde_json::Value::String(output));
+        } else if let HTTPError::API(HTTPAPIError::CheckInput {
+            fields,
+            maybe_usage,
+            ..
+        }) = error
+        {
+            if !fields.is_empty() {
+                body.as_object_mut()
+                    .unwrap()
+                    .insert("fields".to_string(), json!(fields));
+            };
+            if let Some(usage) = maybe_usage {
+                body.as_object_mut()
+                    .unwrap()
+                    .insert("usage".to_string(), usage);
+            };
+        };
+    };
+    body
+}
+
+/// Serializes command statistics.
+///
+/// If `commands.statistics_fields` is configured, Only those fields are included with their
+/// configured names and unknown paths (e.g. `signal.name` for not signaled commands) are skipped.
+fn make_statistics(
+    statistics: &CommandStats,
+    statistics_fields: &HashMap<String, String>,
+) -> serde_json::Value {
+    let statistics = serde_json::to_value(statistics).unwrap();
+    if statistics_fields.is_empty() {
+        return statistics;
+    };
+    serde_json::Value::Object(
+        statistics_fields
+            .iter()
+            .filter_map(|(name, path)| {
+                statistics
+                    .pointer(&format!("/{}", path.replace('.', "/")))
+                    .map(|value| (name.clone(), value.clone()))
+            })
+            .collect(),
+    )
+}
+
+fn make_api_response_from_body(
+    body: serde_json::Value,
+    maybe_headers: Option<HeaderMap>,
+    status_code: StatusCode,
+) -> Response<String> {
+    let mut body = body;
+    if let Ok(start_time) = RESPONSE_TIMING_START_TIME.try_with(|start_time| *start_time) {
+        if let Some(object) = body.as_object_mut() {
+            object.insert(
+                "timestamp".to_string(),
+                serde_json::Value::String(
+                    humantime::format_rfc3339_millis(time::SystemTime::now()).to_string(),
+                ),
+            );
+            object.insert(
+                "elapsed_seconds".to_string(),
+                json!(start_time.elapsed().as_micros() as f64 / 1000000.0),
+            );
+        };
+    };
+    let mut response = warp::http::Response::builder().status(status_code);
+    let headers_mut = response.headers_mut().unwrap();
+    if let Some(headers) = maybe_headers {
+        for (header, header_value) in headers.iter() {
+            headers_mut.append(header.clone(), header_value.clone());
+        }
     };
     headers_mut.insert(
         warp::http::header::CONTENT_TYPE,
@@ -1463,19 +5129,67 @@ fn make_api_response_with_header_and_stats(
         .unwrap()
 }
 
-async fn handle_rejection(rejection: Rejection) -> Result<Response<String>, Rejection> {
-    let response = if let Some(http_error) = rejection.find::<HTTPError>() {
+/// Whether the request has `X-RESTCOMMANDER-VERBOSE-ERRORS` header or `verbose_errors=true`
+/// query-string parameter.
+fn verbose_errors_filter() -> impl Filter<Extract = (bool,), Error = Infallible> + Clone {
+    warp::header::headers_cloned()
+        .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+        .map(|headers: HeaderMap, query: String| {
+            headers.contains_key("x-restcommander-verbose-errors")
+                || serde_urlencoded::from_str::<Vec<(String, String)>>(&query)
+                    .map(|parameter_list| {
+                        parameter_list.iter().any(|(name, value)| {
+                            name == VERBOSE_ERRORS_QUERY_PARAMETER && value == "true"
+                        })
+                    })
+                    .unwrap_or_default()
+        })
+}
+
+/// Makes the JSON response of a rejection of [`api_filter`] or rejects it again if it is unknown.
+pub async fn handle_rejection(
+    rejection: Rejection,
+    cfg: Arc<ArcSwap<Cfg>>,
+    verbose_errors: bool,
+) -> Result<Response<String>, Rejection> {
+    let response = if let Some(HTTPError::API(HTTPAPIError::CommandRateLimited { retry_after })) =
+        rejection.find::<HTTPError>()
+    {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from(*retry_after));
+        make_api_response_with_headers(
+            Err(HTTPError::API(HTTPAPIError::CommandRateLimited {
+                retry_after: *retry_after,
+            })),
+            Some(headers),
+        )
+    } else if let Some(http_error) = rejection.find::<HTTPError>() {
         make_api_response(Err(http_error.clone()))
     } else if let Some(body_deserialize_error) =
         rejection.find::<warp::filters::body::BodyDeserializeError>()
     {
-        make_api_response(Err(HTTPError::Deserialize(
-            body_deserialize_error.to_string(),
-        )))
+        // Do not leak serde internals to clients unless they ask for them:
+        let error = HTTPError::Deserialize(BODY_DESERIALIZE_ERROR_MESSAGE.to_string());
+        let status_code = error.http_status_code();
+        let mut body = make_api_response_body(Err(error), None, None, None, None);
+        if verbose_errors {
+            let detail = std::error::Error::source(body_deserialize_error)
+                .map(|source| source.to_string())
+                .unwrap_or_else(|| body_deserialize_error.to_string());
+            body.as_object_mut()
+                .unwrap()
+                .insert("detail".to_string(), serde_json::Value::String(detail));
+        };
+        make_api_response_from_body(body, None, status_code)
     } else if let Some(missing_header) = rejection.find::<warp::reject::MissingHeader>() {
         if missing_header.name() == AUTHORIZATION.as_str() {
             let mut headers = HeaderMap::new();
-            headers.insert("WWW-Authenticate", HeaderValue::from_str("Bearer").unwrap());
+            let realm = cfg.load().config_value.server.auth_realm.clone();
+            headers.insert(
+                "WWW-Authenticate",
+                HeaderValue::from_str(format!("Bearer realm={}", quoted_string(&realm)).as_str())
+                    .unwrap_or_else(|_| HeaderValue::from_static("Bearer")),
+            );
             make_api_response_with_headers(
                 Err(HTTPError::Authentication(HTTPAuthenticationError::Required)),
                 Some(headers),
@@ -1496,9 +5210,10 @@ async fn handle_rejection(rejection: Rejection) -> Result<Response<String>, Reje
         };
         return Err(rejection);
     };
+    let max_line_length = cfg.load().config_value.logging.max_line_length;
     trace!(
         response.status = response.status().as_u16(),
-        response.body = response.body().as_str(),
+        response.body = utils::truncate_line(response.body().as_str(), max_line_length).as_ref(),
         response.headers = format!(
             "{:?}",
             response
@@ -1507,7 +5222,11 @@ async fn handle_rejection(rejection: Rejection) -> Result<Response<String>, Reje
                 .fold(Vec::new(), |mut acc, (header_name, header_value)| {
                     acc.push((
                         header_name.to_string(),
-                        header_value.to_str().unwrap_or_default().to_string(),
+                        utils::truncate_line(
+                            header_value.to_str().unwrap_or_default(),
+                            max_line_length,
+                        )
+                        .to_string(),
                     ));
                     acc
                 })
@@ -1518,19 +5237,303 @@ async fn handle_rejection(rejection: Rejection) -> Result<Response<String>, Reje
     Ok(response)
 }
 
-fn http_logging(info: warp::log::Info) {
-    let elapsed = info.elapsed().as_micros() as f64 / 1000000.0;
+/// Removes `WWW-Authenticate` challenge of 401 responses for API clients.
+///
+/// Some tools show a credential dialog for the challenge, So only browsers get it.
+fn remove_authentication_challenge_for_api_clients(
+    request_headers: HeaderMap,
+    reply: impl warp::Reply,
+) -> Response<Body> {
+    let mut response = reply.into_response();
+    if response.status() == StatusCode::UNAUTHORIZED && is_api_client(&request_headers) {
+        response.headers_mut().remove(WWW_AUTHENTICATE);
+    };
+    response
+}
+
+/// Detects clients that send `X-Requested-With` header or prefer JSON over HTML in `Accept` header.
+fn is_api_client(request_headers: &HeaderMap) -> bool {
+    if request_headers.contains_key("X-Requested-With") {
+        return true;
+    };
+    let accept_quality = |accepted_media_type: &str| {
+        request_headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter(|accept| media_type(accept) == accepted_media_type)
+            .map(|accept| {
+                accept
+                    .split(';')
+                    .skip(1)
+                    .find_map(|parameter| {
+                        parameter
+                            .trim()
+                            .strip_prefix("q=")
+                            .and_then(|quality| quality.parse::<f32>().ok())
+                    })
+                    .unwrap_or(1.0)
+            })
+            .fold(0.0, f32::max)
+    };
+    accept_quality("application/json") > accept_quality("text/html")
+}
+
+/// Spawns [serve] and acknowledges its start through `initialize_channel`.
+fn spawn_server<S, I, C>(
+    service: S,
+    incoming: I,
+    shutdown_signal: impl Future<Output = ()> + Send + 'static,
+    cfg: Arc<ArcSwap<Cfg>>,
+    initialize_channel: tokio::sync::mpsc::Sender<()>,
+    running_server: RunningServer,
+    description: String,
+) where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+    I: Stream<Item = std::io::Result<C>> + Send + 'static,
+    C: AsyncRead + AsyncWrite + RemoteAddress + Send + Unpin + 'static,
+{
+    tokio::spawn(async move {
+        initialize_channel.send(()).await.unwrap();
+        serve(service, incoming, shutdown_signal, cfg).await;
+        info!("stopped {}", description);
+        drop(running_server);
+    });
+}
+
+/// Serves HTTP requests of client connections until `shutdown_signal` is resolved.
+///
+/// Remote address of each client is attached to its requests as [ClientAddress] extension and
+/// its verified TLS certificate name as [ClientCertificateName] extension.
+async fn serve<S, I, C>(
+    service: S,
+    incoming: I,
+    shutdown_signal: impl Future<Output = ()>,
+    cfg: Arc<ArcSwap<Cfg>>,
+) where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+    I: Stream<Item = std::io::Result<C>>,
+    C: AsyncRead + AsyncWrite + RemoteAddress + Send + Unpin + 'static,
+{
+    let maybe_timeout = |timeout| {
+        if timeout == 0 {
+            None
+        } else {
+            Some(time::Duration::from_secs(timeout))
+        }
+    };
+    let incoming_cfg = cfg.clone();
+    let incoming = incoming.map_ok(move |connection| {
+        let server_cfg = &incoming_cfg.load().config_value.server;
+        TimeoutStream::new(
+            connection,
+            maybe_timeout(server_cfg.header_read_timeout),
+            maybe_timeout(server_cfg.body_read_timeout),
+            make_client_timeout_response,
+        )
+    });
+    let make_service = make_service_fn(move |connection: &TimeoutStream<C>| {
+        let maybe_remote_address = connection.remote_address();
+        let maybe_client_certificate_name = connection.client_certificate_name();
+        let service = service.clone();
+        let cfg = cfg.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |mut request: Request<Body>| {
+                let mut service = service.clone();
+                let max_line_length = cfg.load().config_value.logging.max_line_length;
+                let start_time = time::Instant::now();
+                let path = request.uri().path().to_string();
+                let request_log_info = HTTPRequestLogInfo::new(
+                    &request,
+                    maybe_client_certificate_name
+                        .as_ref()
+                        .and_then(|name| name.get())
+                        .cloned(),
+                );
+                http_request_logging(
+                    maybe_remote_address,
+                    path.as_str(),
+                    request.headers(),
+                    max_line_length,
+                );
+                if let Some(remote_address) = maybe_remote_address {
+                    request
+                        .extensions_mut()
+                        .insert(ClientAddress(remote_address));
+                };
+                if let Some(name) = maybe_client_certificate_name
+                    .as_ref()
+                    .and_then(|name| name.get())
+                {
+                    request
+                        .extensions_mut()
+                        .insert(ClientCertificateName(name.clone()));
+                };
+                let request_timeout = cfg.load().config_value.server.request_timeout;
+                let log_format = cfg.load().config_value.logging.format.clone();
+                let include_timing = cfg.load().config_value.server.include_timing
+                    || request.uri().query().is_some_and(|query| {
+                        serde_urlencoded::from_str::<Vec<(String, String)>>(query)
+                            .unwrap_or_default()
+                            .iter()
+                            .any(|(key, value)| key == "timing" && value == "true")
+                    });
+                let handle_request = async move {
+                    let response = if request_timeout == 0 {
+                        service.call(request).await?
+                    } else {
+                        let timeout = time::Duration::from_secs(request_timeout);
+                        tokio::time::timeout(timeout, service.call(request))
+                            .await
+                            .unwrap_or_else(|_| {
+                                warn!(
+                                    path = path.as_str(),
+                                    timeout = ?timeout,
+                                    "HTTP request timed out"
+                                );
+                                Ok(make_api_response(Err(HTTPError::API(
+                                    HTTPAPIError::RequestTimeout { timeout },
+                                )))
+                                .map(Body::from))
+                            })?
+                    };
+                    http_logging(
+                        maybe_remote_address,
+                        path.as_str(),
+                        &request_log_info,
+                        response.status(),
+                        response_body_length(&response),
+                        start_time.elapsed(),
+                        &log_format,
+                    );
+                    Ok::<_, Infallible>(response)
+                };
+                async move {
+                    if include_timing {
+                        RESPONSE_TIMING_START_TIME
+                            .scope(start_time, handle_request)
+                            .await
+                    } else {
+                        handle_request.await
+                    }
+                }
+            }))
+        }
+    });
+    if let Err(reason) = warp::hyper::Server::builder(accept::from_stream(incoming))
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown_signal)
+        .await
+    {
+        error!(error = reason.to_string().as_str(), "HTTP server error");
+    };
+}
+
+/// Makes the raw HTTP response of [TimeoutStream] connections that are too slow to send their
+/// request.
+fn make_client_timeout_response(timeout: time::Duration) -> Vec<u8> {
+    let body = serde_json::to_string(&make_api_response_body(
+        Err(HTTPError::API(HTTPAPIError::ClientTimeout { timeout })),
+        None,
+        None,
+        None,
+        None,
+    ))
+    .unwrap();
+    format!(
+        "HTTP/1.1 408 Request Timeout\r\ncontent-type: application/json; charset=utf-8\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+    .into_bytes()
+}
+
+tokio::task_local! {
+    /// Start time of current HTTP request if its API response object should contain timing
+    /// fields.
+    static RESPONSE_TIMING_START_TIME: time::Instant;
+}
+
+/// Remote address of HTTP client that the server attaches to request extensions.
+#[derive(Clone, Copy, Debug)]
+struct ClientAddress(SocketAddr);
+
+/// Common name of verified TLS client certificate that the server attaches to request extensions.
+#[derive(Clone, Debug)]
+struct ClientCertificateName(String);
+
+/// Extracts common name of verified TLS client certificate.
+fn client_certificate_name_filter(
+) -> impl Filter<Extract = (Option<String>,), Error = Infallible> + Copy {
+    warp::ext::optional::<ClientCertificateName>().map(
+        |maybe_client_certificate_name: Option<ClientCertificateName>| {
+            maybe_client_certificate_name.map(|client_certificate_name| client_certificate_name.0)
+        },
+    )
+}
+
+/// Extracts username of the session token of the request (if it has any).
+fn token_username_filter(
+    tokens: AuthTokens,
+) -> impl Filter<Extract = (Option<String>,), Error = Infallible> + Clone {
+    extract_token_filter()
+        .map(move |token: String| {
+            tokens
+                .read()
+                .get(token.as_str())
+                .and_then(|auth_token| auth_token.maybe_username.clone())
+        })
+        .or(warp::any().map(|| None))
+        .unify()
+}
+
+/// Extracts remote address of HTTP client.
+///
+/// Falls back to the connection's remote address if the request is not served via [`setup`]
+/// (e.g. [`api_filter`] is mounted in another warp server).
+fn remote_address_filter() -> impl Filter<Extract = (Option<SocketAddr>,), Error = Infallible> + Copy
+{
+    warp::ext::optional::<ClientAddress>()
+        .and(warp::addr::remote())
+        .map(
+            |maybe_client_address: Option<ClientAddress>, maybe_remote_address| {
+                maybe_client_address
+                    .map(|client_address| client_address.0)
+                    .or(maybe_remote_address)
+            },
+        )
+}
+
+fn http_request_logging(
+    maybe_remote_address: Option<SocketAddr>,
+    path: &str,
+    headers: &HeaderMap,
+    max_line_length: usize,
+) {
     trace!(
-        remote_address = info.remote_addr().unwrap().to_string().as_str(),
-        path = info.path(),
+        remote_address = ?maybe_remote_address,
+        path = path,
         headers = format!(
             "{:?}",
-            info.request_headers().iter().fold(
+            headers.iter().fold(
                 Vec::new(),
                 |mut acc, (header_name, header_value)| {
                     acc.push((
                         header_name.to_string(),
-                        header_value.to_str().unwrap_or_default().to_string(),
+                        utils::truncate_line(
+                            header_value.to_str().unwrap_or_default(),
+                            max_line_length,
+                        )
+                        .to_string(),
                     ));
                     acc
                 }
@@ -1538,11 +5541,344 @@ fn http_logging(info: warp::log::Info) {
         )
         .as_str(),
     );
+}
+
+/// Parts of a request that are needed for its log line after it is handled.
+struct HTTPRequestLogInfo {
+    time: time::SystemTime,
+    method: String,
+    target: String,
+    version: Version,
+    maybe_referer: Option<String>,
+    maybe_user_agent: Option<String>,
+    maybe_user: Option<String>,
+}
+
+impl HTTPRequestLogInfo {
+    fn new(request: &Request<Body>, maybe_user: Option<String>) -> Self {
+        let header = |name| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        Self {
+            time: time::SystemTime::now(),
+            method: request.method().to_string(),
+            target: request
+                .uri()
+                .path_and_query()
+                .map(|path_and_query| path_and_query.to_string())
+                .unwrap_or_else(|| request.uri().path().to_string()),
+            version: request.version(),
+            maybe_referer: header(REFERER),
+            maybe_user_agent: header(USER_AGENT),
+            maybe_user,
+        }
+    }
+
+    /// Makes an Apache/Nginx combined log format line of the request and its response:
+    /// `HOST - USER [TIME] "METHOD TARGET VERSION" STATUS BYTES "REFERER" "USER-AGENT"`
+    fn to_combined_log_line(
+        &self,
+        maybe_remote_address: Option<SocketAddr>,
+        status: StatusCode,
+        maybe_body_length: Option<u64>,
+    ) -> String {
+        let quoted = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+        format!(
+            "{} - {} [{}] \"{} {} {:?}\" {} {} \"{}\" \"{}\"",
+            maybe_remote_address
+                .map(|remote_address| remote_address.ip().to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            self.maybe_user
+                .as_ref()
+                .map(|user| quoted(user))
+                .unwrap_or_else(|| "-".to_string()),
+            combined_log_time(self.time),
+            self.method,
+            quoted(self.target.as_str()),
+            self.version,
+            status.as_u16(),
+            maybe_body_length
+                .filter(|length| *length > 0)
+                .map(|length| length.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            quoted(self.maybe_referer.as_deref().unwrap_or("-")),
+            quoted(self.maybe_user_agent.as_deref().unwrap_or("-")),
+        )
+    }
+}
+
+/// Body length of streamed responses (e.g. static files) is taken from their `Content-Length`.
+fn response_body_length(response: &Response<Body>) -> Option<u64> {
+    HttpBody::size_hint(response.body()).exact().or_else(|| {
+        response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+    })
+}
+
+/// Formats time like `10/Oct/2000:13:55:36 +0000` in UTC.
+fn combined_log_time(time: time::SystemTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    // In form of `2000-10-10T13:55:36Z`:
+    let rfc3339 = humantime::format_rfc3339_seconds(time).to_string();
+    let month = rfc3339[5..7].parse::<usize>().unwrap_or(1);
+    format!(
+        "{}/{}/{}:{} +0000",
+        &rfc3339[8..10],
+        MONTHS[month.clamp(1, 12) - 1],
+        &rfc3339[0..4],
+        &rfc3339[11..19]
+    )
+}
+
+fn http_logging(
+    maybe_remote_address: Option<SocketAddr>,
+    path: &str,
+    request_log_info: &HTTPRequestLogInfo,
+    status: StatusCode,
+    maybe_body_length: Option<u64>,
+    elapsed: time::Duration,
+    format: &CfgLoggingFormat,
+) {
+    if format == &CfgLoggingFormat::Combined {
+        info!(
+            target: logging::ACCESS_LOG_TARGET,
+            "{}",
+            request_log_info.to_combined_log_line(maybe_remote_address, status, maybe_body_length)
+        );
+        return;
+    }
+    let elapsed = elapsed.as_micros() as f64 / 1000000.0;
     info!(
-        remote_address = info.remote_addr().unwrap().to_string().as_str(),
-        path = info.path(),
-        status = info.status().as_u16(),
+        remote_address = maybe_remote_address
+            .map(|remote_address| remote_address.to_string())
+            .unwrap_or_default()
+            .as_str(),
+        path = path,
+        status = status.as_u16(),
+        bytes = maybe_body_length.unwrap_or_default(),
         time = elapsed,
         "Handled HTTP request."
     );
 }
+
+#[cfg(all(test, feature = "www"))]
+mod tests {
+    use super::*;
+
+    fn make_cfg(static_directory: &Path) -> Cfg {
+        let mut config_value = CfgValue::default();
+        config_value.www.enabled = true;
+        config_value.www.static_directory = static_directory.to_path_buf();
+        Cfg {
+            config_value,
+            filename: None,
+            override_filename_list: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn multipart_form_filter_uses_current_upload_max_size() {
+        let body =
+            "--XYZ\r\nContent-Disposition: form-data; name=\"foo\"\r\n\r\nbar\r\n--XYZ--\r\n";
+        let request = || {
+            warp::test::request()
+                .method("POST")
+                .header(CONTENT_TYPE, "multipart/form-data; boundary=XYZ")
+                .body(body)
+        };
+        let mut cfg_value = CfgValue::default();
+        cfg_value.commands.upload_max_size = body.len() as u64 - 1;
+        let cfg = Arc::new(ArcSwap::from_pointee(Cfg {
+            config_value: cfg_value.clone(),
+            filename: None,
+            override_filename_list: Vec::new(),
+        }));
+        let filter = multipart_form_filter(cfg.clone());
+        let rejection = request().filter(&filter).await.unwrap_err();
+        assert!(matches!(
+            rejection.find::<HTTPError>(),
+            Some(HTTPError::API(HTTPAPIError::PayloadTooLarge { .. }))
+        ));
+
+        // Reloaded configuration:
+        cfg_value.commands.upload_max_size = body.len() as u64;
+        cfg.store(Arc::new(Cfg {
+            config_value: cfg_value,
+            filename: None,
+            override_filename_list: Vec::new(),
+        }));
+        assert!(request().filter(&filter).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn idempotency_key_is_kept_per_requester() {
+        let idempotency_keys = IdempotencyKeys::default();
+        let make_key = |maybe_username: Option<&str>| IdempotencyKey {
+            key: "foo".to_string(),
+            command_path: "bar".to_string(),
+            maybe_username: maybe_username.map(ToString::to_string),
+            maybe_client_certificate_name: None,
+        };
+        let run = |body: &str| {
+            let body = body.to_string();
+            async move { Ok(Response::new(body)) }
+        };
+        for (maybe_username, body, expected_body) in [
+            (Some("alice"), "alice", "alice"),
+            (Some("alice"), "alice again", "alice"),
+            (Some("bob"), "bob", "bob"),
+            (None, "anonymous", "anonymous"),
+        ] {
+            let response = run_once_per_idempotency_key(
+                idempotency_keys.clone(),
+                Some(make_key(maybe_username)),
+                60,
+                run(body),
+            )
+            .await
+            .unwrap();
+            assert_eq!(response.body(), expected_body);
+        }
+        assert_eq!(idempotency_keys.read().len(), 3);
+    }
+
+    #[test]
+    fn first_expiring_idempotency_key_is_removed() {
+        let now = time::Instant::now();
+        let make_key = |key: &str| IdempotencyKey {
+            key: key.to_string(),
+            command_path: "bar".to_string(),
+            maybe_username: None,
+            maybe_client_certificate_name: None,
+        };
+        let make_done = |ttl: u64| IdempotencyKeyState::Done {
+            status_code: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: String::new(),
+            expire_time: now + time::Duration::from_secs(ttl),
+        };
+        let mut idempotency_keys = HashMap::new();
+        idempotency_keys.insert(make_key("late"), make_done(20));
+        idempotency_keys.insert(make_key("early"), make_done(10));
+        idempotency_keys.insert(make_key("running"), IdempotencyKeyState::Running);
+        assert!(remove_first_expiring_idempotency_key(&mut idempotency_keys));
+        assert!(!idempotency_keys.contains_key(&make_key("early")));
+        assert!(remove_first_expiring_idempotency_key(&mut idempotency_keys));
+        // Running requests are never removed:
+        assert!(!remove_first_expiring_idempotency_key(
+            &mut idempotency_keys
+        ));
+        assert_eq!(idempotency_keys.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn panicking_handler_does_not_poison_tokens() {
+        let tokens = AuthTokens::default();
+        tokens.write().insert(
+            "foo".to_string(),
+            AuthToken {
+                expire_time: usize::MAX,
+                maybe_username: Some("admin".to_string()),
+                maybe_scopes: None,
+            },
+        );
+        let panicking_tokens = tokens.clone();
+        // A handler that panics while it holds the lock:
+        let result = tokio::spawn(async move {
+            let _tokens = panicking_tokens.write();
+            panic!("handler panicked");
+        })
+        .await;
+        assert!(result.unwrap_err().is_panic());
+
+        let filter = token_username_filter(tokens);
+        let maybe_username = warp::test::request()
+            .header(AUTHORIZATION, "Bearer foo")
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(maybe_username, Some("admin".to_string()));
+    }
+
+    #[test]
+    fn command_path_segments_reject_traversal() {
+        for command_path in [
+            "../../etc/passwd",
+            "foo/../../etc/passwd",
+            "%2e%2e/%2e%2e/etc/passwd",
+            "foo/%2E%2E/bar",
+            "%2e%2e%2f%2e%2e%2fetc%2fpasswd",
+            "./foo",
+            "/etc/passwd",
+            "%2fetc%2fpasswd",
+            "foo%5c..%5cbar",
+        ] {
+            assert!(
+                check_command_path_segments(command_path).is_err(),
+                "{:?} is accepted",
+                command_path
+            );
+        }
+        for command_path in ["foo", "foo/bar", "foo..bar/baz", "%66oo"] {
+            assert!(
+                check_command_path_segments(command_path).is_ok(),
+                "{:?} is rejected",
+                command_path
+            );
+        }
+    }
+
+    #[test]
+    fn quoted_string_escapes_quotes_and_backslashes() {
+        assert_eq!(quoted_string("RestCommander"), r#""RestCommander""#);
+        assert_eq!(quoted_string(r#"my "lab"\1"#), r#""my \"lab\"\\1""#);
+        assert_eq!(quoted_string(""), r#""""#);
+    }
+
+    #[tokio::test]
+    async fn static_external_filter_serves_current_static_directory() {
+        let old_directory = tempfile::tempdir().unwrap();
+        std::fs::write(old_directory.path().join("foo.txt"), "old").unwrap();
+        let new_directory = tempfile::tempdir().unwrap();
+        std::fs::write(new_directory.path().join("foo.txt"), "new content").unwrap();
+        let cfg = Arc::new(ArcSwap::from_pointee(make_cfg(old_directory.path())));
+        let filter = static_external_filter(cfg.clone());
+
+        let response = warp::test::request().path("/foo.txt").reply(&filter).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().as_ref(), b"old");
+        assert!(response.headers().contains_key(LAST_MODIFIED));
+
+        // Reloaded configuration:
+        cfg.store(Arc::new(make_cfg(new_directory.path())));
+        let response = warp::test::request()
+            .path("/foo.txt")
+            .header("range", "bytes=0-2")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.body().as_ref(), b"new");
+
+        let response = warp::test::request()
+            .method("HEAD")
+            .path("/foo.txt")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = warp::test::request()
+            .path("/../foo.txt")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
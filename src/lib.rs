@@ -0,0 +1,24 @@
+//! HTTP REST API layer on top of scripts with a simple web dashboard.
+//!
+//! The `restcommander` binary is built on top of this library. To serve commands from your own
+//! [warp](https://docs.rs/warp) server, Make a [`settings::Cfg`] and load a [`cmd::tree::Command`]
+//! tree from its `commands.root_directory`, Then mount [`http::api_filter`] under any path and
+//! handle its rejections via [`http::handle_rejection`] (see `examples/embed.rs`). To run commands
+//! under your own routes, Combine [`http::authentication_filter`] and
+//! [`http::api_run_command_filter`] with the same [`http::AuthTokens`] that you pass to
+//! [`http::api_filter`].
+
+pub mod cmd;
+pub mod http;
+pub mod logging;
+pub mod report;
+pub mod samples;
+pub mod settings;
+
+mod captcha;
+mod listener;
+mod notification;
+mod select;
+mod utils;
+#[cfg(feature = "www")]
+mod www;
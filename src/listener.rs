@@ -0,0 +1,1110 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::io::BufReader;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use futures_util::{ready, Stream, StreamExt};
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::internal::pemfile;
+use tokio_rustls::rustls::{
+    AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, NoClientAuth,
+    RootCertStore, ServerConfig, Session,
+};
+use tracing::warn;
+
+/// Delay before accepting new connections after an accept error (e.g. too many open files).
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_secs(1);
+/// Maximum time a client has to send its PROXY protocol header after connecting.
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+/// Number of connections with a parsed PROXY protocol header waiting to be served.
+const PROXY_CONNECTION_QUEUE_SIZE: usize = 128;
+/// Maximum length of a PROXY protocol v1 header line including the trailing CRLF.
+const PROXY_V1_HEADER_MAX_LENGTH: usize = 107;
+const PROXY_V1_HEADER_PREFIX: &[u8] = b"PROXY ";
+const PROXY_V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+#[derive(Clone, Debug)]
+pub struct ListenerSettings {
+    pub backlog: u32,
+    pub maybe_keepalive: Option<Duration>,
+    pub maybe_keepalive_interval: Option<Duration>,
+    pub maybe_keepalive_retries: Option<u32>,
+}
+
+/// Client connections that know their remote address.
+pub trait RemoteAddress {
+    fn remote_address(&self) -> Option<SocketAddr>;
+
+    /// Common name of verified TLS client certificate which is set after TLS handshake.
+    fn client_certificate_name(&self) -> Option<Arc<OnceLock<String>>> {
+        None
+    }
+}
+
+impl RemoteAddress for TcpStream {
+    fn remote_address(&self) -> Option<SocketAddr> {
+        self.peer_addr().ok()
+    }
+}
+
+/// Makes a TCP listener with configured backlog.
+pub fn bind(address: SocketAddr, settings: &ListenerSettings) -> io::Result<TcpListener> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    // The same as standard library listeners, So restarting the server does not fail:
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(i32::try_from(settings.backlog).unwrap_or(i32::MAX))?;
+    socket.set_nonblocking(true)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Accepts client connections of the listener forever and sets their TCP options.
+pub fn incoming(
+    listener: TcpListener,
+    settings: ListenerSettings,
+) -> impl Stream<Item = io::Result<TcpStream>> {
+    let maybe_keepalive = settings.maybe_keepalive.map(|keepalive| {
+        let keepalive = TcpKeepalive::new().with_time(keepalive);
+        #[cfg(any(
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "fuchsia",
+            target_os = "linux",
+            target_os = "netbsd",
+            target_vendor = "apple",
+            windows,
+        ))]
+        let keepalive = match settings.maybe_keepalive_interval {
+            Some(interval) => keepalive.with_interval(interval),
+            None => keepalive,
+        };
+        #[cfg(any(
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "fuchsia",
+            target_os = "linux",
+            target_os = "netbsd",
+            target_vendor = "apple",
+        ))]
+        let keepalive = match settings.maybe_keepalive_retries {
+            Some(retries) => keepalive.with_retries(retries),
+            None => keepalive,
+        };
+        keepalive
+    });
+    futures_util::stream::unfold(
+        (listener, maybe_keepalive),
+        |(listener, maybe_keepalive)| async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        set_stream_options(&stream, &maybe_keepalive);
+                        return Some((Ok(stream), (listener, maybe_keepalive)));
+                    }
+                    // Client closed the connection before we accept it:
+                    Err(reason)
+                        if matches!(
+                            reason.kind(),
+                            io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset
+                        ) => {}
+                    Err(reason) => {
+                        warn!(
+                            error = reason.to_string().as_str(),
+                            delay = ?ACCEPT_ERROR_DELAY,
+                            "Could not accept new connection"
+                        );
+                        tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                    }
+                }
+            }
+        },
+    )
+}
+
+fn set_stream_options(stream: &TcpStream, maybe_keepalive: &Option<TcpKeepalive>) {
+    if let Err(reason) = stream.set_nodelay(true) {
+        warn!(
+            error = reason.to_string().as_str(),
+            "Could not set TCP_NODELAY option of connection"
+        );
+    };
+    if let Some(keepalive) = maybe_keepalive {
+        if let Err(reason) = SockRef::from(stream).set_tcp_keepalive(keepalive) {
+            warn!(
+                error = reason.to_string().as_str(),
+                "Could not set TCP keepalive options of connection"
+            );
+        };
+    };
+}
+
+/// TCP client connection that came through a PROXY protocol speaking load balancer.
+pub struct ProxiedTcpStream {
+    stream: TcpStream,
+    maybe_remote_address: Option<SocketAddr>,
+}
+
+impl RemoteAddress for ProxiedTcpStream {
+    fn remote_address(&self) -> Option<SocketAddr> {
+        self.maybe_remote_address
+    }
+}
+
+impl AsyncRead for ProxiedTcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ProxiedTcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+/// Reads PROXY protocol (v1 or v2) header of every accepted connection.
+///
+/// Headers are read in their own tasks, So a slow client does not block accepting other
+/// connections. Connections without a valid header are closed.
+pub fn proxy_protocol_incoming(
+    incoming: impl Stream<Item = io::Result<TcpStream>> + Send + 'static,
+) -> impl Stream<Item = io::Result<ProxiedTcpStream>> {
+    let (sender, receiver) = tokio::sync::mpsc::channel(PROXY_CONNECTION_QUEUE_SIZE);
+    tokio::spawn(async move {
+        futures_util::pin_mut!(incoming);
+        loop {
+            // Server drops the receiver when it stops, So we stop accepting too:
+            let mut stream = tokio::select! {
+                _ = sender.closed() => break,
+                maybe_stream = incoming.next() => match maybe_stream {
+                    Some(Ok(stream)) => stream,
+                    Some(Err(reason)) => {
+                        if sender.send(Err(reason)).await.is_err() {
+                            break;
+                        };
+                        continue;
+                    }
+                    None => break,
+                },
+            };
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let maybe_peer_address = stream.remote_address();
+                match tokio::time::timeout(PROXY_HEADER_TIMEOUT, read_proxy_header(&mut stream))
+                    .await
+                {
+                    Ok(Ok(maybe_remote_address)) => {
+                        let _ = sender
+                            .send(Ok(ProxiedTcpStream {
+                                stream,
+                                maybe_remote_address: maybe_remote_address.or(maybe_peer_address),
+                            }))
+                            .await;
+                    }
+                    Ok(Err(reason)) => warn!(
+                        peer_address = ?maybe_peer_address,
+                        error = reason.to_string().as_str(),
+                        "Could not read PROXY protocol header of connection"
+                    ),
+                    Err(_) => warn!(
+                        peer_address = ?maybe_peer_address,
+                        timeout = ?PROXY_HEADER_TIMEOUT,
+                        "Timed out reading PROXY protocol header of connection"
+                    ),
+                };
+            });
+        }
+    });
+    futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver
+            .recv()
+            .await
+            .map(|maybe_stream| (maybe_stream, receiver))
+    })
+}
+
+/// Reads PROXY protocol header and returns the source address it contains.
+///
+/// `None` means the header does not contain any address (e.g. health checks of the load
+/// balancer itself) and the peer address should be used.
+async fn read_proxy_header(stream: &mut TcpStream) -> io::Result<Option<SocketAddr>> {
+    let mut header = vec![0; PROXY_V1_HEADER_PREFIX.len()];
+    stream.read_exact(&mut header).await?;
+    if header == PROXY_V1_HEADER_PREFIX {
+        // Version 1 header is a single line, So we read it byte by byte to not consume
+        // any byte of the HTTP request:
+        while !header.ends_with(b"\r\n") {
+            if header.len() == PROXY_V1_HEADER_MAX_LENGTH {
+                return Err(invalid_proxy_header("too long version 1 header"));
+            };
+            header.push(stream.read_u8().await?);
+        }
+        parse_proxy_v1_header(&header[..header.len() - 2])
+    } else if header == PROXY_V2_SIGNATURE[..PROXY_V1_HEADER_PREFIX.len()] {
+        header.resize(PROXY_V2_SIGNATURE.len() + 4, 0);
+        stream
+            .read_exact(&mut header[PROXY_V1_HEADER_PREFIX.len()..])
+            .await?;
+        if &header[..PROXY_V2_SIGNATURE.len()] != PROXY_V2_SIGNATURE {
+            return Err(invalid_proxy_header("invalid version 2 signature"));
+        };
+        let version_and_command = header[12];
+        let family_and_protocol = header[13];
+        let mut addresses = vec![0; u16::from_be_bytes([header[14], header[15]]) as usize];
+        stream.read_exact(&mut addresses).await?;
+        parse_proxy_v2_addresses(version_and_command, family_and_protocol, &addresses)
+    } else {
+        Err(invalid_proxy_header("unknown header"))
+    }
+}
+
+fn parse_proxy_v1_header(header: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let header = std::str::from_utf8(header)
+        .map_err(|_| invalid_proxy_header("non UTF-8 version 1 header"))?;
+    let field_list: Vec<&str> = header.split(' ').collect();
+    match field_list.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", source_address, _, source_port, _] => {
+            let source_address = source_address
+                .parse::<IpAddr>()
+                .map_err(|_| invalid_proxy_header("invalid version 1 source address"))?;
+            let source_port = source_port
+                .parse::<u16>()
+                .map_err(|_| invalid_proxy_header("invalid version 1 source port"))?;
+            Ok(Some(SocketAddr::new(source_address, source_port)))
+        }
+        _ => Err(invalid_proxy_header("invalid version 1 header")),
+    }
+}
+
+fn parse_proxy_v2_addresses(
+    version_and_command: u8,
+    family_and_protocol: u8,
+    addresses: &[u8],
+) -> io::Result<Option<SocketAddr>> {
+    if version_and_command >> 4 != 2 {
+        return Err(invalid_proxy_header("unsupported version 2 version"));
+    };
+    match version_and_command & 0x0F {
+        // LOCAL command (e.g. health checks of the load balancer itself):
+        0 => return Ok(None),
+        // PROXY command:
+        1 => (),
+        _ => return Err(invalid_proxy_header("unsupported version 2 command")),
+    };
+    match family_and_protocol >> 4 {
+        // IPv4:
+        1 if addresses.len() >= 12 => {
+            let mut source_address = [0; 4];
+            source_address.copy_from_slice(&addresses[..4]);
+            Ok(Some(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::from(source_address)),
+                u16::from_be_bytes([addresses[8], addresses[9]]),
+            )))
+        }
+        // IPv6:
+        2 if addresses.len() >= 36 => {
+            let mut source_address = [0; 16];
+            source_address.copy_from_slice(&addresses[..16]);
+            Ok(Some(SocketAddr::new(
+                IpAddr::V6(Ipv6Addr::from(source_address)),
+                u16::from_be_bytes([addresses[32], addresses[33]]),
+            )))
+        }
+        1 | 2 => Err(invalid_proxy_header("too short version 2 addresses")),
+        // UNSPEC and UNIX families:
+        _ => Ok(None),
+    }
+}
+
+fn invalid_proxy_header(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid PROXY protocol header: {}", message),
+    )
+}
+
+/// TLS client certificate verification.
+#[derive(Clone, Debug)]
+pub struct TlsClientAuth {
+    /// PEM file of CA certificates that client certificates should be signed by.
+    pub ca_file: std::path::PathBuf,
+    /// Reject clients without a certificate in TLS handshake.
+    pub required: bool,
+}
+
+/// Loads TLS certificate chain and private key (PKCS8 or RSA) PEM files.
+pub fn tls_config(
+    cert_file: &Path,
+    key_file: &Path,
+    maybe_client_auth: Option<&TlsClientAuth>,
+) -> Result<Arc<ServerConfig>, String> {
+    let cert_file_content = fs::read(cert_file)
+        .map_err(|reason| format!("could not read TLS cert file {:?}: {}", cert_file, reason))?;
+    let cert_list = pemfile::certs(&mut BufReader::new(cert_file_content.as_slice()))
+        .map_err(|_| format!("could not decode TLS cert file {:?}", cert_file))?;
+    if cert_list.is_empty() {
+        return Err(format!(
+            "could not find any PEM certificate in TLS cert file {:?}",
+            cert_file
+        ));
+    };
+    let key_file_content = fs::read(key_file)
+        .map_err(|reason| format!("could not read TLS key file {:?}: {}", key_file, reason))?;
+    let mut key_list = pemfile::pkcs8_private_keys(&mut key_file_content.as_slice())
+        .map_err(|_| format!("could not decode TLS key file {:?}", key_file))?;
+    if key_list.is_empty() {
+        key_list = pemfile::rsa_private_keys(&mut key_file_content.as_slice())
+            .map_err(|_| format!("could not decode TLS key file {:?}", key_file))?;
+    };
+    if key_list.is_empty() {
+        return Err(format!(
+            "could not find any PEM private key in TLS key file {:?}",
+            key_file
+        ));
+    };
+    let client_cert_verifier = if let Some(client_auth) = maybe_client_auth {
+        let ca_file_content = fs::read(&client_auth.ca_file).map_err(|reason| {
+            format!(
+                "could not read TLS client CA file {:?}: {}",
+                client_auth.ca_file, reason
+            )
+        })?;
+        let mut root_cert_store = RootCertStore::empty();
+        match root_cert_store.add_pem_file(&mut BufReader::new(ca_file_content.as_slice())) {
+            Ok((added, _)) if added > 0 => (),
+            _ => {
+                return Err(format!(
+                    "could not find any certificate in TLS client CA file {:?}",
+                    client_auth.ca_file
+                ))
+            }
+        };
+        if client_auth.required {
+            AllowAnyAuthenticatedClient::new(root_cert_store)
+        } else {
+            AllowAnyAnonymousOrAuthenticatedClient::new(root_cert_store)
+        }
+    } else {
+        NoClientAuth::new()
+    };
+    let mut config = ServerConfig::new(client_cert_verifier);
+    config
+        .set_single_cert(cert_list, key_list.remove(0))
+        .map_err(|reason| {
+            format!(
+                "TLS key file {:?} does not match TLS cert file {:?}: {}",
+                key_file, cert_file, reason
+            )
+        })?;
+    config.set_protocols(&["h2".into(), "http/1.1".into()]);
+    Ok(Arc::new(config))
+}
+
+enum TlsConnectionState<S> {
+    Handshaking(tokio_rustls::Accept<S>),
+    Streaming(tokio_rustls::server::TlsStream<S>),
+}
+
+/// TLS client connection that does its handshake on the first read or write.
+///
+/// So a slow client does not block accepting other connections.
+pub struct TlsConnection<S = TcpStream> {
+    state: TlsConnectionState<S>,
+    maybe_remote_address: Option<SocketAddr>,
+    client_certificate_name: Arc<OnceLock<String>>,
+}
+
+impl<S: AsyncRead + AsyncWrite + RemoteAddress + Unpin> TlsConnection<S> {
+    pub fn new(stream: S, config: Arc<ServerConfig>) -> Self {
+        let maybe_remote_address = stream.remote_address();
+        Self {
+            state: TlsConnectionState::Handshaking(
+                tokio_rustls::TlsAcceptor::from(config).accept(stream),
+            ),
+            maybe_remote_address,
+            client_certificate_name: Arc::new(OnceLock::new()),
+        }
+    }
+
+    fn poll_handshake(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<&mut tokio_rustls::server::TlsStream<S>>> {
+        if let TlsConnectionState::Handshaking(ref mut accept) = self.state {
+            let stream = ready!(Pin::new(accept).poll(cx))?;
+            if let Some(name) = stream
+                .get_ref()
+                .1
+                .get_peer_certificates()
+                .and_then(|cert_list| cert_list.first().cloned())
+                .and_then(|cert| certificate_common_name(&cert.0))
+            {
+                let _ = self.client_certificate_name.set(name);
+            };
+            self.state = TlsConnectionState::Streaming(stream);
+        };
+        match self.state {
+            TlsConnectionState::Streaming(ref mut stream) => Poll::Ready(Ok(stream)),
+            TlsConnectionState::Handshaking(_) => unreachable!(),
+        }
+    }
+}
+
+impl<S> RemoteAddress for TlsConnection<S> {
+    fn remote_address(&self) -> Option<SocketAddr> {
+        self.maybe_remote_address
+    }
+
+    fn client_certificate_name(&self) -> Option<Arc<OnceLock<String>>> {
+        Some(self.client_certificate_name.clone())
+    }
+}
+
+/// Finds subject common name (CN) of a DER encoded X.509 certificate.
+fn certificate_common_name(der: &[u8]) -> Option<String> {
+    const COMMON_NAME_OID: &[u8] = &[0x55, 0x04, 0x03];
+    let (_, certificate, _) = der_element(der)?;
+    let (_, tbs_certificate, _) = der_element(certificate)?;
+    let mut rest = tbs_certificate;
+    let (tag, _, after_version) = der_element(rest)?;
+    // Explicit `[0]` version is optional:
+    if tag == 0xa0 {
+        rest = after_version;
+    };
+    // Skip serial number, signature algorithm, issuer and validity:
+    for _ in 0..4 {
+        rest = der_element(rest)?.2;
+    }
+    let (_, mut subject, _) = der_element(rest)?;
+    while !subject.is_empty() {
+        let (_, mut relative_name, after_relative_name) = der_element(subject)?;
+        while !relative_name.is_empty() {
+            let (_, attribute, after_attribute) = der_element(relative_name)?;
+            let (_, oid, after_oid) = der_element(attribute)?;
+            if oid == COMMON_NAME_OID {
+                let (_, value, _) = der_element(after_oid)?;
+                return String::from_utf8(value.to_vec()).ok();
+            };
+            relative_name = after_attribute;
+        }
+        subject = after_relative_name;
+    }
+    None
+}
+
+/// Splits the first DER element to its tag, content and the rest of input.
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *input.first()?;
+    let first_length_byte = *input.get(1)? as usize;
+    let (length, header_length) = if first_length_byte < 0x80 {
+        (first_length_byte, 2)
+    } else {
+        let length_byte_count = first_length_byte & 0x7f;
+        if length_byte_count == 0 || length_byte_count > 4 {
+            return None;
+        };
+        let length = input
+            .get(2..2 + length_byte_count)?
+            .iter()
+            .fold(0, |length, byte| (length << 8) | *byte as usize);
+        (length, 2 + length_byte_count)
+    };
+    let content = input.get(header_length..header_length + length)?;
+    Some((tag, content, &input[header_length + length..]))
+}
+
+impl<S: AsyncRead + AsyncWrite + RemoteAddress + Unpin> AsyncRead for TlsConnection<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let stream = ready!(self.get_mut().poll_handshake(cx))?;
+        Pin::new(stream).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + RemoteAddress + Unpin> AsyncWrite for TlsConnection<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let stream = ready!(self.get_mut().poll_handshake(cx))?;
+        Pin::new(stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut().state {
+            TlsConnectionState::Handshaking(_) => Poll::Ready(Ok(())),
+            TlsConnectionState::Streaming(ref mut stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut().state {
+            TlsConnectionState::Handshaking(_) => Poll::Ready(Ok(())),
+            TlsConnectionState::Streaming(ref mut stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Maximum buffered length of request or response headers (or a chunk-size line) to find their
+/// end and body length.
+const TIMEOUT_STREAM_MAX_HEAD_LENGTH: usize = 65536;
+const HTTP2_PREFACE_PREFIX: &[u8] = b"PRI * HTTP/2.0";
+
+/// Client connection that is answered with a `408` response and closed if the client sends
+/// request headers or body too slowly (e.g. slowloris attacks).
+///
+/// Request headers should be received in `maybe_header_timeout` after the server starts waiting
+/// for them, and each read of request body should not wait more than `maybe_body_timeout`.
+/// The server only waits for request headers after it has answered all received requests, So
+/// clients that wait for a response (e.g. of a long-running command) are not timed out.
+/// Connections that time out before sending any byte of the next request are closed without
+/// response. HTTP/2 and upgraded connections are not checked.
+pub struct TimeoutStream<S> {
+    stream: S,
+    maybe_header_timeout: Option<Duration>,
+    maybe_body_timeout: Option<Duration>,
+    make_timeout_response: fn(Duration) -> Vec<u8>,
+    state: TimeoutStreamState,
+    tracker: HTTP1Tracker,
+    maybe_deadline: Option<Pin<Box<tokio::time::Sleep>>>,
+    /// Pending read that should be polled again to start the timeout once the server waits for
+    /// the client.
+    maybe_read_waker: Option<Waker>,
+}
+
+enum TimeoutStreamState {
+    /// HTTP/1 requests and responses are tracked.
+    Checked,
+    Unchecked,
+    /// Timeout response and the number of its bytes that are written.
+    TimedOut(Vec<u8>, usize),
+    Closed,
+}
+
+impl<S: AsyncRead + AsyncWrite + RemoteAddress + Unpin> TimeoutStream<S> {
+    pub fn new(
+        stream: S,
+        maybe_header_timeout: Option<Duration>,
+        maybe_body_timeout: Option<Duration>,
+        make_timeout_response: fn(Duration) -> Vec<u8>,
+    ) -> Self {
+        Self {
+            stream,
+            maybe_header_timeout,
+            maybe_body_timeout,
+            make_timeout_response,
+            state: TimeoutStreamState::Checked,
+            tracker: HTTP1Tracker::new(),
+            maybe_deadline: None,
+            maybe_read_waker: None,
+        }
+    }
+
+    fn maybe_timeout(&self) -> Option<Duration> {
+        if !matches!(self.state, TimeoutStreamState::Checked) {
+            return None;
+        };
+        match self.tracker.awaiting() {
+            HTTP1Awaiting::NextRequest | HTTP1Awaiting::RequestHead => self.maybe_header_timeout,
+            HTTP1Awaiting::RequestBody => self.maybe_body_timeout,
+            HTTP1Awaiting::Nothing => None,
+        }
+    }
+}
+
+/// Follows HTTP/1 messages of a connection to find out what the server is waiting for.
+#[derive(Debug)]
+struct HTTP1Tracker {
+    request: HTTP1MessageState,
+    response: HTTP1MessageState,
+    /// Whether received requests that are not answered yet are `HEAD` requests (their responses
+    /// have no body).
+    pending_request_list: VecDeque<bool>,
+}
+
+#[derive(Debug, PartialEq)]
+enum HTTP1MessageState {
+    /// Buffered bytes of headers.
+    Head(Vec<u8>),
+    /// Remaining bytes of body.
+    Body(u64),
+    ChunkedBody(HTTP1ChunkState),
+}
+
+#[derive(Debug, PartialEq)]
+enum HTTP1ChunkState {
+    /// Buffered bytes of a chunk-size line.
+    Size(Vec<u8>),
+    /// Remaining bytes of chunk data and its CRLF.
+    Data(u64),
+    /// Buffered bytes of a trailer line after the last chunk.
+    Trailer(Vec<u8>),
+}
+
+/// What the server is waiting for from the client.
+#[derive(Debug, PartialEq)]
+enum HTTP1Awaiting {
+    /// Next request of an idle connection.
+    NextRequest,
+    /// Rest of request headers.
+    RequestHead,
+    RequestBody,
+    /// Nothing from the client (e.g. the server is handling a request).
+    Nothing,
+}
+
+/// Progress of reading a message body.
+enum HTTP1BodyProgress {
+    /// All bytes belong to the body.
+    Partial,
+    /// Body ends after this number of bytes.
+    Done(usize),
+    Invalid,
+}
+
+impl HTTP1Tracker {
+    fn new() -> Self {
+        Self {
+            request: HTTP1MessageState::Head(Vec::new()),
+            response: HTTP1MessageState::Head(Vec::new()),
+            pending_request_list: VecDeque::new(),
+        }
+    }
+
+    fn awaiting(&self) -> HTTP1Awaiting {
+        match self.request {
+            HTTP1MessageState::Head(ref head) => {
+                if !self.pending_request_list.is_empty() || self.is_responding() {
+                    HTTP1Awaiting::Nothing
+                } else if head.is_empty() {
+                    HTTP1Awaiting::NextRequest
+                } else {
+                    HTTP1Awaiting::RequestHead
+                }
+            }
+            _ => HTTP1Awaiting::RequestBody,
+        }
+    }
+
+    /// Whether a part of a response is written.
+    fn is_responding(&self) -> bool {
+        self.response != HTTP1MessageState::Head(Vec::new())
+    }
+
+    /// Follows received bytes. Returns `false` if the connection can not be tracked anymore (e.g.
+    /// it is HTTP/2).
+    fn on_read(&mut self, mut bytes: &[u8]) -> bool {
+        while !bytes.is_empty() {
+            if let HTTP1MessageState::Head(ref mut head) = self.request {
+                let search_start = head.len().saturating_sub(3);
+                head.extend_from_slice(bytes);
+                if head.starts_with(HTTP2_PREFACE_PREFIX) {
+                    return false;
+                };
+                let head_end = match find_head_end(head, search_start) {
+                    Some(head_end) => head_end,
+                    None => return head.len() <= TIMEOUT_STREAM_MAX_HEAD_LENGTH,
+                };
+                // Bytes after headers belong to the body or the next (pipelined) request:
+                bytes = &bytes[bytes.len() - (head.len() - head_end)..];
+                let head = &head[..head_end];
+                self.pending_request_list
+                    .push_back(head.starts_with(b"HEAD "));
+                // Requests without length have no body:
+                self.request = body_state(head).unwrap_or(HTTP1MessageState::Head(Vec::new()));
+                continue;
+            };
+            match self.request.consume_body(bytes) {
+                HTTP1BodyProgress::Partial => break,
+                HTTP1BodyProgress::Done(length) => {
+                    bytes = &bytes[length..];
+                    self.request = HTTP1MessageState::Head(Vec::new());
+                }
+                HTTP1BodyProgress::Invalid => return false,
+            }
+        }
+        true
+    }
+
+    /// Follows written bytes. Returns `false` if the connection can not be tracked anymore (e.g.
+    /// it is upgraded).
+    fn on_write(&mut self, mut bytes: &[u8]) -> bool {
+        while !bytes.is_empty() {
+            if let HTTP1MessageState::Head(ref mut head) = self.response {
+                let search_start = head.len().saturating_sub(3);
+                head.extend_from_slice(bytes);
+                let head_end = match find_head_end(head, search_start) {
+                    Some(head_end) => head_end,
+                    None => return head.len() <= TIMEOUT_STREAM_MAX_HEAD_LENGTH,
+                };
+                bytes = &bytes[bytes.len() - (head.len() - head_end)..];
+                let head = &head[..head_end];
+                let status_code = match String::from_utf8_lossy(head)
+                    .split(' ')
+                    .nth(1)
+                    .and_then(|status_code| status_code.parse::<u16>().ok())
+                {
+                    Some(status_code) if status_code != 101 => status_code,
+                    _ => return false,
+                };
+                // Interim responses (e.g. `100 Continue`) are followed by the final one:
+                if status_code < 200 {
+                    self.response = HTTP1MessageState::Head(Vec::new());
+                    continue;
+                };
+                let is_head_request = self.pending_request_list.pop_front().unwrap_or_default();
+                self.response = if is_head_request || status_code == 204 || status_code == 304 {
+                    HTTP1MessageState::Head(Vec::new())
+                } else {
+                    match body_state(head) {
+                        Some(state) => state,
+                        // Body ends when the connection is closed:
+                        None => return false,
+                    }
+                };
+                continue;
+            };
+            match self.response.consume_body(bytes) {
+                HTTP1BodyProgress::Partial => break,
+                HTTP1BodyProgress::Done(length) => {
+                    bytes = &bytes[length..];
+                    self.response = HTTP1MessageState::Head(Vec::new());
+                }
+                HTTP1BodyProgress::Invalid => return false,
+            }
+        }
+        true
+    }
+}
+
+impl HTTP1MessageState {
+    fn consume_body(&mut self, bytes: &[u8]) -> HTTP1BodyProgress {
+        match self {
+            Self::Head(_) => HTTP1BodyProgress::Invalid,
+            Self::Body(ref mut remaining) => {
+                let length = (*remaining).min(bytes.len() as u64);
+                *remaining -= length;
+                if *remaining == 0 {
+                    HTTP1BodyProgress::Done(length as usize)
+                } else {
+                    HTTP1BodyProgress::Partial
+                }
+            }
+            Self::ChunkedBody(ref mut chunk_state) => {
+                let mut index = 0;
+                while index < bytes.len() {
+                    match chunk_state {
+                        HTTP1ChunkState::Data(ref mut remaining) => {
+                            let length = (*remaining).min((bytes.len() - index) as u64);
+                            *remaining -= length;
+                            index += length as usize;
+                            if *remaining == 0 {
+                                *chunk_state = HTTP1ChunkState::Size(Vec::new());
+                            };
+                        }
+                        HTTP1ChunkState::Size(ref mut line)
+                        | HTTP1ChunkState::Trailer(ref mut line) => {
+                            let line_end =
+                                match bytes[index..].iter().position(|byte| *byte == b'\n') {
+                                    Some(position) => index + position + 1,
+                                    None => {
+                                        line.extend_from_slice(&bytes[index..]);
+                                        if line.len() > TIMEOUT_STREAM_MAX_HEAD_LENGTH {
+                                            return HTTP1BodyProgress::Invalid;
+                                        };
+                                        return HTTP1BodyProgress::Partial;
+                                    }
+                                };
+                            line.extend_from_slice(&bytes[index..line_end]);
+                            index = line_end;
+                            let line = String::from_utf8_lossy(line).trim().to_string();
+                            if let HTTP1ChunkState::Trailer(_) = chunk_state {
+                                if line.is_empty() {
+                                    return HTTP1BodyProgress::Done(index);
+                                };
+                                *chunk_state = HTTP1ChunkState::Trailer(Vec::new());
+                                continue;
+                            };
+                            // Chunk extensions are after `;`:
+                            let size = line.split(';').next().unwrap_or_default().trim();
+                            *chunk_state = match u64::from_str_radix(size, 16) {
+                                Ok(0) => HTTP1ChunkState::Trailer(Vec::new()),
+                                Ok(size) => HTTP1ChunkState::Data(size.saturating_add(2)),
+                                Err(_) => return HTTP1BodyProgress::Invalid,
+                            };
+                        }
+                    }
+                }
+                HTTP1BodyProgress::Partial
+            }
+        }
+    }
+}
+
+/// Returns the index after the empty line that ends headers if it is found after `search_start`.
+fn find_head_end(head: &[u8], search_start: usize) -> Option<usize> {
+    head[search_start..]
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|index| search_start + index + 4)
+}
+
+/// Finds out how message body is sent from its headers. Returns `None` if it has no length.
+fn body_state(head: &[u8]) -> Option<HTTP1MessageState> {
+    let head = String::from_utf8_lossy(head).to_lowercase();
+    let header_value = |name: &str| {
+        head.split("\r\n")
+            .filter_map(|line| line.split_once(':'))
+            .find(|(header_name, _)| header_name.trim() == name)
+            .map(|(_, value)| value.trim().to_string())
+    };
+    if header_value("transfer-encoding").is_some_and(|value| value.contains("chunked")) {
+        return Some(HTTP1MessageState::ChunkedBody(HTTP1ChunkState::Size(
+            Vec::new(),
+        )));
+    };
+    header_value("content-length")
+        .and_then(|value| value.parse::<u64>().ok())
+        .map(|length| {
+            if length > 0 {
+                HTTP1MessageState::Body(length)
+            } else {
+                HTTP1MessageState::Head(Vec::new())
+            }
+        })
+}
+
+impl<S> RemoteAddress for TimeoutStream<S>
+where
+    S: RemoteAddress,
+{
+    fn remote_address(&self) -> Option<SocketAddr> {
+        self.stream.remote_address()
+    }
+
+    fn client_certificate_name(&self) -> Option<Arc<OnceLock<String>>> {
+        self.stream.client_certificate_name()
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + RemoteAddress + Unpin> AsyncRead for TimeoutStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            match this.state {
+                TimeoutStreamState::Closed => return Poll::Ready(Ok(())),
+                TimeoutStreamState::TimedOut(ref response, ref mut written) => {
+                    while *written < response.len() {
+                        *written += ready!(
+                            Pin::new(&mut this.stream).poll_write(cx, &response[*written..])
+                        )?;
+                    }
+                    ready!(Pin::new(&mut this.stream).poll_flush(cx))?;
+                    this.state = TimeoutStreamState::Closed;
+                    continue;
+                }
+                _ => (),
+            };
+            let filled_length = buf.filled().len();
+            match Pin::new(&mut this.stream).poll_read(cx, buf) {
+                Poll::Ready(Ok(())) => {
+                    if let TimeoutStreamState::Checked = this.state {
+                        let awaiting = this.tracker.awaiting();
+                        if !this.tracker.on_read(&buf.filled()[filled_length..]) {
+                            this.state = TimeoutStreamState::Unchecked;
+                        };
+                        // Each read of request body has its own timeout:
+                        if awaiting == HTTP1Awaiting::RequestBody
+                            || awaiting != this.tracker.awaiting()
+                        {
+                            this.maybe_deadline = None;
+                        };
+                    };
+                    return Poll::Ready(Ok(()));
+                }
+                Poll::Ready(Err(reason)) => return Poll::Ready(Err(reason)),
+                Poll::Pending => (),
+            };
+            let timeout = match this.maybe_timeout() {
+                Some(timeout) => timeout,
+                None => {
+                    this.maybe_read_waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            };
+            let deadline = this
+                .maybe_deadline
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+            ready!(deadline.as_mut().poll(cx));
+            this.maybe_deadline = None;
+            this.state = if this.tracker.awaiting() == HTTP1Awaiting::NextRequest {
+                TimeoutStreamState::Closed
+            } else {
+                warn!(
+                    remote_address = ?this.stream.remote_address(),
+                    timeout = ?timeout,
+                    "Client is too slow to send its request"
+                );
+                // A response can not be put in the middle of another one:
+                if this.tracker.is_responding() {
+                    TimeoutStreamState::Closed
+                } else {
+                    TimeoutStreamState::TimedOut((this.make_timeout_response)(timeout), 0)
+                }
+            };
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + RemoteAddress + Unpin> AsyncWrite for TimeoutStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        match this.state {
+            // The client already got the timeout response:
+            TimeoutStreamState::TimedOut(..) | TimeoutStreamState::Closed => {
+                return Poll::Ready(Ok(buf.len()))
+            }
+            _ => (),
+        };
+        let written = ready!(Pin::new(&mut this.stream).poll_write(cx, buf))?;
+        if let TimeoutStreamState::Checked = this.state {
+            let awaiting = this.tracker.awaiting();
+            if !this.tracker.on_write(&buf[..written]) {
+                this.state = TimeoutStreamState::Unchecked;
+            };
+            // e.g. The server has answered all requests and waits for the next one:
+            if awaiting != this.tracker.awaiting() {
+                this.maybe_deadline = None;
+                if let Some(waker) = this.maybe_read_waker.take() {
+                    waker.wake();
+                };
+            };
+        };
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interim_response_does_not_end_request() {
+        let mut tracker = HTTP1Tracker::new();
+        assert!(tracker.on_read(
+            b"POST /api/run/foo HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n"
+        ));
+        assert_eq!(tracker.awaiting(), HTTP1Awaiting::RequestBody);
+        assert!(tracker.on_write(b"HTTP/1.1 100 Continue\r\n\r\n"));
+        assert_eq!(tracker.awaiting(), HTTP1Awaiting::RequestBody);
+        assert!(tracker.on_read(b"hello"));
+        assert_eq!(tracker.awaiting(), HTTP1Awaiting::Nothing);
+        assert!(tracker.on_write(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n"));
+        assert_eq!(tracker.awaiting(), HTTP1Awaiting::Nothing);
+        assert!(tracker.on_write(b"{}"));
+        assert_eq!(tracker.awaiting(), HTTP1Awaiting::NextRequest);
+    }
+
+    #[test]
+    fn handled_request_is_not_timed_out() {
+        let mut tracker = HTTP1Tracker::new();
+        assert!(tracker.on_read(b"GET /api/run/slow HTTP/1.1\r\nHost: x\r\n\r\n"));
+        // The server is running the command:
+        assert_eq!(tracker.awaiting(), HTTP1Awaiting::Nothing);
+        assert!(tracker.on_write(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n"));
+        assert!(tracker.on_write(b"2\r\n{}\r\n"));
+        assert_eq!(tracker.awaiting(), HTTP1Awaiting::Nothing);
+        assert!(tracker.is_responding());
+        assert!(tracker.on_write(b"0\r\n\r\n"));
+        assert_eq!(tracker.awaiting(), HTTP1Awaiting::NextRequest);
+        assert!(!tracker.is_responding());
+    }
+
+    #[test]
+    fn pipelined_requests_are_tracked() {
+        let mut tracker = HTTP1Tracker::new();
+        assert!(tracker.on_read(
+            b"POST /a HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}HEAD /b HTTP/1.1\r\n\r\nGET /c HT"
+        ));
+        assert_eq!(tracker.pending_request_list, VecDeque::from([false, true]));
+        assert!(tracker.on_write(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}"));
+        // Response of `HEAD` request has no body:
+        assert!(tracker.on_write(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n"));
+        assert_eq!(tracker.awaiting(), HTTP1Awaiting::RequestHead);
+        assert!(tracker.on_read(b"TP/1.1\r\n\r\n"));
+        assert_eq!(tracker.awaiting(), HTTP1Awaiting::Nothing);
+    }
+
+    #[test]
+    fn chunked_request_body_is_tracked() {
+        let mut tracker = HTTP1Tracker::new();
+        assert!(tracker.on_read(b"POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n"));
+        // Chunk data that looks like the last chunk:
+        assert!(tracker.on_read(b"7;ext=1\r\n\r\n0\r\n\r\n\r\n"));
+        assert_eq!(tracker.awaiting(), HTTP1Awaiting::RequestBody);
+        assert!(tracker.on_read(b"0\r\nTrailer: x\r\n"));
+        assert_eq!(tracker.awaiting(), HTTP1Awaiting::RequestBody);
+        assert!(tracker.on_read(b"\r\nGET / HTTP/1.1\r\n\r\n"));
+        assert_eq!(tracker.pending_request_list.len(), 2);
+    }
+
+    #[test]
+    fn untrackable_connections_are_detected() {
+        assert!(!HTTP1Tracker::new().on_read(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"));
+        let mut tracker = HTTP1Tracker::new();
+        assert!(tracker.on_read(b"GET /ws HTTP/1.1\r\nUpgrade: websocket\r\n\r\n"));
+        assert!(!tracker.on_write(b"HTTP/1.1 101 Switching Protocols\r\n\r\n"));
+        assert!(!HTTP1Tracker::new().on_read(&vec![b'a'; TIMEOUT_STREAM_MAX_HEAD_LENGTH + 1]));
+    }
+}
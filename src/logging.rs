@@ -1,33 +1,45 @@
 use crate::settings::CfgLogging;
 
 use std::io::Write;
+use std::path::PathBuf;
 
 use structopt::clap::crate_name;
 use tracing::debug;
 use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
 use tracing_subscriber::{
-    filter::Filtered, fmt::format::JsonFields, layer::SubscriberExt, reload::Handle,
-    util::SubscriberInitExt, Layer, Registry,
+    filter::{Filtered, LevelFilter, Targets},
+    fmt::format::{DefaultFields, Format, Full, Json, JsonFields},
+    layer::{Layered, SubscriberExt},
+    reload::Handle,
+    util::SubscriberInitExt,
+    Layer, Registry,
 };
 
-type JsonHandle = Handle<
+/// Target of HTTP request log lines that are already formatted (e.g. in combined log format)
+/// and are written as they are instead of JSON.
+pub const ACCESS_LOG_TARGET: &str = "restcommander_access_log";
+
+type JsonLayer = Filtered<
+    tracing_subscriber::fmt::Layer<Registry, JsonFields, Format<Json>, NonBlocking>,
+    Targets,
+    Registry,
+>;
+type JsonHandle = Handle<JsonLayer, Registry>;
+type JsonRegistry = Layered<tracing_subscriber::reload::Layer<JsonLayer, Registry>, Registry>;
+type AccessHandle = Handle<
     Filtered<
-        tracing_subscriber::fmt::Layer<
-            Registry,
-            JsonFields,
-            tracing_subscriber::fmt::format::Format<tracing_subscriber::fmt::format::Json>,
-            NonBlocking,
-        >,
-        tracing_subscriber::filter::LevelFilter,
-        Registry,
+        tracing_subscriber::fmt::Layer<JsonRegistry, DefaultFields, Format<Full, ()>, NonBlocking>,
+        Targets,
+        JsonRegistry,
     >,
-    Registry,
+    JsonRegistry,
 >;
 
 #[derive(Debug)]
 pub struct LoggingState {
     worker_guard: WorkerGuard,
     json_handle: JsonHandle,
+    access_handle: AccessHandle,
 }
 
 #[derive(Debug)]
@@ -52,17 +64,28 @@ pub fn setup(config: CfgLogging) -> LoggingState {
         .with_target(true)
         .with_thread_ids(false)
         .with_thread_names(false)
-        .with_writer(logging_writer)
-        .with_filter(tracing_subscriber::filter::LevelFilter::OFF);
+        .with_writer(logging_writer.clone())
+        .with_filter(json_filter(LevelFilter::OFF));
     let (logging_json_layer, logging_json_handle) =
         tracing_subscriber::reload::Layer::new(logging_json_layer);
+    let logging_access_layer = tracing_subscriber::fmt::layer()
+        .with_ansi(false)
+        .without_time()
+        .with_level(false)
+        .with_target(false)
+        .with_writer(logging_writer)
+        .with_filter(access_filter(LevelFilter::OFF));
+    let (logging_access_layer, logging_access_handle) =
+        tracing_subscriber::reload::Layer::new(logging_access_layer);
 
     tracing_subscriber::registry()
         .with(logging_json_layer)
+        .with(logging_access_layer)
         .init();
     LoggingState {
         worker_guard: logging_writer_guard,
         json_handle: logging_json_handle,
+        access_handle: logging_access_handle,
     }
 }
 
@@ -72,14 +95,31 @@ pub fn update(config: CfgLogging, state: &mut LoggingState) {
     state
         .json_handle
         .modify(|json_layer| {
-            *json_layer.filter_mut() = config.level_name.to_level_filter();
-            *json_layer.inner_mut().writer_mut() = logging_writer;
+            *json_layer.filter_mut() = json_filter(config.level_name.to_level_filter());
+            *json_layer.inner_mut().writer_mut() = logging_writer.clone();
+        })
+        .unwrap();
+    state
+        .access_handle
+        .modify(|access_layer| {
+            *access_layer.filter_mut() = access_filter(config.level_name.to_level_filter());
+            *access_layer.inner_mut().writer_mut() = logging_writer;
         })
         .unwrap();
     state.worker_guard = logging_writer_guard;
     debug!(level = ?config.level_name, output = ?config.output, "Updated logging options.");
 }
 
+fn json_filter(level: LevelFilter) -> Targets {
+    Targets::new()
+        .with_default(level)
+        .with_target(ACCESS_LOG_TARGET, LevelFilter::OFF)
+}
+
+fn access_filter(level: LevelFilter) -> Targets {
+    Targets::new().with_target(ACCESS_LOG_TARGET, level)
+}
+
 fn writer(config: &CfgLogging) -> (NonBlocking, WorkerGuard) {
     match config.output.to_str() {
         Some("stdout") => tracing_appender::non_blocking(std::io::stdout()),
@@ -91,3 +131,24 @@ fn writer(config: &CfgLogging) -> (NonBlocking, WorkerGuard) {
         )),
     }
 }
+
+/// Returns the current daily log file (the most recently modified one) if `output` is a directory.
+pub fn current_log_file(config: &CfgLogging) -> Option<PathBuf> {
+    if matches!(config.output.to_str(), Some("stdout" | "stderr" | "off")) {
+        return None;
+    };
+    let prefix = crate_name!().to_owned() + ".log";
+    std::fs::read_dir(&config.output)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .map(|name| name.starts_with(prefix.as_str()))
+                .unwrap_or_default()
+        })
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .max()
+        .map(|(_, filename)| filename)
+}
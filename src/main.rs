@@ -1,27 +1,20 @@
 use std::path::PathBuf;
 use std::process::exit;
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
 use std::thread::sleep;
 use std::time::Duration;
 use tokio::sync::RwLock as AsyncRwLock;
 
-use tracing::error;
+use arc_swap::ArcSwap;
+use tracing::{error, info, warn};
 
-mod captcha;
-mod cmd;
-mod http;
-mod logging;
-mod report;
-mod samples;
-mod settings;
-mod utils;
-mod www;
+use restcommander::{cmd, http, logging, report, samples, settings};
 
 #[tokio::main]
 async fn main() -> Result<(), String> {
     let mut logging_state = logging::setup(settings::CfgLogging::default());
     let cfg = match settings::try_setup() {
-        Ok(cfg) => Arc::new(RwLock::new(cfg)),
+        Ok(cfg) => Arc::new(ArcSwap::from_pointee(cfg)),
         Err(maybe_error) => {
             if maybe_error.is_none() {
                 exit(0)
@@ -31,14 +24,11 @@ async fn main() -> Result<(), String> {
             }
         }
     };
-    logging::update(
-        cfg.read().unwrap().config_value.clone().logging,
-        &mut logging_state,
-    );
+    logging::update(cfg.load().config_value.clone().logging, &mut logging_state);
     let cfg = match settings::try_setup() {
         Ok(cfg) => {
             cfg.trace_log();
-            Arc::new(RwLock::new(cfg))
+            Arc::new(ArcSwap::from_pointee(cfg))
         }
         Err(maybe_error) => {
             if maybe_error.is_none() {
@@ -49,32 +39,79 @@ async fn main() -> Result<(), String> {
             }
         }
     };
-    let mut cfg_instance = cfg.read().unwrap().config_value.clone();
+    let mut cfg_instance = cfg.load().config_value.clone();
     let root_directory = cfg_instance.commands.root_directory.clone();
-    let commands = Arc::new(RwLock::new(
-        cmd::tree::Command::new(
-            &root_directory,
-            &PathBuf::from(cfg_instance.server.http_base_path.clone()).join(
-                PathBuf::from(http::API_RUN_BASE_PATH)
-                    .strip_prefix("/")
-                    .unwrap(),
-            ),
-        )
-        .map_err(|reason| reason.to_string())?,
-    ));
+    let http_base_path = PathBuf::from(cfg_instance.server.http_base_path.clone()).join(
+        PathBuf::from(http::API_RUN_BASE_PATH)
+            .strip_prefix("/")
+            .unwrap(),
+    );
+    let environment = cfg_instance.commands.environment.clone();
+    let commands =
+        match cmd::tree::Command::new(&root_directory, &http_base_path, environment.clone()) {
+            Ok(commands) => {
+                cmd::check_aliases(&cfg_instance.commands.aliases, &commands)
+                    .map_err(|reason| reason.to_string())?;
+                commands
+            }
+            Err(reason) if cfg_instance.commands.wait_for_commands => {
+                warn!(
+                    error = reason.to_string().as_str(),
+                    "Could not load commands, Waiting for them to be reloaded."
+                );
+                cmd::tree::Command::empty(&root_directory, &http_base_path, environment)
+            }
+            Err(reason) => return Err(reason.to_string()),
+        };
+    let commands = Arc::new(ArcSwap::from_pointee(commands));
     let report_state = Arc::new(AsyncRwLock::new(
         report::maybe_setup(cfg_instance.logging.clone(), None).await?,
     ));
-    let (mut _http_server_sender, mut _http_server_receiver) =
-        http::setup(cfg.clone(), commands.clone(), report_state.clone()).await?;
+    let maintenance = http::MaintenanceState::default();
+    let drain_state = http::DrainState::default();
+    let traffic = http::TrafficState::default();
+    let command_semaphores = http::CommandSemaphores::default();
+    let command_rate_limits = http::CommandRateLimits::default();
+    let (mut _http_server_sender, mut _http_server_receiver) = http::setup(
+        cfg.clone(),
+        commands.clone(),
+        report_state.clone(),
+        maintenance.clone(),
+        drain_state.clone(),
+        traffic.clone(),
+        command_semaphores.clone(),
+        command_rate_limits.clone(),
+    )
+    .await?;
     if cfg_instance.server.print_banner {
         samples::maybe_print(samples::CMDSample::Banner)
     }
+    let shutdown_signal = wait_for_shutdown_signal();
+    tokio::pin!(shutdown_signal);
     loop {
-        match http::maybe_handle_message(&mut _http_server_receiver).await {
+        let maybe_message = tokio::select! {
+            _ = &mut shutdown_signal => {
+                let cfg_instance = cfg.load().config_value.clone();
+                info!(
+                    timeout = cfg_instance.server.shutdown_drain_timeout,
+                    "Got shutdown signal. Waiting for in-flight requests."
+                );
+                // Listeners stop accepting new connections:
+                let _ = _http_server_sender.send(());
+                drain_state
+                    .drain(
+                        Duration::from_secs(cfg_instance.server.shutdown_drain_timeout),
+                        Duration::from_secs(cfg_instance.commands.kill_grace_period + 1),
+                    )
+                    .await;
+                return Ok(());
+            }
+            maybe_message = http::maybe_handle_message(&mut _http_server_receiver) => maybe_message,
+        };
+        match maybe_message {
             Ok(true) => {
                 // Update logging:
-                let new_cfg_instance = cfg.write().unwrap().config_value.clone();
+                let new_cfg_instance = cfg.load().config_value.clone();
                 let new_cfg_logging = new_cfg_instance.logging.clone();
                 if cfg_instance.logging.level_name != new_cfg_logging.level_name
                     || cfg_instance.logging.output != new_cfg_logging.output
@@ -91,7 +128,9 @@ async fn main() -> Result<(), String> {
                     }
                 }
                 let new_commands_root_directory = new_cfg_instance.commands.root_directory.clone();
-                if cfg_instance.commands.root_directory != new_commands_root_directory {
+                if cfg_instance.commands.root_directory != new_commands_root_directory
+                    || cfg_instance.commands.environment != new_cfg_instance.commands.environment
+                {
                     let load_new_commands = cmd::tree::Command::new(
                         &new_commands_root_directory,
                         &PathBuf::from(new_cfg_instance.server.http_base_path.clone()).join(
@@ -99,9 +138,10 @@ async fn main() -> Result<(), String> {
                                 .strip_prefix("/")
                                 .unwrap(),
                         ),
+                        new_cfg_instance.commands.environment.clone(),
                     );
                     match load_new_commands {
-                        Ok(new_commands) => commands.write().unwrap().replace(new_commands),
+                        Ok(new_commands) => commands.store(Arc::new(new_commands)),
                         Err(reason) => {
                             error!("{:?}", reason);
                         }
@@ -109,30 +149,63 @@ async fn main() -> Result<(), String> {
                 };
                 let new_server_cfg = new_cfg_instance.server.clone();
                 if new_server_cfg.http_base_path != cfg_instance.server.http_base_path {
-                    commands
-                        .write()
-                        .unwrap()
-                        .replace_http_base_path(&PathBuf::from(
+                    commands.rcu(|commands| {
+                        let mut commands = commands.as_ref().clone();
+                        commands.replace_http_base_path(&PathBuf::from(
                             new_server_cfg.http_base_path.clone(),
                         ));
+                        commands
+                    });
                 };
-                if new_server_cfg.host != cfg_instance.server.host
-                    || new_server_cfg.port != cfg_instance.server.port
-                {
-                    if new_server_cfg.port == cfg_instance.server.port {
+                let old_listener_list = cfg_instance.server.listener_list();
+                let new_listener_list = new_server_cfg.listener_list();
+                if new_listener_list != old_listener_list {
+                    // Old server should be stopped first if a port is still in use:
+                    if new_listener_list.iter().any(|new_listener| {
+                        old_listener_list
+                            .iter()
+                            .any(|old_listener| old_listener.port == new_listener.port)
+                    }) {
                         _http_server_sender.send(()).unwrap();
                         sleep(Duration::from_secs(5));
-                        let start_new_http_server =
-                            http::setup(cfg.clone(), commands.clone(), report_state.clone()).await;
+                        let start_new_http_server = http::setup(
+                            cfg.clone(),
+                            commands.clone(),
+                            report_state.clone(),
+                            maintenance.clone(),
+                            drain_state.clone(),
+                            traffic.clone(),
+                            command_semaphores.clone(),
+                            command_rate_limits.clone(),
+                        )
+                        .await;
                         if let Err(reason) = start_new_http_server {
                             error!("could not start new HTTP server: {}. Attempt to start another server with old configuration settings", reason);
-                            cfg.write().unwrap().config_value.server.host =
-                                cfg_instance.server.host.clone();
-                            cfg.write().unwrap().config_value.server.port =
-                                cfg_instance.server.port.clone();
-                            let start_old_http_server =
-                                http::setup(cfg.clone(), commands.clone(), report_state.clone())
-                                    .await;
+                            cfg.rcu(|cfg| {
+                                let mut cfg = cfg.as_ref().clone();
+                                cfg.config_value.server.host = cfg_instance.server.host.clone();
+                                cfg.config_value.server.port = cfg_instance.server.port.clone();
+                                cfg.config_value.server.tls_cert_file =
+                                    cfg_instance.server.tls_cert_file.clone();
+                                cfg.config_value.server.tls_key_file =
+                                    cfg_instance.server.tls_key_file.clone();
+                                cfg.config_value.server.proxy_protocol =
+                                    cfg_instance.server.proxy_protocol;
+                                cfg.config_value.server.listeners =
+                                    cfg_instance.server.listeners.clone();
+                                cfg
+                            });
+                            let start_old_http_server = http::setup(
+                                cfg.clone(),
+                                commands.clone(),
+                                report_state.clone(),
+                                maintenance.clone(),
+                                drain_state.clone(),
+                                traffic.clone(),
+                                command_semaphores.clone(),
+                                command_rate_limits.clone(),
+                            )
+                            .await;
                             if let Err(reason) = start_old_http_server {
                                 let reason = format!("could not start old HTTP server: {}", reason);
                                 error!("{}", reason);
@@ -145,8 +218,17 @@ async fn main() -> Result<(), String> {
                                 start_new_http_server.unwrap();
                         }
                     } else {
-                        let start_new_http_server =
-                            http::setup(cfg.clone(), commands.clone(), report_state.clone()).await;
+                        let start_new_http_server = http::setup(
+                            cfg.clone(),
+                            commands.clone(),
+                            report_state.clone(),
+                            maintenance.clone(),
+                            drain_state.clone(),
+                            traffic.clone(),
+                            command_semaphores.clone(),
+                            command_rate_limits.clone(),
+                        )
+                        .await;
                         if let Err(reason) = start_new_http_server {
                             error!(
                                 "could not start new HTTP server: {}. Old HTTP server still works",
@@ -168,3 +250,18 @@ async fn main() -> Result<(), String> {
         }?;
     }
 }
+
+/// Resolves after the process gets `SIGINT` (Ctrl-C) or `SIGTERM` (Unix only) signal.
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).unwrap();
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        };
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
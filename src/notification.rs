@@ -0,0 +1,144 @@
+use crate::settings::{CfgNotifications, CfgNotificationsSeverity};
+
+use std::time::{Duration, SystemTime};
+
+use futures_util::FutureExt;
+use hyper_rustls::HttpsConnector;
+use serde_json::json;
+use tracing::{debug, warn};
+use warp::http::header::CONTENT_TYPE;
+use warp::hyper::{Body, Client, Method, Request};
+
+const CLOUDEVENTS_CONTENT_TYPE: &str = "application/cloudevents+json";
+const CLOUDEVENTS_SPEC_VERSION: &str = "1.0";
+const CLOUDEVENTS_COMMAND_COMPLETED_TYPE: &str = "restcommander.command.completed";
+
+/// A finished command run.
+#[derive(Clone, Debug)]
+pub struct CommandEvent {
+    pub http_path: String,
+    /// Username of the requester's session token or name of its TLS client certificate.
+    ///
+    /// It is `None` for anonymous requests and requests with the configured `api_token`.
+    pub maybe_username: Option<String>,
+    /// It is `None` if the command process did not exit by itself (e.g. it is timed out).
+    pub maybe_exit_code: Option<i32>,
+    pub maybe_error: Option<String>,
+    pub severity: CfgNotificationsSeverity,
+    pub duration: Duration,
+}
+
+impl CommandEvent {
+    pub fn is_ok(&self) -> bool {
+        self.maybe_exit_code == Some(0)
+    }
+}
+
+/// Sends configured notifications of the event in background.
+///
+/// It never blocks and failures are just logged.
+pub fn notify(cfg: &CfgNotifications, event: CommandEvent) {
+    if !cfg.cloudevents_url.is_empty() {
+        tokio::spawn(post(
+            cfg.clone(),
+            cfg.cloudevents_url.clone(),
+            CLOUDEVENTS_CONTENT_TYPE,
+            make_cloudevent(&cfg.cloudevents_source, &event).to_string(),
+        ));
+    };
+    if !cfg.webhook_url.is_empty() && !event.is_ok() && event.severity >= cfg.webhook_min_severity {
+        tokio::spawn(post(
+            cfg.clone(),
+            cfg.webhook_url.clone(),
+            "application/json",
+            render_webhook_template(&cfg.webhook_template, &event),
+        ));
+    };
+}
+
+/// Replaces `{NAME}` placeholders of the template with JSON-escaped values of the event.
+///
+/// Other braces are kept as they are, so the template can be a JSON document itself.
+fn render_webhook_template(template: &str, event: &CommandEvent) -> String {
+    [
+        ("path", event.http_path.clone()),
+        ("error", event.maybe_error.clone().unwrap_or_default()),
+        (
+            "exit_code",
+            event
+                .maybe_exit_code
+                .map(|exit_code| exit_code.to_string())
+                .unwrap_or_default(),
+        ),
+        ("severity", event.severity.as_str().to_string()),
+        ("username", event.maybe_username.clone().unwrap_or_default()),
+        ("duration", (event.duration.as_micros() as u64).to_string()),
+    ]
+    .iter()
+    .fold(template.to_string(), |body, (name, value)| {
+        let value = serde_json::to_string(value).unwrap();
+        body.replace(format!("{{{}}}", name).as_str(), &value[1..value.len() - 1])
+    })
+}
+
+fn make_cloudevent(source: &str, event: &CommandEvent) -> serde_json::Value {
+    json!({
+        "specversion": CLOUDEVENTS_SPEC_VERSION,
+        "id": uuid::Uuid::new_v4().to_string(),
+        "source": source,
+        "type": CLOUDEVENTS_COMMAND_COMPLETED_TYPE,
+        "subject": event.http_path,
+        "time": humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+        "datacontenttype": "application/json",
+        "data": {
+            "path": event.http_path,
+            "ok": event.is_ok(),
+            "exit_code": event.maybe_exit_code,
+            "error": event.maybe_error,
+            "severity": event.severity,
+            "duration": event.duration.as_micros() as u64,
+            "username": event.maybe_username,
+        },
+    })
+}
+
+async fn post(cfg: CfgNotifications, url: String, content_type: &'static str, body: String) {
+    // Supports both `http://` and `https://` URLs:
+    let client = Client::builder().build::<_, Body>(HttpsConnector::with_webpki_roots());
+    for attempt in 0..=cfg.retry_count {
+        if attempt > 0 {
+            tokio::time::sleep(Duration::from_millis(cfg.retry_delay)).await;
+        };
+        // URL is checked when configuration is loaded:
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(url.as_str())
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::from(body.clone()))
+            .unwrap();
+        let response = client
+            .request(request)
+            .map(|result| result.map_err(|reason| reason.to_string()));
+        let maybe_response = if cfg.timeout > 0 {
+            tokio::time::timeout(Duration::from_secs(cfg.timeout), response)
+                .await
+                .unwrap_or_else(|_| Err("timed out".to_string()))
+        } else {
+            response.await
+        };
+        let reason = match maybe_response {
+            Ok(response) if response.status().is_success() => {
+                debug!(url = url.as_str(), "Sent notification.");
+                return;
+            }
+            Ok(response) => format!("got status-code {}", response.status()),
+            Err(reason) => reason,
+        };
+        warn!(
+            url = url.as_str(),
+            attempt = attempt,
+            error = reason.as_str(),
+            "Could not send notification"
+        );
+    }
+}
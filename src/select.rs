@@ -0,0 +1,309 @@
+//! A safe subset of [jq](https://jqlang.github.io/jq/) expressions to select parts of command
+//! results (e.g. `.items[].name` or `{name, id: .meta.id}`).
+use serde_json::Value;
+use std::str::FromStr;
+
+/// Maximum length of a select expression in characters.
+const MAX_SELECT_LENGTH: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Select {
+    /// `.`, `.foo.bar`, `.[0]`, `.foo[-1]`, `.items[].name` or `.["a-b"]`
+    Path(Vec<SelectSegment>),
+    /// `{foo, bar: .baz[0]}`
+    Object(Vec<(String, Vec<SelectSegment>)>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SelectSegment {
+    Key(String),
+    /// Negative indexes count from the end of array.
+    Index(i64),
+    /// `[]` applies the rest of the path to each item of array (or value of object) and collects
+    /// results in an array.
+    Iterate,
+}
+
+impl FromStr for Select {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        if expression.chars().count() > MAX_SELECT_LENGTH {
+            return Err(format!(
+                "expression should not be longer than {} characters",
+                MAX_SELECT_LENGTH
+            ));
+        };
+        let mut parser = SelectParser {
+            char_list: expression.chars().collect(),
+            position: 0,
+        };
+        parser.skip_whitespaces();
+        let select = if parser.peek() == Some('{') {
+            Select::Object(parser.object()?)
+        } else {
+            Select::Path(parser.path()?)
+        };
+        parser.skip_whitespaces();
+        parser.end()?;
+        Ok(select)
+    }
+}
+
+impl Select {
+    /// Missing keys, out of range indexes and indexing values of other types result in `null`.
+    pub fn apply(&self, value: &Value) -> Value {
+        match self {
+            Self::Path(segment_list) => apply_path(segment_list, value),
+            Self::Object(field_list) => Value::Object(
+                field_list
+                    .iter()
+                    .map(|(name, segment_list)| (name.clone(), apply_path(segment_list, value)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+fn apply_path(segment_list: &[SelectSegment], value: &Value) -> Value {
+    match segment_list.split_first() {
+        None => value.clone(),
+        Some((SelectSegment::Key(key), rest)) => {
+            apply_path(rest, value.get(key.as_str()).unwrap_or(&Value::Null))
+        }
+        Some((SelectSegment::Index(index), rest)) => {
+            let maybe_item = value.as_array().and_then(|list| {
+                let index = if *index < 0 {
+                    list.len() as i64 + index
+                } else {
+                    *index
+                };
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|index| list.get(index))
+            });
+            apply_path(rest, maybe_item.unwrap_or(&Value::Null))
+        }
+        Some((SelectSegment::Iterate, rest)) => match value {
+            Value::Array(list) => Value::Array(list.iter().map(|x| apply_path(rest, x)).collect()),
+            Value::Object(map) => Value::Array(map.values().map(|x| apply_path(rest, x)).collect()),
+            _ => Value::Null,
+        },
+    }
+}
+
+struct SelectParser {
+    char_list: Vec<char>,
+    position: usize,
+}
+
+impl SelectParser {
+    fn peek(&self) -> Option<char> {
+        self.char_list.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let maybe_char = self.peek();
+        if maybe_char.is_some() {
+            self.position += 1;
+        };
+        maybe_char
+    }
+
+    fn skip_whitespaces(&mut self) {
+        while self.peek().map(char::is_whitespace).unwrap_or_default() {
+            self.position += 1;
+        }
+    }
+
+    fn unexpected(&self) -> String {
+        match self.peek() {
+            Some(character) => format!(
+                "unexpected {:?} at position {}",
+                character,
+                self.position + 1
+            ),
+            None => "unexpected end of expression".to_string(),
+        }
+    }
+
+    fn expect(&mut self, character: char) -> Result<(), String> {
+        if self.peek() == Some(character) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(format!("{} (expected {:?})", self.unexpected(), character))
+        }
+    }
+
+    fn end(&self) -> Result<(), String> {
+        if self.peek().is_none() {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn is_identifier_start(maybe_char: Option<char>) -> bool {
+        maybe_char
+            .map(|character| character.is_ascii_alphabetic() || character == '_')
+            .unwrap_or_default()
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        if !Self::is_identifier_start(self.peek()) {
+            return Err(format!("{} (expected a key name)", self.unexpected()));
+        };
+        let mut identifier = String::new();
+        while let Some(character) = self
+            .peek()
+            .filter(|character| character.is_ascii_alphanumeric() || *character == '_')
+        {
+            identifier.push(character);
+            self.position += 1;
+        }
+        Ok(identifier)
+    }
+
+    /// A JSON string literal.
+    fn string(&mut self) -> Result<String, String> {
+        let start = self.position;
+        self.expect('"')?;
+        loop {
+            match self.next() {
+                Some('"') => break,
+                Some('\\') => {
+                    self.next();
+                }
+                Some(_) => (),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+        let literal: String = self.char_list[start..self.position].iter().collect();
+        serde_json::from_str(&literal)
+            .map_err(|reason| format!("invalid string {}: {}", literal, reason))
+    }
+
+    fn index(&mut self) -> Result<i64, String> {
+        let start = self.position;
+        if self.peek() == Some('-') {
+            self.position += 1;
+        };
+        while self.peek().map(|x| x.is_ascii_digit()).unwrap_or_default() {
+            self.position += 1;
+        }
+        let literal: String = self.char_list[start..self.position].iter().collect();
+        i64::from_str(&literal).map_err(|_| format!("{} (expected an index)", self.unexpected()))
+    }
+
+    fn bracket(&mut self) -> Result<SelectSegment, String> {
+        self.expect('[')?;
+        self.skip_whitespaces();
+        let segment = match self.peek() {
+            Some(']') => SelectSegment::Iterate,
+            Some('"') => SelectSegment::Key(self.string()?),
+            _ => SelectSegment::Index(self.index()?),
+        };
+        self.skip_whitespaces();
+        self.expect(']')?;
+        Ok(segment)
+    }
+
+    fn path(&mut self) -> Result<Vec<SelectSegment>, String> {
+        let mut segment_list = Vec::new();
+        self.expect('.')?;
+        if Self::is_identifier_start(self.peek()) {
+            segment_list.push(SelectSegment::Key(self.identifier()?));
+        };
+        loop {
+            match self.peek() {
+                Some('[') => segment_list.push(self.bracket()?),
+                Some('.') => {
+                    self.position += 1;
+                    if self.peek() != Some('[') {
+                        segment_list.push(SelectSegment::Key(self.identifier()?));
+                    };
+                }
+                _ => break,
+            }
+        }
+        Ok(segment_list)
+    }
+
+    fn object(&mut self) -> Result<Vec<(String, Vec<SelectSegment>)>, String> {
+        let mut field_list = Vec::new();
+        self.expect('{')?;
+        loop {
+            self.skip_whitespaces();
+            let name = if self.peek() == Some('"') {
+                self.string()?
+            } else {
+                self.identifier()?
+            };
+            self.skip_whitespaces();
+            let segment_list = if self.peek() == Some(':') {
+                self.position += 1;
+                self.skip_whitespaces();
+                self.path()?
+            } else {
+                vec![SelectSegment::Key(name.clone())]
+            };
+            field_list.push((name, segment_list));
+            self.skip_whitespaces();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some('}') => {
+                    self.position += 1;
+                    break;
+                }
+                _ => return Err(format!("{} (expected ',' or '}}')", self.unexpected())),
+            }
+        }
+        Ok(field_list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn select(expression: &str, value: &Value) -> Value {
+        Select::from_str(expression).unwrap().apply(value)
+    }
+
+    #[test]
+    fn paths_are_applied() {
+        let value = json!({"items": [{"name": "a"}, {"name": "b"}, {"name": "c"}], "a-b": 1});
+        assert_eq!(select(".", &value), value);
+        assert_eq!(select(".items[0].name", &value), json!("a"));
+        assert_eq!(select(".items[-1].name", &value), json!("c"));
+        assert_eq!(select(".items[-4]", &value), Value::Null);
+        assert_eq!(select(".items[].name", &value), json!(["a", "b", "c"]));
+        assert_eq!(select(".[\"a-b\"]", &value), json!(1));
+        assert_eq!(select(".missing.key", &value), Value::Null);
+    }
+
+    #[test]
+    fn objects_are_projected() {
+        let value = json!({"name": "foo", "meta": {"id": 10}, "tags": ["x", "y"]});
+        assert_eq!(
+            select("{name, id: .meta.id, \"last-tag\": .tags[-1]}", &value),
+            json!({"name": "foo", "id": 10, "last-tag": "y"})
+        );
+    }
+
+    #[test]
+    fn malformed_expressions_are_rejected() {
+        for expression in [".foo.", "{a,", ".[x]", ".[\"a-b]", "foo", ".foo bar"] {
+            assert!(
+                Select::from_str(expression).is_err(),
+                "{:?} is accepted",
+                expression
+            );
+        }
+        let long_expression = format!(".{}", "a".repeat(MAX_SELECT_LENGTH));
+        assert!(Select::from_str(&long_expression).is_err());
+        assert!(Select::from_str(&long_expression[..MAX_SELECT_LENGTH]).is_ok());
+    }
+}
@@ -6,21 +6,28 @@ use std::io::Error;
 use std::net::{AddrParseError, IpAddr};
 use std::path::PathBuf;
 use std::string::FromUtf8Error;
+use std::time::Duration;
 
 use structopt::clap::{crate_authors, crate_description, crate_name, crate_version};
 use structopt::StructOpt;
 
 use tracing::{info, trace, warn};
 
-use config::{Config, ConfigError, Environment, File};
+use config::{Config, ConfigError, Environment, File, Source, Value, ValueKind};
 
 use warp::http::uri::PathAndQuery;
+use warp::http::StatusCode;
 
 use serde_derive::{Deserialize, Serialize};
 
 use ttyaskpass::AskPass;
 
-use crate::cmd::runner::CommandOptionsValue;
+use crate::cmd;
+use crate::cmd::runner::{CommandLimits, CommandOptionsValue, CommandRunSettings};
+use crate::cmd::tree::Command;
+use crate::http::API_RUN_BASE_PATH;
+use crate::listener;
+use crate::listener::{ListenerSettings, TlsClientAuth};
 use thiserror::Error;
 use tracing_subscriber::filter::LevelFilter;
 
@@ -30,13 +37,52 @@ const DEFAULT_SERVER_HOST: &str = "127.0.0.1";
 const DEFAULT_SERVER_PORT: u16 = 1995;
 const DEFAULT_SERVER_HTTP_BASE_PATH: &str = "/";
 const DEFAULT_SERVER_USERNAME: &str = "";
+const DEFAULT_SERVER_AUTH_REALM: &str = "Restricted";
 const DEFAULT_SERVER_PASSWORD_SHA512: &str = "";
 const DEFAULT_SERVER_PASSWORD_FILE: &str = "";
 const DEFAULT_SERVER_TOKEN_TIMEOUT: usize = 604800; // 1 week in seconds
+const DEFAULT_SERVER_RETRY_AFTER: u64 = 0;
+const DEFAULT_SERVER_REQUEST_TIMEOUT: u64 = 0; // seconds
+const DEFAULT_SERVER_HEADER_READ_TIMEOUT: u64 = 30; // seconds
+const DEFAULT_SERVER_BODY_READ_TIMEOUT: u64 = 60; // seconds
+const DEFAULT_SERVER_SHUTDOWN_DRAIN_TIMEOUT: u64 = 20; // seconds
+const DEFAULT_SERVER_PASSWORD_MIN_LENGTH: usize = 0;
+const DEFAULT_SERVER_PASSWORD_CHARACTER_CLASSES: usize = 0;
+const DEFAULT_SERVER_BACKLOG: u32 = 128;
+const DEFAULT_SERVER_TCP_KEEPALIVE: u64 = 0; // seconds
+const DEFAULT_SERVER_TCP_KEEPALIVE_INTERVAL: u64 = 0; // seconds
+const DEFAULT_SERVER_TCP_KEEPALIVE_RETRIES: u32 = 0;
+const DEFAULT_COMMANDS_SPAWN_RETRY_COUNT: usize = 0;
+const DEFAULT_COMMANDS_SPAWN_RETRY_DELAY: u64 = 100; // milliseconds
+const DEFAULT_COMMANDS_TIMEOUT: u64 = 0; // seconds
+const DEFAULT_COMMANDS_KILL_SIGNAL: &str = "SIGTERM";
+const DEFAULT_COMMANDS_KILL_GRACE_PERIOD: u64 = 5; // seconds
+const DEFAULT_COMMANDS_UPLOAD_MAX_SIZE: u64 = 10485760; // 10MB
+const DEFAULT_COMMANDS_UPLOAD_FILE_MAX_SIZE: u64 = 10485760; // 10MB
+const DEFAULT_COMMANDS_MAP_CONCURRENCY: usize = 4;
+const DEFAULT_COMMANDS_MAP_MAX_INPUTS: usize = 100;
+const DEFAULT_COMMANDS_RPC_MAX_BATCH_SIZE: usize = 100;
+const DEFAULT_COMMANDS_IDEMPOTENCY_KEY_TTL: u64 = 3600; // seconds
+const DEFAULT_COMMANDS_MAX_PATH_DEPTH: usize = 16;
+const DEFAULT_COMMANDS_LIMIT_CPU_TIME: u64 = 0; // seconds
+const DEFAULT_COMMANDS_LIMIT_MEMORY: u64 = 0; // bytes
+const DEFAULT_COMMANDS_LIMIT_OPEN_FILES: u64 = 0;
 const DEFAULT_LOGGING_LEVEL_NAME: &str = "info";
 const DEFAULT_LOGGING_OUTPUT: &str = "stderr";
 const DEFAULT_LOGGING_REPORT: &str = "stdout";
+const DEFAULT_LOGGING_MAX_LINE_LENGTH: usize = 4096; // bytes
+const DEFAULT_LOGGING_MAX_LINE_COUNT: usize = 1000;
+const DEFAULT_LOGGING_FORMAT: &str = "json";
 const DEFAULT_WWW_STATIC_DIRECTORY: &str = "";
+const DEFAULT_NOTIFICATIONS_CLOUDEVENTS_URL: &str = "";
+const DEFAULT_NOTIFICATIONS_CLOUDEVENTS_SOURCE: &str = "restcommander";
+const DEFAULT_NOTIFICATIONS_WEBHOOK_URL: &str = "";
+const DEFAULT_NOTIFICATIONS_WEBHOOK_TEMPLATE: &str =
+    r#"{"text": "Command {path} failed ({severity}): {error}"}"#;
+const DEFAULT_NOTIFICATIONS_WEBHOOK_MIN_SEVERITY: &str = "warning";
+const DEFAULT_NOTIFICATIONS_RETRY_COUNT: usize = 3;
+const DEFAULT_NOTIFICATIONS_RETRY_DELAY: u64 = 1000; // milliseconds
+const DEFAULT_NOTIFICATIONS_TIMEOUT: u64 = 10; // seconds
 
 pub mod defaults {
     use super::*;
@@ -77,6 +123,14 @@ pub mod defaults {
             username_str().to_string()
         }
 
+        pub fn auth_realm_str<'a>() -> &'a str {
+            DEFAULT_SERVER_AUTH_REALM
+        }
+
+        pub fn auth_realm() -> String {
+            auth_realm_str().to_string()
+        }
+
         pub fn password_file_str<'a>() -> &'a str {
             DEFAULT_SERVER_PASSWORD_FILE
         }
@@ -101,6 +155,22 @@ pub mod defaults {
             None
         }
 
+        pub fn tls_client_ca_file() -> Option<PathBuf> {
+            None
+        }
+
+        pub fn tls_client_cert_required() -> bool {
+            false
+        }
+
+        pub fn tls_client_allowed_names() -> Vec<String> {
+            Vec::new()
+        }
+
+        pub fn proxy_protocol() -> bool {
+            false
+        }
+
         pub fn captcha() -> bool {
             true
         }
@@ -117,6 +187,14 @@ pub mod defaults {
             None
         }
 
+        pub fn auth_command() -> Option<PathBuf> {
+            None
+        }
+
+        pub fn anonymous_paths() -> Vec<String> {
+            Vec::new()
+        }
+
         pub fn token_timeout_str<'a>() -> &'a str {
             // 1 week
             Box::leak(DEFAULT_SERVER_TOKEN_TIMEOUT.to_string().into_boxed_str())
@@ -126,9 +204,153 @@ pub mod defaults {
             usize::from_str(token_timeout_str()).unwrap()
         }
 
+        pub fn retry_after_str<'a>() -> &'a str {
+            Box::leak(DEFAULT_SERVER_RETRY_AFTER.to_string().into_boxed_str())
+        }
+
+        pub fn retry_after() -> u64 {
+            u64::from_str(retry_after_str()).unwrap()
+        }
+
+        pub fn request_timeout_str<'a>() -> &'a str {
+            Box::leak(DEFAULT_SERVER_REQUEST_TIMEOUT.to_string().into_boxed_str())
+        }
+
+        pub fn request_timeout() -> u64 {
+            u64::from_str(request_timeout_str()).unwrap()
+        }
+
+        pub fn header_read_timeout_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_SERVER_HEADER_READ_TIMEOUT
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn header_read_timeout() -> u64 {
+            u64::from_str(header_read_timeout_str()).unwrap()
+        }
+
+        pub fn body_read_timeout_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_SERVER_BODY_READ_TIMEOUT
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn body_read_timeout() -> u64 {
+            u64::from_str(body_read_timeout_str()).unwrap()
+        }
+
+        pub fn shutdown_drain_timeout_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_SERVER_SHUTDOWN_DRAIN_TIMEOUT
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn shutdown_drain_timeout() -> u64 {
+            u64::from_str(shutdown_drain_timeout_str()).unwrap()
+        }
+
+        pub fn include_timing_str<'a>() -> &'a str {
+            "false"
+        }
+
+        pub fn include_timing() -> bool {
+            bool::from_str(include_timing_str()).unwrap()
+        }
+
+        pub fn allow_set_password_str<'a>() -> &'a str {
+            "true"
+        }
+
+        pub fn allow_set_password() -> bool {
+            bool::from_str(allow_set_password_str()).unwrap()
+        }
+
+        pub fn require_old_password() -> bool {
+            false
+        }
+
+        pub fn password_min_length_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_SERVER_PASSWORD_MIN_LENGTH
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn password_min_length() -> usize {
+            usize::from_str(password_min_length_str()).unwrap()
+        }
+
+        pub fn password_character_classes_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_SERVER_PASSWORD_CHARACTER_CLASSES
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn password_character_classes() -> usize {
+            usize::from_str(password_character_classes_str()).unwrap()
+        }
+
+        pub fn backlog_str<'a>() -> &'a str {
+            Box::leak(DEFAULT_SERVER_BACKLOG.to_string().into_boxed_str())
+        }
+
+        pub fn backlog() -> u32 {
+            u32::from_str(backlog_str()).unwrap()
+        }
+
+        pub fn tcp_keepalive_str<'a>() -> &'a str {
+            Box::leak(DEFAULT_SERVER_TCP_KEEPALIVE.to_string().into_boxed_str())
+        }
+
+        pub fn tcp_keepalive() -> u64 {
+            u64::from_str(tcp_keepalive_str()).unwrap()
+        }
+
+        pub fn tcp_keepalive_interval_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_SERVER_TCP_KEEPALIVE_INTERVAL
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn tcp_keepalive_interval() -> u64 {
+            u64::from_str(tcp_keepalive_interval_str()).unwrap()
+        }
+
+        pub fn tcp_keepalive_retries_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_SERVER_TCP_KEEPALIVE_RETRIES
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn tcp_keepalive_retries() -> u32 {
+            u32::from_str(tcp_keepalive_retries_str()).unwrap()
+        }
+
         pub fn print_banner() -> bool {
             true
         }
+
+        pub fn api_only() -> bool {
+            false
+        }
+
+        pub fn listeners() -> Vec<CfgServerListener> {
+            Vec::new()
+        }
     }
 
     pub mod commands {
@@ -152,6 +374,202 @@ pub mod defaults {
         pub fn configuration() -> CommandOptionsValue {
             HashMap::default()
         }
+
+        pub fn spawn_retry_count_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_COMMANDS_SPAWN_RETRY_COUNT
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn spawn_retry_count() -> usize {
+            usize::from_str(spawn_retry_count_str()).unwrap()
+        }
+
+        pub fn spawn_retry_delay_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_COMMANDS_SPAWN_RETRY_DELAY
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn spawn_retry_delay() -> u64 {
+            u64::from_str(spawn_retry_delay_str()).unwrap()
+        }
+
+        pub fn timeout_str<'a>() -> &'a str {
+            Box::leak(DEFAULT_COMMANDS_TIMEOUT.to_string().into_boxed_str())
+        }
+
+        pub fn timeout() -> u64 {
+            u64::from_str(timeout_str()).unwrap()
+        }
+
+        pub fn kill_signal_str<'a>() -> &'a str {
+            DEFAULT_COMMANDS_KILL_SIGNAL
+        }
+
+        pub fn kill_signal() -> String {
+            kill_signal_str().to_string()
+        }
+
+        pub fn kill_grace_period_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_COMMANDS_KILL_GRACE_PERIOD
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn kill_grace_period() -> u64 {
+            u64::from_str(kill_grace_period_str()).unwrap()
+        }
+
+        pub fn kill_on_disconnect() -> bool {
+            false
+        }
+
+        pub fn always_ok_status() -> bool {
+            false
+        }
+
+        pub fn no_content_on_empty_output() -> bool {
+            false
+        }
+
+        pub fn allow_debug() -> bool {
+            false
+        }
+
+        pub fn wait_for_commands() -> bool {
+            false
+        }
+
+        pub fn upload_max_size_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_COMMANDS_UPLOAD_MAX_SIZE
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn upload_max_size() -> u64 {
+            u64::from_str(upload_max_size_str()).unwrap()
+        }
+
+        pub fn upload_file_max_size_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_COMMANDS_UPLOAD_FILE_MAX_SIZE
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn upload_file_max_size() -> u64 {
+            u64::from_str(upload_file_max_size_str()).unwrap()
+        }
+
+        pub fn map_concurrency_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_COMMANDS_MAP_CONCURRENCY
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn map_concurrency() -> usize {
+            usize::from_str(map_concurrency_str()).unwrap()
+        }
+
+        pub fn map_max_inputs_str<'a>() -> &'a str {
+            Box::leak(DEFAULT_COMMANDS_MAP_MAX_INPUTS.to_string().into_boxed_str())
+        }
+
+        pub fn map_max_inputs() -> usize {
+            usize::from_str(map_max_inputs_str()).unwrap()
+        }
+
+        pub fn rpc_max_batch_size_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_COMMANDS_RPC_MAX_BATCH_SIZE
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn rpc_max_batch_size() -> usize {
+            usize::from_str(rpc_max_batch_size_str()).unwrap()
+        }
+
+        pub fn max_path_depth_str<'a>() -> &'a str {
+            Box::leak(DEFAULT_COMMANDS_MAX_PATH_DEPTH.to_string().into_boxed_str())
+        }
+
+        pub fn max_path_depth() -> usize {
+            usize::from_str(max_path_depth_str()).unwrap()
+        }
+
+        pub fn idempotency_key_ttl_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_COMMANDS_IDEMPOTENCY_KEY_TTL
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn idempotency_key_ttl() -> u64 {
+            u64::from_str(idempotency_key_ttl_str()).unwrap()
+        }
+
+        pub fn limit_cpu_time_str<'a>() -> &'a str {
+            Box::leak(DEFAULT_COMMANDS_LIMIT_CPU_TIME.to_string().into_boxed_str())
+        }
+
+        pub fn limit_cpu_time() -> u64 {
+            u64::from_str(limit_cpu_time_str()).unwrap()
+        }
+
+        pub fn limit_memory_str<'a>() -> &'a str {
+            Box::leak(DEFAULT_COMMANDS_LIMIT_MEMORY.to_string().into_boxed_str())
+        }
+
+        pub fn limit_memory() -> u64 {
+            u64::from_str(limit_memory_str()).unwrap()
+        }
+
+        pub fn limit_open_files_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_COMMANDS_LIMIT_OPEN_FILES
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn limit_open_files() -> u64 {
+            u64::from_str(limit_open_files_str()).unwrap()
+        }
+
+        pub fn statistics_fields() -> HashMap<String, String> {
+            HashMap::new()
+        }
+
+        pub fn aliases() -> HashMap<String, String> {
+            HashMap::new()
+        }
+
+        pub fn environment() -> Option<String> {
+            None
+        }
+
+        pub fn pre_run_hook() -> Option<PathBuf> {
+            None
+        }
+
+        pub fn post_run_hook() -> Option<PathBuf> {
+            None
+        }
     }
 
     pub mod www {
@@ -169,6 +587,14 @@ pub mod defaults {
             true
         }
 
+        pub fn autoindex() -> bool {
+            false
+        }
+
+        pub fn mime_types() -> HashMap<String, String> {
+            HashMap::new()
+        }
+
         pub fn configuration() -> HashMap<String, String> {
             HashMap::new()
         }
@@ -200,6 +626,110 @@ pub mod defaults {
         pub fn report() -> PathBuf {
             PathBuf::from(report_str())
         }
+
+        pub fn max_line_length_str<'a>() -> &'a str {
+            Box::leak(DEFAULT_LOGGING_MAX_LINE_LENGTH.to_string().into_boxed_str())
+        }
+
+        pub fn max_line_length() -> usize {
+            usize::from_str(max_line_length_str()).unwrap()
+        }
+
+        pub fn max_line_count_str<'a>() -> &'a str {
+            Box::leak(DEFAULT_LOGGING_MAX_LINE_COUNT.to_string().into_boxed_str())
+        }
+
+        pub fn max_line_count() -> usize {
+            usize::from_str(max_line_count_str()).unwrap()
+        }
+
+        pub fn format_str<'a>() -> &'a str {
+            DEFAULT_LOGGING_FORMAT
+        }
+
+        pub fn format() -> CfgLoggingFormat {
+            CfgLoggingFormat::from_str(format_str()).unwrap()
+        }
+
+        pub fn api_logs() -> bool {
+            false
+        }
+    }
+
+    pub mod notifications {
+        use super::*;
+
+        pub fn cloudevents_url_str<'a>() -> &'a str {
+            DEFAULT_NOTIFICATIONS_CLOUDEVENTS_URL
+        }
+
+        pub fn cloudevents_url() -> String {
+            cloudevents_url_str().to_string()
+        }
+
+        pub fn cloudevents_source_str<'a>() -> &'a str {
+            DEFAULT_NOTIFICATIONS_CLOUDEVENTS_SOURCE
+        }
+
+        pub fn cloudevents_source() -> String {
+            cloudevents_source_str().to_string()
+        }
+
+        pub fn webhook_url_str<'a>() -> &'a str {
+            DEFAULT_NOTIFICATIONS_WEBHOOK_URL
+        }
+
+        pub fn webhook_url() -> String {
+            webhook_url_str().to_string()
+        }
+
+        pub fn webhook_template_str<'a>() -> &'a str {
+            DEFAULT_NOTIFICATIONS_WEBHOOK_TEMPLATE
+        }
+
+        pub fn webhook_template() -> String {
+            webhook_template_str().to_string()
+        }
+
+        pub fn webhook_min_severity_str<'a>() -> &'a str {
+            DEFAULT_NOTIFICATIONS_WEBHOOK_MIN_SEVERITY
+        }
+
+        pub fn webhook_min_severity() -> CfgNotificationsSeverity {
+            CfgNotificationsSeverity::from_str(webhook_min_severity_str()).unwrap()
+        }
+
+        pub fn retry_count_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_NOTIFICATIONS_RETRY_COUNT
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn retry_count() -> usize {
+            usize::from_str(retry_count_str()).unwrap()
+        }
+
+        pub fn retry_delay_str<'a>() -> &'a str {
+            Box::leak(
+                DEFAULT_NOTIFICATIONS_RETRY_DELAY
+                    .to_string()
+                    .into_boxed_str(),
+            )
+        }
+
+        pub fn retry_delay() -> u64 {
+            u64::from_str(retry_delay_str()).unwrap()
+        }
+
+        pub fn timeout_str<'a>() -> &'a str {
+            Box::leak(DEFAULT_NOTIFICATIONS_TIMEOUT.to_string().into_boxed_str())
+        }
+
+        pub fn timeout() -> u64 {
+            u64::from_str(timeout_str()).unwrap()
+        }
     }
 }
 
@@ -214,9 +744,11 @@ pub mod defaults {
 ]
 pub enum CMDOpt {
     Config(CMDOptCfg),
+    Check(CMDOptCheck),
     Playground(CfgValue),
     Sample(CMDSample),
     Sha512(CMDSha512),
+    HashPassword(CMDHashPassword),
     Base64(CMDBase64),
 }
 
@@ -225,15 +757,31 @@ pub enum CMDOpt {
 pub struct CMDOptCfg {
     #[structopt(
         parse(from_os_str),
-        about = "A .toml configuration file. To generate a new one, use `sample config` subcommand."
+        required = true,
+        about = "One or more .toml configuration files. Later files override earlier ones. To generate a new one, use `sample config` subcommand."
+    )]
+    config_file_list: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, StructOpt)]
+#[structopt(
+    about = "Validates .toml configuration files and all commands without starting the server. Exits with non-zero exit-code if there is any problem."
+)]
+pub struct CMDOptCheck {
+    #[structopt(
+        parse(from_os_str),
+        required = true,
+        about = "One or more .toml configuration files. Later files override earlier ones."
     )]
-    config_file: PathBuf,
+    config_file_list: Vec<PathBuf>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Cfg {
     pub config_value: CfgValue,
     pub filename: Option<PathBuf>,
+    /// Configuration files that are merged on top of `filename` in order.
+    pub override_filename_list: Vec<PathBuf>,
 }
 
 impl Cfg {
@@ -245,12 +793,14 @@ impl Cfg {
         };
         trace!(
             source = ?source,
+            overrides = ?self.override_filename_list,
             server = ?self.config_value.server,
             commands = ?self.config_value.commands,
             logging = ?self.config_value.logging,
             www = ?self.config_value.www,
+            notifications = ?self.config_value.notifications,
         );
-        info!(source = ?source, "Loaded configuration.");
+        info!(source = ?source, overrides = ?self.override_filename_list, "Loaded configuration.");
     }
 }
 
@@ -263,11 +813,17 @@ pub enum CfgError {
         filename: PathBuf,
         message: ConfigError,
     },
-    #[error("Could not deserialize configuration file {filename:?}: {message:?}")]
+    #[error("Could not deserialize configuration file(s) {filename_list:?}: {message:?}")]
     Deserialize {
-        filename: PathBuf,
+        filename_list: Vec<PathBuf>,
         message: ConfigError,
     },
+    #[error("Could not interpolate configuration key {key:?} in {filename:?}: {message}")]
+    Interpolate {
+        filename: PathBuf,
+        key: String,
+        message: String,
+    },
     #[error("{0}")]
     Check(String),
 }
@@ -277,7 +833,7 @@ trait CheckValue {
     fn check_value(&mut self) -> Result<(), Self::Error>;
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, StructOpt)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, StructOpt)]
 #[structopt(about = "Runs a server from command-line configuration values.")]
 pub struct CfgValue {
     #[serde(default)]
@@ -292,23 +848,60 @@ pub struct CfgValue {
     #[serde(default)]
     #[structopt(flatten)]
     pub www: CfgWWW,
+    #[serde(default)]
+    #[structopt(flatten)]
+    pub notifications: CfgNotifications,
 }
 
 impl TryFrom<PathBuf> for CfgValue {
     type Error = CfgError;
 
     fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
-        let mut config_value = Config::builder()
-            .add_source(File::from(path.clone()).required(true))
-            .add_source(Environment::with_prefix(crate_name!()))
+        Self::try_from_file_list(&[path])
+    }
+}
+
+impl CfgValue {
+    /// Loads and merges configuration files in order, So later files override earlier ones.
+    ///
+    /// Tables are merged key by key and other values (including lists) are replaced as a whole.
+    /// Environment variables override all files.
+    pub fn try_from_file_list(path_list: &[PathBuf]) -> Result<Self, CfgError> {
+        let mut builder = Config::builder();
+        let source_list = path_list
+            .iter()
+            .map(|path| {
+                let source: Box<dyn Source + Send + Sync> =
+                    Box::new(File::from(path.clone()).required(true));
+                (path.clone(), source)
+            })
+            .chain([(
+                PathBuf::from("<ENVIRONMENT>"),
+                Box::new(Environment::with_prefix(crate_name!())) as Box<dyn Source + Send + Sync>,
+            )]);
+        for (source_name, source) in source_list {
+            let mut config =
+                Config::builder()
+                    .add_source(vec![source])
+                    .build()
+                    .map_err(|reason| CfgError::ReadFile {
+                        filename: source_name.clone(),
+                        message: reason,
+                    })?;
+            interpolate_value(&mut config.cache, String::new()).map_err(|(key, message)| {
+                CfgError::Interpolate {
+                    filename: source_name.clone(),
+                    key,
+                    message,
+                }
+            })?;
+            builder = builder.add_source(config);
+        }
+        let mut config_value = builder
             .build()
-            .map_err(|reason| CfgError::ReadFile {
-                filename: path.clone(),
-                message: reason,
-            })?
-            .try_deserialize::<CfgValue>()
+            .and_then(|config| config.try_deserialize::<CfgValue>())
             .map_err(|reason| CfgError::Deserialize {
-                filename: path.clone(),
+                filename_list: path_list.to_vec(),
                 message: reason,
             })?;
         config_value
@@ -318,6 +911,64 @@ impl TryFrom<PathBuf> for CfgValue {
     }
 }
 
+/// Replaces `${ENV_VAR}` inside string values with value of environment variables and string
+/// values in form of `@file:/path/to/file` with content of the file.
+///
+/// Use `$${` to have a literal `${`. On error, it returns the key and the reason.
+fn interpolate_value(value: &mut Value, key: String) -> Result<(), (String, String)> {
+    match value.kind {
+        ValueKind::Table(ref mut table) => {
+            for (table_key, table_value) in table.iter_mut() {
+                let key = if key.is_empty() {
+                    table_key.clone()
+                } else {
+                    format!("{}.{}", key, table_key)
+                };
+                interpolate_value(table_value, key)?;
+            }
+        }
+        ValueKind::Array(ref mut array) => {
+            for (index, array_value) in array.iter_mut().enumerate() {
+                interpolate_value(array_value, format!("{}[{}]", key, index))?;
+            }
+        }
+        ValueKind::String(ref mut string) => {
+            *string = interpolate_string(string).map_err(|reason| (key, reason))?;
+        }
+        _ => (),
+    };
+    Ok(())
+}
+
+fn interpolate_string(string: &str) -> Result<String, String> {
+    if let Some(filename) = string.strip_prefix("@file:") {
+        return fs::read_to_string(filename)
+            .map(|content| content.trim_end_matches(&['\r', '\n'][..]).to_string())
+            .map_err(|reason| format!("could not read file {:?}: {}", filename, reason));
+    };
+    let mut result = String::new();
+    let mut rest = string;
+    while let Some(index) = rest.find("${") {
+        if rest[..index].ends_with('$') {
+            result.push_str(&rest[..index]);
+            result.push('{');
+            rest = &rest[index + 2..];
+            continue;
+        };
+        result.push_str(&rest[..index]);
+        let end = rest[index..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed `${{` in {:?}", string))?;
+        let name = &rest[index + 2..index + end];
+        let env_value = std::env::var(name)
+            .map_err(|reason| format!("environment variable {:?}: {}", name, reason))?;
+        result.push_str(env_value.as_str());
+        rest = &rest[index + end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
 impl CheckValue for CfgValue {
     type Error = CfgError;
     fn check_value(&mut self) -> Result<(), Self::Error> {
@@ -336,6 +987,9 @@ impl CheckValue for CfgValue {
         self.www
             .check_value()
             .map_err(|reason| CfgError::Check(reason.to_string()))?;
+        self.notifications
+            .check_value()
+            .map_err(|reason| CfgError::Check(reason.to_string()))?;
         Ok(())
     }
 }
@@ -387,10 +1041,24 @@ pub struct CfgServer {
     )]
     pub username: String,
 
+    /// Realm of `WWW-Authenticate` HTTP response header.
+    ///
+    /// Use a different value for each instance if you run multiple RestCommander instances,
+    /// so browsers do not reuse credentials across them.
+    #[serde(default = "defaults::server::auth_realm")]
+    #[structopt(
+        name = "server-auth-realm",
+        long,
+        default_value = defaults::server::auth_realm_str(),
+        env = "RESTCOMMANDER_SERVER_AUTH_REALM",
+    )]
+    pub auth_realm: String,
+
     /// A file containing sha512 of your user password.
     ///
     /// By configuring this you are able to change the password in runtime via REST API.
-    /// Make sure that RestCommander process has appropriate permissions to write to the file.
+    /// Make sure that RestCommander process has appropriate permissions to write to the file and
+    /// its directory (new password is written to a temporary file and is renamed to the file).
     /// Empty value means this option should be discarded and if one of server `password_file`
     /// and `password_sha512` is not configured, You can call every REST API endpoint without
     /// authentication.
@@ -415,93 +1083,350 @@ pub struct CfgServer {
     /// You can use `sha512` subcommand to generate sha512 of your passwords.
     #[serde(default = "defaults::server::password_sha512")]
     #[structopt(
-        name = "server-password-sha512",
+        name = "server-password-sha512",
+        long,
+        default_value = defaults::server::password_sha512_str(),
+        env = "RESTCOMMANDER_SERVER_PASSWORD_SHA512"
+    )]
+    pub password_sha512: String,
+
+    /// Executable file that authenticates users of `/api/auth/token` instead of server
+    /// `username` and password.
+    ///
+    /// It gets `{"username": "<USERNAME>", "password": "<PASSWORD>"}` from its stdin and the
+    /// username in `RESTCOMMANDER_AUTH_USERNAME` environment variable. Exit code 0 means the user
+    /// is authenticated. It can print a JSON list of API path prefixes (e.g. `["run/deploy"]`) that
+    /// the new token is limited to.
+    #[serde(default = "defaults::server::auth_command")]
+    #[structopt(
+        name = "server-auth-command",
+        long,
+        parse(from_os_str),
+        env = "RESTCOMMANDER_SERVER_AUTH_COMMAND"
+    )]
+    pub auth_command: Option<PathBuf>,
+
+    /// HTTP server TLS certificate file.
+    ///
+    /// If you configure this along with server `tls_key_file` option, RestCommander
+    /// serves everything over HTTPS.
+    /// You can get a test certificate via `sample self-signed-cert` subcommand.
+    #[serde(default = "defaults::server::tls_cert_file")]
+    #[structopt(
+        name = "server-tls-cert-file",
+        long,
+        parse(from_os_str),
+        env = "RESTCOMMANDER_SERVER_TLS_CERT_FILE"
+    )]
+    pub tls_cert_file: Option<PathBuf>,
+
+    /// HTTP server TLS private-key file.
+    ///
+    /// If you configure this along with server `tls_cert_file` option, RestCommander
+    /// serves everything over HTTPS.
+    /// You can get a test private-key via `sample self-signed-key` subcommand.
+    #[serde(default = "defaults::server::tls_key_file")]
+    #[structopt(
+        name = "server-tls-key-file",
+        long,
+        parse(from_os_str),
+        env = "RESTCOMMANDER_SERVER_TLS_KEY_FILE"
+    )]
+    pub tls_key_file: Option<PathBuf>,
+
+    /// PEM file of CA certificates for verifying TLS client certificates.
+    ///
+    /// If you configure this, HTTPS listeners ask clients for a certificate and clients
+    /// whose certificate subject common name (CN) matches `tls_client_allowed_names` are
+    /// authorized without username/password or token.
+    #[serde(default = "defaults::server::tls_client_ca_file")]
+    #[structopt(
+        name = "server-tls-client-ca-file",
+        long,
+        parse(from_os_str),
+        env = "RESTCOMMANDER_SERVER_TLS_CLIENT_CA_FILE"
+    )]
+    pub tls_client_ca_file: Option<PathBuf>,
+
+    /// Reject TLS clients that do not send a certificate signed by `tls_client_ca_file`.
+    ///
+    /// If it is not enabled, clients without certificate can still use other
+    /// authentication methods.
+    #[serde(default = "defaults::server::tls_client_cert_required")]
+    #[structopt(
+        name = "server-tls-client-cert-required",
+        long,
+        env = "RESTCOMMANDER_SERVER_TLS_CLIENT_CERT_REQUIRED"
+    )]
+    pub tls_client_cert_required: bool,
+
+    /// List of TLS client certificate common names (CN) that are authorized. Wildcard
+    /// characters like * are allowed.
+    ///
+    /// No value means no client is authorized by its certificate.
+    #[serde(default = "defaults::server::tls_client_allowed_names")]
+    #[structopt(
+        name = "server-tls-client-allowed-names",
+        long,
+        env = "RESTCOMMANDER_SERVER_TLS_CLIENT_ALLOWED_NAMES"
+    )]
+    pub tls_client_allowed_names: Vec<String>,
+
+    /// Expect PROXY protocol (version 1 or 2) header on every client connection.
+    ///
+    /// Enable it if RestCommander is behind a load balancer that speaks PROXY protocol
+    /// (e.g. HAProxy or AWS NLB), So the real client address is used for logging and IP
+    /// whitelist. Connections without a valid header are closed.
+    #[serde(default = "defaults::server::proxy_protocol")]
+    #[structopt(
+        name = "server-proxy-protocol",
+        long,
+        env = "RESTCOMMANDER_SERVER_PROXY_PROTOCOL"
+    )]
+    pub proxy_protocol: bool,
+
+    /// Enable/Disable CAPTCHA.
+    #[serde(default = "defaults::server::captcha")]
+    #[structopt(name = "server-captcha", long, env = "RESTCOMMANDER_SERVER_CAPTCHA")]
+    pub captcha: bool,
+
+    /// Make CAPTCHA case-sensitive
+    #[serde(default = "defaults::server::captcha_case_sensitive")]
+    #[structopt(
+        name = "server-captcha-case-sensitive",
+        long,
+        env = "RESTCOMMANDER_SERVER_CAPTCHA_CASE_SENSITIVE"
+    )]
+    pub captcha_case_sensitive: bool,
+
+    /// List of IP addresses that can interact with REST-API. Wildcard characters like *
+    /// are allowed.
+    ///
+    /// No value means everyone can interact with REST-API.
+    /// RestCommander currently does not support HTTP IP headers, So this IP address
+    /// is the connected client IP address and not the IP address that upstream webserver
+    /// forwards in the request header.
+    #[serde(default = "defaults::server::ip_whitelist")]
+    #[structopt(
+        name = "server-ip-whitelist",
+        long,
+        env = "RESTCOMMANDER_SERVER_IP_WHITELIST"
+    )]
+    pub ip_whitelist: Vec<String>,
+
+    /// List of `/api/` path prefixes (e.g. `run/status`) that do not need authentication.
+    ///
+    /// A prefix matches whole path segments, So `run/status` matches `/api/run/status` and
+    /// `/api/run/status/foo` but not `/api/run/statuses`. Anonymous requests are still checked
+    /// against `ip_whitelist` and logged.
+    #[serde(default = "defaults::server::anonymous_paths")]
+    #[structopt(
+        name = "server-anonymous-paths",
+        long,
+        env = "RESTCOMMANDER_SERVER_ANONYMOUS_PATHS"
+    )]
+    pub anonymous_paths: Vec<String>,
+
+    /// hardcoded HTTP bearer token that does not expire.
+    ///
+    /// You can use this value in your application(s) then you do not have to pass
+    /// CAPTCHA each time the previous token has expired to get a new one.
+    #[serde(default = "defaults::server::api_token")]
+    #[structopt(
+        name = "server-api-token",
+        long,
+        env = "RESTCOMMANDER_SERVER_API_TOKEN"
+    )]
+    pub api_token: Option<String>,
+
+    /// Timeout for dynamically generated HTTP bearer tokens in seconds.
+    ///
+    /// The default value is 1 week.
+    #[serde(default = "defaults::server::token_timeout")]
+    #[structopt(
+        name = "server-token-timeout",
+        long,
+        default_value = defaults::server::token_timeout_str(),
+        env = "RESTCOMMANDER_SERVER_TOKEN_TIMEOUT",
+    )]
+    pub token_timeout: usize,
+
+    /// Value of `Retry-After` HTTP response header in seconds for `503` and `429` responses.
+    ///
+    /// It is used when a command exits with exit-code `5` (`503` status-code).
+    /// The default value is 0 which means the header is omitted.
+    #[serde(default = "defaults::server::retry_after")]
+    #[structopt(
+        name = "server-retry-after",
+        long,
+        default_value = defaults::server::retry_after_str(),
+        env = "RESTCOMMANDER_SERVER_RETRY_AFTER",
+    )]
+    pub retry_after: u64,
+
+    /// Maximum time of handling an HTTP request in seconds (e.g. reading request body and running
+    /// the command).
+    ///
+    /// After that, the client gets a `408` response. Commands are killed no later than this,
+    /// even if `commands.timeout` is greater or not set.
+    /// The default value is 0 which means no timeout.
+    #[serde(default = "defaults::server::request_timeout")]
+    #[structopt(
+        name = "server-request-timeout",
+        long,
+        default_value = defaults::server::request_timeout_str(),
+        env = "RESTCOMMANDER_SERVER_REQUEST_TIMEOUT",
+    )]
+    pub request_timeout: u64,
+
+    /// Maximum time of receiving request headers in seconds after the server starts waiting for
+    /// them.
+    ///
+    /// Slower clients get a `408` response and their connection is closed. Idle keep-alive
+    /// connections are closed after it too. 0 means no timeout.
+    #[serde(default = "defaults::server::header_read_timeout")]
+    #[structopt(
+        name = "server-header-read-timeout",
+        long,
+        default_value = defaults::server::header_read_timeout_str(),
+        env = "RESTCOMMANDER_SERVER_HEADER_READ_TIMEOUT",
+    )]
+    pub header_read_timeout: u64,
+
+    /// Maximum time of waiting for each part of request body in seconds.
+    ///
+    /// Slower clients get a `408` response and their connection is closed. 0 means no timeout.
+    #[serde(default = "defaults::server::body_read_timeout")]
+    #[structopt(
+        name = "server-body-read-timeout",
+        long,
+        default_value = defaults::server::body_read_timeout_str(),
+        env = "RESTCOMMANDER_SERVER_BODY_READ_TIMEOUT",
+    )]
+    pub body_read_timeout: u64,
+
+    /// Maximum time of waiting for in-flight requests (and their commands) in seconds after the
+    /// server gets `SIGINT` or `SIGTERM` signal.
+    ///
+    /// After that, Running commands are killed (the same way as timed out commands) and the
+    /// server exits. 0 means killing them immediately.
+    #[serde(default = "defaults::server::shutdown_drain_timeout")]
+    #[structopt(
+        name = "server-shutdown-drain-timeout",
+        long,
+        default_value = defaults::server::shutdown_drain_timeout_str(),
+        env = "RESTCOMMANDER_SERVER_SHUTDOWN_DRAIN_TIMEOUT",
+    )]
+    pub shutdown_drain_timeout: u64,
+
+    /// Include server-side `timestamp` and request `elapsed_seconds` in every API response
+    /// object.
+    ///
+    /// Clients can enable it per request with `timing=true` URL query-string too.
+    #[serde(default = "defaults::server::include_timing")]
+    #[structopt(
+        name = "server-include-timing",
         long,
-        default_value = defaults::server::password_sha512_str(),
-        env = "RESTCOMMANDER_SERVER_PASSWORD_SHA512"
+        parse(try_from_str),
+        default_value = defaults::server::include_timing_str(),
+        env = "RESTCOMMANDER_SERVER_INCLUDE_TIMING"
     )]
-    pub password_sha512: String,
+    pub include_timing: bool,
 
-    /// HTTP server TLS certificate file.
+    /// Allow changing the password via REST API or in web dashboard.
     ///
-    /// If you configure this along with server `tls_key_file` option, RestCommander
-    /// serves everything over HTTPS.
-    /// You can get a test certificate via `sample self-signed-cert` subcommand.
-    #[serde(default = "defaults::server::tls_cert_file")]
+    /// If it is disabled, the password can not be changed at runtime even if server
+    /// `password_file` is configured.
+    #[serde(default = "defaults::server::allow_set_password")]
     #[structopt(
-        name = "server-tls-cert-file",
+        name = "server-allow-set-password",
         long,
-        parse(from_os_str),
-        env = "RESTCOMMANDER_SERVER_TLS_CERT_FILE"
+        parse(try_from_str),
+        default_value = defaults::server::allow_set_password_str(),
+        env = "RESTCOMMANDER_SERVER_ALLOW_SET_PASSWORD"
     )]
-    pub tls_cert_file: Option<PathBuf>,
+    pub allow_set_password: bool,
 
-    /// HTTP server TLS private-key file.
+    /// Require the current password to change the password via REST API or in web dashboard.
+    #[serde(default = "defaults::server::require_old_password")]
+    #[structopt(
+        name = "server-require-old-password",
+        long,
+        env = "RESTCOMMANDER_SERVER_REQUIRE_OLD_PASSWORD"
+    )]
+    pub require_old_password: bool,
+
+    /// Minimum length of a new password that is set via REST API or in web dashboard.
     ///
-    /// If you configure this along with server `tls_cert_file` option, RestCommander
-    /// serves everything over HTTPS.
-    /// You can get a test private-key via `sample self-signed-key` subcommand.
-    #[serde(default = "defaults::server::tls_key_file")]
+    /// The default value is 0 which means no minimum length.
+    #[serde(default = "defaults::server::password_min_length")]
     #[structopt(
-        name = "server-tls-key-file",
+        name = "server-password-min-length",
         long,
-        parse(from_os_str),
-        env = "RESTCOMMANDER_SERVER_TLS_KEY_FILE"
+        default_value = defaults::server::password_min_length_str(),
+        env = "RESTCOMMANDER_SERVER_PASSWORD_MIN_LENGTH",
     )]
-    pub tls_key_file: Option<PathBuf>,
+    pub password_min_length: usize,
 
-    /// Enable/Disable CAPTCHA.
-    #[serde(default = "defaults::server::captcha")]
-    #[structopt(name = "server-captcha", long, env = "RESTCOMMANDER_SERVER_CAPTCHA")]
-    pub captcha: bool,
+    /// Number of character classes a new password should contain.
+    ///
+    /// Character classes are lowercase letters, uppercase letters, digits and other characters.
+    /// The default value is 0 which means no requirement.
+    #[serde(default = "defaults::server::password_character_classes")]
+    #[structopt(
+        name = "server-password-character-classes",
+        long,
+        default_value = defaults::server::password_character_classes_str(),
+        env = "RESTCOMMANDER_SERVER_PASSWORD_CHARACTER_CLASSES",
+    )]
+    pub password_character_classes: usize,
 
-    /// Make CAPTCHA case-sensitive
-    #[serde(default = "defaults::server::captcha_case_sensitive")]
+    /// Maximum length of the queue of pending connections of HTTP server listener.
+    #[serde(default = "defaults::server::backlog")]
     #[structopt(
-        name = "server-captcha-case-sensitive",
+        name = "server-backlog",
         long,
-        env = "RESTCOMMANDER_SERVER_CAPTCHA_CASE_SENSITIVE"
+        default_value = defaults::server::backlog_str(),
+        env = "RESTCOMMANDER_SERVER_BACKLOG",
     )]
-    pub captcha_case_sensitive: bool,
+    pub backlog: u32,
 
-    /// List of IP addresses that can interact with REST-API. Wildcard characters like *
-    /// are allowed.
+    /// Seconds that a client connection should be idle before sending TCP keepalive probes.
     ///
-    /// No value means everyone can interact with REST-API.
-    /// RestCommander currently does not support HTTP IP headers, So this IP address
-    /// is the connected client IP address and not the IP address that upstream webserver
-    /// forwards in the request header.
-    #[serde(default = "defaults::server::ip_whitelist")]
+    /// The default value is 0 which means TCP keepalive is disabled.
+    #[serde(default = "defaults::server::tcp_keepalive")]
     #[structopt(
-        name = "server-ip-whitelist",
+        name = "server-tcp-keepalive",
         long,
-        env = "RESTCOMMANDER_SERVER_IP_WHITELIST"
+        default_value = defaults::server::tcp_keepalive_str(),
+        env = "RESTCOMMANDER_SERVER_TCP_KEEPALIVE",
     )]
-    pub ip_whitelist: Vec<String>,
+    pub tcp_keepalive: u64,
 
-    /// hardcoded HTTP bearer token that does not expire.
+    /// Seconds between TCP keepalive probes.
     ///
-    /// You can use this value in your application(s) then you do not have to pass
-    /// CAPTCHA each time the previous token has expired to get a new one.
-    #[serde(default = "defaults::server::api_token")]
+    /// The default value is 0 which means using operating system default value.
+    #[serde(default = "defaults::server::tcp_keepalive_interval")]
     #[structopt(
-        name = "server-api-token",
+        name = "server-tcp-keepalive-interval",
         long,
-        env = "RESTCOMMANDER_SERVER_API_TOKEN"
+        default_value = defaults::server::tcp_keepalive_interval_str(),
+        env = "RESTCOMMANDER_SERVER_TCP_KEEPALIVE_INTERVAL",
     )]
-    pub api_token: Option<String>,
+    pub tcp_keepalive_interval: u64,
 
-    /// Timeout for dynamically generated HTTP bearer tokens in seconds.
+    /// Number of unanswered TCP keepalive probes before dropping a client connection.
     ///
-    /// The default value is 1 week.
-    #[serde(default = "defaults::server::token_timeout")]
+    /// The default value is 0 which means using operating system default value.
+    /// It is ignored on Windows.
+    #[serde(default = "defaults::server::tcp_keepalive_retries")]
     #[structopt(
-        name = "server-token-timeout",
+        name = "server-tcp-keepalive-retries",
         long,
-        default_value = defaults::server::token_timeout_str(),
-        env = "RESTCOMMANDER_SERVER_TOKEN_TIMEOUT",
+        default_value = defaults::server::tcp_keepalive_retries_str(),
+        env = "RESTCOMMANDER_SERVER_TCP_KEEPALIVE_RETRIES",
     )]
-    pub token_timeout: usize,
+    pub tcp_keepalive_retries: u32,
 
     /// Print RestCommander ASCII banner
     #[serde(default = "defaults::server::print_banner")]
@@ -511,6 +1436,86 @@ pub struct CfgServer {
         env = "RESTCOMMANDER_SERVER_PRINT_BANNER"
     )]
     pub print_banner: bool,
+
+    /// Serve only the REST API under `/api/*`.
+    ///
+    /// Static files, the web dashboard and redirects of `/` and `/favicon.ico` are not served at
+    /// all (regardless of `www.enabled`) and their paths respond with 404.
+    #[serde(default = "defaults::server::api_only")]
+    #[structopt(name = "server-api-only", long, env = "RESTCOMMANDER_SERVER_API_ONLY")]
+    pub api_only: bool,
+
+    /// List of HTTP server listeners.
+    ///
+    /// Every listener has its own `host`, `port`, `tls_cert_file` and `tls_key_file`.
+    /// If it is empty, a single listener is made from server `host`, `port`,
+    /// `tls_cert_file` and `tls_key_file` options.
+    #[serde(default = "defaults::server::listeners")]
+    #[structopt(skip)]
+    pub listeners: Vec<CfgServerListener>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct CfgServerListener {
+    /// Listen address.
+    #[serde(default = "defaults::server::host")]
+    pub host: String,
+
+    /// Listen port number.
+    #[serde(default = "defaults::server::port")]
+    pub port: u16,
+
+    /// TLS certificate file.
+    #[serde(default = "defaults::server::tls_cert_file")]
+    pub tls_cert_file: Option<PathBuf>,
+
+    /// TLS private-key file.
+    #[serde(default = "defaults::server::tls_key_file")]
+    pub tls_key_file: Option<PathBuf>,
+
+    /// Expect PROXY protocol (version 1 or 2) header on every client connection.
+    #[serde(default = "defaults::server::proxy_protocol")]
+    pub proxy_protocol: bool,
+}
+
+impl CfgServerListener {
+    pub fn has_tls(&self) -> bool {
+        self.tls_cert_file.is_some() && self.tls_key_file.is_some()
+    }
+
+    fn check_value(&self) -> Result<(), CfgServerCheckError> {
+        self.host
+            .clone()
+            .parse::<IpAddr>()
+            .map_err(|reason| CfgServerCheckError::Host {
+                host: self.host.clone(),
+                message: reason,
+            })?;
+        if self.tls_cert_file.clone().is_some() && self.tls_key_file.is_some() {
+            if !self.tls_cert_file.clone().unwrap().is_file() {
+                return Err(CfgServerCheckError::TLSCertFileNotFound {
+                    filename: self.tls_cert_file.clone().unwrap(),
+                });
+            };
+            if !self.tls_key_file.clone().unwrap().is_file() {
+                return Err(CfgServerCheckError::TLSKeyFileNotFound {
+                    filename: self.tls_key_file.clone().unwrap(),
+                });
+            };
+            // So a bad cert or key is reported before stopping running servers or binding:
+            listener::tls_config(
+                &self.tls_cert_file.clone().unwrap(),
+                &self.tls_key_file.clone().unwrap(),
+                None,
+            )
+            .map_err(|message| CfgServerCheckError::TLSCertOrKey { message })?;
+        } else if self.tls_cert_file.clone().is_none() && self.tls_key_file.is_some() {
+            return Err(CfgServerCheckError::TLSCertFileISNotSet);
+        } else if self.tls_key_file.is_none() && self.tls_cert_file.clone().is_some() {
+            return Err(CfgServerCheckError::TLSKeyFileISNotSet);
+        };
+        Ok(())
+    }
 }
 
 #[derive(Debug, Error)]
@@ -542,22 +1547,100 @@ pub enum CfgServerCheckError {
     TLSCertFileNotFound { filename: PathBuf },
     #[error("TLS key file {filename:?} is not found")]
     TLSKeyFileNotFound { filename: PathBuf },
+    #[error("Invalid TLS cert or key: {message}")]
+    TLSCertOrKey { message: String },
     #[error("TLS key file is set but TLS cert file is not set")]
     TLSCertFileISNotSet,
     #[error("TLS cert file is set but TLS key file is not set")]
     TLSKeyFileISNotSet,
+    #[error("TLS client CA file {filename:?} is not found")]
+    TLSClientCAFileNotFound { filename: PathBuf },
+    #[error("TLS client certificate is required but TLS client CA file is not set")]
+    TLSClientCAFileIsNotSet,
+    #[error("Invalid anonymous path {path:?}: {message}")]
+    AnonymousPath { path: String, message: String },
+    #[error("Auth command {0:?} is not an executable file or could not be found")]
+    BadAuthCommand(PathBuf),
+}
+
+impl CfgServer {
+    /// Whether clients should authenticate (with a password, `auth_command`, API token or TLS
+    /// client certificate) to call REST API endpoints.
+    pub fn authentication_is_enabled(&self) -> bool {
+        !self.password_sha512.is_empty() || self.auth_command.is_some()
+    }
+
+    /// Configured listeners or a single listener from `host`, `port` and TLS options.
+    pub fn listener_list(&self) -> Vec<CfgServerListener> {
+        if self.listeners.is_empty() {
+            vec![CfgServerListener {
+                host: self.host.clone(),
+                port: self.port,
+                tls_cert_file: self.tls_cert_file.clone(),
+                tls_key_file: self.tls_key_file.clone(),
+                proxy_protocol: self.proxy_protocol,
+            }]
+        } else {
+            self.listeners.clone()
+        }
+    }
+
+    /// TLS client certificate verification of HTTPS listeners.
+    pub fn tls_client_auth(&self) -> Option<TlsClientAuth> {
+        self.tls_client_ca_file
+            .clone()
+            .map(|ca_file| TlsClientAuth {
+                ca_file,
+                required: self.tls_client_cert_required,
+            })
+    }
+
+    pub fn listener_settings(&self) -> ListenerSettings {
+        let maybe_duration = |seconds| {
+            if seconds == 0 {
+                None
+            } else {
+                Some(Duration::from_secs(seconds))
+            }
+        };
+        ListenerSettings {
+            backlog: self.backlog,
+            maybe_keepalive: maybe_duration(self.tcp_keepalive),
+            maybe_keepalive_interval: maybe_duration(self.tcp_keepalive_interval),
+            maybe_keepalive_retries: if self.tcp_keepalive_retries == 0 {
+                None
+            } else {
+                Some(self.tcp_keepalive_retries)
+            },
+        }
+    }
 }
 
 impl CheckValue for CfgServer {
     type Error = CfgServerCheckError;
     fn check_value(&mut self) -> Result<(), Self::Error> {
-        self.host
-            .clone()
-            .parse::<IpAddr>()
-            .map_err(|reason| CfgServerCheckError::Host {
-                host: self.host.clone(),
-                message: reason,
-            })?;
+        self.listener_list()
+            .iter()
+            .try_for_each(|listener| listener.check_value())?;
+        if let Some(tls_client_ca_file) = self.tls_client_ca_file.clone() {
+            if !tls_client_ca_file.is_file() {
+                return Err(CfgServerCheckError::TLSClientCAFileNotFound {
+                    filename: tls_client_ca_file,
+                });
+            };
+        } else if self.tls_client_cert_required {
+            return Err(CfgServerCheckError::TLSClientCAFileIsNotSet);
+        };
+        for path in self.anonymous_paths.iter_mut() {
+            // Would make everything public:
+            if path.trim_matches('/').is_empty() {
+                return Err(CfgServerCheckError::AnonymousPath {
+                    path: path.clone(),
+                    message: "should not be empty".to_string(),
+                });
+            };
+            *path = path.trim_matches('/').to_string();
+        }
         if let Err(reason) = PathAndQuery::try_from(self.http_base_path.clone()) {
             return Err(CfgServerCheckError::HTTPBasePATH {
                 http_base_path: self.http_base_path.clone(),
@@ -576,6 +1659,11 @@ impl CheckValue for CfgServer {
                 message: "should contain '/' at the start".to_string(),
             });
         };
+        if let Some(ref auth_command) = self.auth_command {
+            if !auth_command.is_file() || !is_executable::is_executable(auth_command) {
+                return Err(CfgServerCheckError::BadAuthCommand(auth_command.clone()));
+            }
+        }
         match (
             !self.username.is_empty(),
             !self.password_sha512.is_empty(),
@@ -623,66 +1711,368 @@ impl CheckValue for CfgServer {
             };
             self.password_sha512 = password;
         };
-        if self.tls_cert_file.clone().is_some() && self.tls_key_file.is_some() {
-            if !self.tls_cert_file.clone().unwrap().is_file() {
-                return Err(CfgServerCheckError::TLSCertFileNotFound {
-                    filename: self.tls_cert_file.clone().unwrap(),
-                });
-            };
-            if !self.tls_key_file.clone().unwrap().is_file() {
-                return Err(CfgServerCheckError::TLSKeyFileNotFound {
-                    filename: self.tls_key_file.clone().unwrap(),
-                });
-            };
-        } else if self.tls_cert_file.clone().is_none() && self.tls_key_file.is_some() {
-            return Err(CfgServerCheckError::TLSCertFileISNotSet);
-        } else if self.tls_key_file.is_none() && self.tls_cert_file.clone().is_some() {
-            return Err(CfgServerCheckError::TLSKeyFileISNotSet);
-        };
         Ok(())
     }
 }
 
-impl Default for CfgServer {
-    fn default() -> Self {
-        Self {
-            host: defaults::server::host(),
-            port: defaults::server::port(),
-            http_base_path: defaults::server::http_base_path(),
-            username: defaults::server::username(),
-            password_file: defaults::server::password_file(),
-            password_sha512: defaults::server::password_sha512(),
-            tls_cert_file: defaults::server::tls_cert_file(),
-            tls_key_file: defaults::server::tls_key_file(),
-            captcha: defaults::server::captcha(),
-            captcha_case_sensitive: defaults::server::captcha_case_sensitive(),
-            ip_whitelist: defaults::server::ip_whitelist(),
-            api_token: defaults::server::api_token(),
-            token_timeout: defaults::server::token_timeout(),
-            print_banner: defaults::server::print_banner(),
-        }
-    }
-}
+impl Default for CfgServer {
+    fn default() -> Self {
+        Self {
+            host: defaults::server::host(),
+            port: defaults::server::port(),
+            http_base_path: defaults::server::http_base_path(),
+            username: defaults::server::username(),
+            auth_realm: defaults::server::auth_realm(),
+            password_file: defaults::server::password_file(),
+            password_sha512: defaults::server::password_sha512(),
+            auth_command: defaults::server::auth_command(),
+            tls_cert_file: defaults::server::tls_cert_file(),
+            tls_key_file: defaults::server::tls_key_file(),
+            tls_client_ca_file: defaults::server::tls_client_ca_file(),
+            tls_client_cert_required: defaults::server::tls_client_cert_required(),
+            tls_client_allowed_names: defaults::server::tls_client_allowed_names(),
+            proxy_protocol: defaults::server::proxy_protocol(),
+            captcha: defaults::server::captcha(),
+            captcha_case_sensitive: defaults::server::captcha_case_sensitive(),
+            ip_whitelist: defaults::server::ip_whitelist(),
+            anonymous_paths: defaults::server::anonymous_paths(),
+            api_token: defaults::server::api_token(),
+            token_timeout: defaults::server::token_timeout(),
+            retry_after: defaults::server::retry_after(),
+            request_timeout: defaults::server::request_timeout(),
+            header_read_timeout: defaults::server::header_read_timeout(),
+            body_read_timeout: defaults::server::body_read_timeout(),
+            shutdown_drain_timeout: defaults::server::shutdown_drain_timeout(),
+            include_timing: defaults::server::include_timing(),
+            allow_set_password: defaults::server::allow_set_password(),
+            require_old_password: defaults::server::require_old_password(),
+            password_min_length: defaults::server::password_min_length(),
+            password_character_classes: defaults::server::password_character_classes(),
+            backlog: defaults::server::backlog(),
+            tcp_keepalive: defaults::server::tcp_keepalive(),
+            tcp_keepalive_interval: defaults::server::tcp_keepalive_interval(),
+            tcp_keepalive_retries: defaults::server::tcp_keepalive_retries(),
+            print_banner: defaults::server::print_banner(),
+            api_only: defaults::server::api_only(),
+            listeners: defaults::server::listeners(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, StructOpt)]
+pub struct CfgCommands {
+    /// Root directory to load command files and directories and their information files.
+    ///
+    /// The default value is your current working directory.
+    #[serde(default = "defaults::commands::root_directory")]
+    #[structopt(
+        name = "commands-root-directory",
+        long,
+        parse(from_os_str),
+        default_value=defaults::commands::root_directory_str(),
+        env="RESTCOMMANDER_COMMANDS_ROOT_DIRECTORY",
+    )]
+    pub root_directory: PathBuf,
+
+    /// Your scripts will receive below configuration key/values directly from env or stdin.
+    #[serde(default = "defaults::commands::configuration")]
+    #[structopt(skip)]
+    pub configuration: CommandOptionsValue,
+
+    /// Number of retries if creating a command process fails because of a transient error.
+    ///
+    /// Transient errors are things like reaching the maximum number of open files or
+    /// `EAGAIN`. Commands that exited with a non-zero exit-code are never retried.
+    /// The default value is 0 which means no retry.
+    #[serde(default = "defaults::commands::spawn_retry_count")]
+    #[structopt(
+        name = "commands-spawn-retry-count",
+        long,
+        default_value = defaults::commands::spawn_retry_count_str(),
+        env = "RESTCOMMANDER_COMMANDS_SPAWN_RETRY_COUNT",
+    )]
+    pub spawn_retry_count: usize,
+
+    /// Delay before the first retry of creating a command process in milliseconds.
+    ///
+    /// The delay is doubled after each retry up to 10 seconds (or this delay if it is more).
+    #[serde(default = "defaults::commands::spawn_retry_delay")]
+    #[structopt(
+        name = "commands-spawn-retry-delay",
+        long,
+        default_value = defaults::commands::spawn_retry_delay_str(),
+        env = "RESTCOMMANDER_COMMANDS_SPAWN_RETRY_DELAY",
+    )]
+    pub spawn_retry_delay: u64,
+
+    /// Maximum running time of a command process in seconds.
+    ///
+    /// After that, the command process and all processes it started are killed.
+    /// The default value is 0 which means no timeout.
+    #[serde(default = "defaults::commands::timeout")]
+    #[structopt(
+        name = "commands-timeout",
+        long,
+        default_value = defaults::commands::timeout_str(),
+        env = "RESTCOMMANDER_COMMANDS_TIMEOUT",
+    )]
+    pub timeout: u64,
+
+    /// Signal that is sent to command processes to terminate them (e.g. after `timeout`).
+    ///
+    /// If processes are still running after `kill_grace_period`, they are killed with `SIGKILL`.
+    /// It is ignored on non-Unix platforms which have a single termination mechanism.
+    #[serde(default = "defaults::commands::kill_signal")]
+    #[structopt(
+        name = "commands-kill-signal",
+        long,
+        default_value = defaults::commands::kill_signal_str(),
+        env = "RESTCOMMANDER_COMMANDS_KILL_SIGNAL",
+    )]
+    pub kill_signal: String,
+
+    /// Seconds to wait after sending `kill_signal` before killing command processes with `SIGKILL`.
+    ///
+    /// 0 means killing processes with `SIGKILL` immediately.
+    #[serde(default = "defaults::commands::kill_grace_period")]
+    #[structopt(
+        name = "commands-kill-grace-period",
+        long,
+        default_value = defaults::commands::kill_grace_period_str(),
+        env = "RESTCOMMANDER_COMMANDS_KILL_GRACE_PERIOD",
+    )]
+    pub kill_grace_period: u64,
+
+    /// Kill the running command if its HTTP client disconnects before the response is sent.
+    ///
+    /// By default the command runs to completion and its output is dropped.
+    #[serde(default = "defaults::commands::kill_on_disconnect")]
+    #[structopt(
+        name = "commands-kill-on-disconnect",
+        long,
+        env = "RESTCOMMANDER_COMMANDS_KILL_ON_DISCONNECT"
+    )]
+    pub kill_on_disconnect: bool,
+
+    /// Always respond to `/api/run/...` requests with HTTP status-code 200 regardless of command
+    /// exit-status and report failures only via `ok` and `exit_code` fields of response.
+    ///
+    /// Commands can override it with `always_ok_status` in their information file.
+    #[serde(default = "defaults::commands::always_ok_status")]
+    #[structopt(
+        name = "commands-always-ok-status",
+        long,
+        env = "RESTCOMMANDER_COMMANDS_ALWAYS_OK_STATUS"
+    )]
+    pub always_ok_status: bool,
+
+    /// Respond to successful `/api/run/...` requests of commands with empty output with HTTP
+    /// status-code 204 (NO CONTENT) and no body.
+    ///
+    /// Commands can override it with `no_content_on_empty_output` in their information file.
+    #[serde(default = "defaults::commands::no_content_on_empty_output")]
+    #[structopt(
+        name = "commands-no-content-on-empty-output",
+        long,
+        env = "RESTCOMMANDER_COMMANDS_NO_CONTENT_ON_EMPTY_OUTPUT"
+    )]
+    pub no_content_on_empty_output: bool,
+
+    /// Allow `debug` query-string parameter of `/api/run/...` requests that includes resolved
+    /// arguments, working directory and environment variables of the command in response.
+    ///
+    /// Secret values are redacted but it is disabled by default.
+    #[serde(default = "defaults::commands::allow_debug")]
+    #[structopt(
+        name = "commands-allow-debug",
+        long,
+        env = "RESTCOMMANDER_COMMANDS_ALLOW_DEBUG"
+    )]
+    pub allow_debug: bool,
+
+    /// Start even if commands could not be loaded and report not-ready (HTTP status-code 503) in
+    /// `/api/ping` until at least one command is loaded.
+    ///
+    /// By default RestCommander does not start if it could not load commands.
+    #[serde(default = "defaults::commands::wait_for_commands")]
+    #[structopt(
+        name = "commands-wait-for-commands",
+        long,
+        env = "RESTCOMMANDER_COMMANDS_WAIT_FOR_COMMANDS"
+    )]
+    pub wait_for_commands: bool,
+
+    /// Name of current environment (e.g. `prod`).
+    ///
+    /// Commands that declare `environments` in their information files are only enabled if it is
+    /// one of them.
+    #[serde(default = "defaults::commands::environment")]
+    #[structopt(
+        name = "commands-environment",
+        long,
+        env = "RESTCOMMANDER_COMMANDS_ENVIRONMENT"
+    )]
+    pub environment: Option<String>,
 
-#[derive(Debug, Clone, Deserialize, Serialize, StructOpt)]
-pub struct CfgCommands {
-    /// Root directory to load command files and directories and their information files.
+    /// Executable file that runs before every command with the command context in its
+    /// environment variables.
     ///
-    /// The default value is your current working directory.
-    #[serde(default = "defaults::commands::root_directory")]
+    /// If it does not exit with code 0, the command is not run and the HTTP status-code is 403.
+    #[serde(default = "defaults::commands::pre_run_hook")]
     #[structopt(
-        name = "commands-root-directory",
+        name = "commands-pre-run-hook",
         long,
         parse(from_os_str),
-        default_value=defaults::commands::root_directory_str(),
-        env="RESTCOMMANDER_COMMANDS_ROOT_DIRECTORY",
+        env = "RESTCOMMANDER_COMMANDS_PRE_RUN_HOOK"
     )]
-    pub root_directory: PathBuf,
+    pub pre_run_hook: Option<PathBuf>,
 
-    /// Your scripts will receive below configuration key/values directly from env or stdin.
-    #[serde(default = "defaults::commands::configuration")]
+    /// Executable file that runs in background after every command with the command context and
+    /// its result in its environment variables.
+    #[serde(default = "defaults::commands::post_run_hook")]
+    #[structopt(
+        name = "commands-post-run-hook",
+        long,
+        parse(from_os_str),
+        env = "RESTCOMMANDER_COMMANDS_POST_RUN_HOOK"
+    )]
+    pub post_run_hook: Option<PathBuf>,
+
+    /// Maximum size of a `multipart/form-data`, `/api/map/...` or `/api/rpc` request body in
+    /// bytes.
+    #[serde(default = "defaults::commands::upload_max_size")]
+    #[structopt(
+        name = "commands-upload-max-size",
+        long,
+        default_value = defaults::commands::upload_max_size_str(),
+        env = "RESTCOMMANDER_COMMANDS_UPLOAD_MAX_SIZE",
+    )]
+    pub upload_max_size: u64,
+
+    /// Maximum size of each part of a `multipart/form-data` request body in bytes.
+    ///
+    /// Uploaded files are saved to temporary files and their path is passed to the command.
+    #[serde(default = "defaults::commands::upload_file_max_size")]
+    #[structopt(
+        name = "commands-upload-file-max-size",
+        long,
+        default_value = defaults::commands::upload_file_max_size_str(),
+        env = "RESTCOMMANDER_COMMANDS_UPLOAD_FILE_MAX_SIZE",
+    )]
+    pub upload_file_max_size: u64,
+
+    /// Maximum number of command processes that run at the same time for one `/api/map/...`
+    /// request.
+    ///
+    /// 0 is treated as 1.
+    #[serde(default = "defaults::commands::map_concurrency")]
+    #[structopt(
+        name = "commands-map-concurrency",
+        long,
+        default_value = defaults::commands::map_concurrency_str(),
+        env = "RESTCOMMANDER_COMMANDS_MAP_CONCURRENCY",
+    )]
+    pub map_concurrency: usize,
+
+    /// Maximum number of inputs of a `/api/map/...` request.
+    #[serde(default = "defaults::commands::map_max_inputs")]
+    #[structopt(
+        name = "commands-map-max-inputs",
+        long,
+        default_value = defaults::commands::map_max_inputs_str(),
+        env = "RESTCOMMANDER_COMMANDS_MAP_MAX_INPUTS",
+    )]
+    pub map_max_inputs: usize,
+
+    /// Maximum number of requests in a `/api/rpc` batch.
+    #[serde(default = "defaults::commands::rpc_max_batch_size")]
+    #[structopt(
+        name = "commands-rpc-max-batch-size",
+        long,
+        default_value = defaults::commands::rpc_max_batch_size_str(),
+        env = "RESTCOMMANDER_COMMANDS_RPC_MAX_BATCH_SIZE",
+    )]
+    pub rpc_max_batch_size: usize,
+
+    /// Seconds that the response of a `/api/run/...` request with `Idempotency-Key` header is
+    /// kept to be returned for repeated requests with the same key instead of running the command
+    /// again.
+    ///
+    /// 0 disables idempotency keys.
+    #[serde(default = "defaults::commands::idempotency_key_ttl")]
+    #[structopt(
+        name = "commands-idempotency-key-ttl",
+        long,
+        default_value = defaults::commands::idempotency_key_ttl_str(),
+        env = "RESTCOMMANDER_COMMANDS_IDEMPOTENCY_KEY_TTL",
+    )]
+    pub idempotency_key_ttl: u64,
+
+    /// Maximum number of path segments (e.g. 2 for `/api/run/foo/bar`) of requested commands.
+    ///
+    /// Deeper paths are rejected with HTTP status-code 404 before searching for the command. 0
+    /// means no limit.
+    #[serde(default = "defaults::commands::max_path_depth")]
+    #[structopt(
+        name = "commands-max-path-depth",
+        long,
+        default_value = defaults::commands::max_path_depth_str(),
+        env = "RESTCOMMANDER_COMMANDS_MAX_PATH_DEPTH",
+    )]
+    pub max_path_depth: usize,
+
+    /// Maximum CPU time of each command process in seconds (Unix only).
+    ///
+    /// A process that exceeds it is killed by `SIGXCPU` signal. Commands can override it in
+    /// their information file. The default value is 0 which means no limit.
+    #[serde(default = "defaults::commands::limit_cpu_time")]
+    #[structopt(
+        name = "commands-limit-cpu-time",
+        long,
+        default_value = defaults::commands::limit_cpu_time_str(),
+        env = "RESTCOMMANDER_COMMANDS_LIMIT_CPU_TIME",
+    )]
+    pub limit_cpu_time: u64,
+
+    /// Maximum virtual memory size of each command process in bytes (Unix only).
+    ///
+    /// Memory allocations of a process fail after it. Commands can override it in their
+    /// information file. The default value is 0 which means no limit.
+    #[serde(default = "defaults::commands::limit_memory")]
+    #[structopt(
+        name = "commands-limit-memory",
+        long,
+        default_value = defaults::commands::limit_memory_str(),
+        env = "RESTCOMMANDER_COMMANDS_LIMIT_MEMORY",
+    )]
+    pub limit_memory: u64,
+
+    /// Maximum number of open files of each command process (Unix only).
+    ///
+    /// Commands can override it in their information file.
+    /// The default value is 0 which means no limit.
+    #[serde(default = "defaults::commands::limit_open_files")]
+    #[structopt(
+        name = "commands-limit-open-files",
+        long,
+        default_value = defaults::commands::limit_open_files_str(),
+        env = "RESTCOMMANDER_COMMANDS_LIMIT_OPEN_FILES",
+    )]
+    pub limit_open_files: u64,
+
+    /// Response field name to command statistics field path (e.g. `duration.total`) map that
+    /// replaces the whole `statistics` object of command responses.
+    #[serde(default = "defaults::commands::statistics_fields")]
     #[structopt(skip)]
-    pub configuration: CommandOptionsValue,
+    pub statistics_fields: HashMap<String, String>,
+
+    /// Command path alias to command path (e.g. `deploy` to `services/production/deploy`) map.
+    ///
+    /// Requests to `/api/run/<ALIAS>` (and `/api/map/...` and `/api/state/...`) use the target
+    /// command. Aliases of aliases are followed.
+    #[serde(default = "defaults::commands::aliases")]
+    #[structopt(skip)]
+    pub aliases: HashMap<String, String>,
 }
 
 impl Default for CfgCommands {
@@ -690,6 +2080,59 @@ impl Default for CfgCommands {
         Self {
             root_directory: defaults::commands::root_directory(),
             configuration: defaults::commands::configuration(),
+            spawn_retry_count: defaults::commands::spawn_retry_count(),
+            spawn_retry_delay: defaults::commands::spawn_retry_delay(),
+            timeout: defaults::commands::timeout(),
+            kill_signal: defaults::commands::kill_signal(),
+            kill_grace_period: defaults::commands::kill_grace_period(),
+            kill_on_disconnect: defaults::commands::kill_on_disconnect(),
+            always_ok_status: defaults::commands::always_ok_status(),
+            no_content_on_empty_output: defaults::commands::no_content_on_empty_output(),
+            allow_debug: defaults::commands::allow_debug(),
+            wait_for_commands: defaults::commands::wait_for_commands(),
+            environment: defaults::commands::environment(),
+            pre_run_hook: defaults::commands::pre_run_hook(),
+            post_run_hook: defaults::commands::post_run_hook(),
+            upload_max_size: defaults::commands::upload_max_size(),
+            upload_file_max_size: defaults::commands::upload_file_max_size(),
+            map_concurrency: defaults::commands::map_concurrency(),
+            map_max_inputs: defaults::commands::map_max_inputs(),
+            rpc_max_batch_size: defaults::commands::rpc_max_batch_size(),
+            idempotency_key_ttl: defaults::commands::idempotency_key_ttl(),
+            max_path_depth: defaults::commands::max_path_depth(),
+            limit_cpu_time: defaults::commands::limit_cpu_time(),
+            limit_memory: defaults::commands::limit_memory(),
+            limit_open_files: defaults::commands::limit_open_files(),
+            statistics_fields: defaults::commands::statistics_fields(),
+            aliases: defaults::commands::aliases(),
+        }
+    }
+}
+
+impl CfgCommands {
+    pub fn run_settings(&self, logging: &CfgLogging, server: &CfgServer) -> CommandRunSettings {
+        let maybe_limit = |limit| if limit == 0 { None } else { Some(limit) };
+        CommandRunSettings {
+            spawn_retry_count: self.spawn_retry_count,
+            spawn_retry_delay: Duration::from_millis(self.spawn_retry_delay),
+            // A command should not outlive the request that runs it:
+            maybe_timeout: [self.timeout, server.request_timeout]
+                .into_iter()
+                .filter(|timeout| *timeout > 0)
+                .min()
+                .map(Duration::from_secs),
+            kill_signal: self.kill_signal.clone(),
+            kill_grace_period: Duration::from_secs(self.kill_grace_period),
+            log_max_line_length: logging.max_line_length,
+            log_max_line_count: logging.max_line_count,
+            run_as: Default::default(),
+            limits: CommandLimits {
+                maybe_cpu_time: maybe_limit(self.limit_cpu_time),
+                maybe_memory: maybe_limit(self.limit_memory),
+                maybe_open_files: maybe_limit(self.limit_open_files),
+            },
+            priority: Default::default(),
+            maybe_cancel: None,
         }
     }
 }
@@ -701,11 +2144,62 @@ impl CheckValue for CfgCommands {
         if self.root_directory.as_os_str().is_empty() {
             self.root_directory = CfgCommands::default().root_directory;
         }
-        if !self.root_directory.is_dir() {
+        // Root directory may be created later if we wait for commands:
+        if !self.wait_for_commands && !self.root_directory.is_dir() {
             return Err(CfgCommandsCheckError::BadRootDir(
                 self.root_directory.clone(),
             ));
         }
+        if let Some(hook) = [&self.pre_run_hook, &self.post_run_hook]
+            .into_iter()
+            .flatten()
+            .find(|hook| !hook.is_file() || !is_executable::is_executable(hook))
+        {
+            return Err(CfgCommandsCheckError::BadHook(hook.clone()));
+        }
+        #[cfg(unix)]
+        if crate::cmd::runner::signal_number(&self.kill_signal).is_none() {
+            return Err(CfgCommandsCheckError::BadKillSignal(
+                self.kill_signal.clone(),
+            ));
+        }
+        if let Some((name, path)) = self
+            .statistics_fields
+            .iter()
+            .find(|(name, path)| name.is_empty() || path.split('.').any(str::is_empty))
+        {
+            return Err(CfgCommandsCheckError::BadStatisticsField {
+                name: name.clone(),
+                path: path.clone(),
+            });
+        }
+        self.aliases = self
+            .aliases
+            .iter()
+            .map(|(alias, path)| {
+                let (alias, path) = (alias.trim_matches('/'), path.trim_matches('/'));
+                if alias.is_empty() || path.is_empty() {
+                    return Err(CfgCommandsCheckError::BadAlias {
+                        alias: alias.to_string(),
+                        path: path.to_string(),
+                    });
+                };
+                Ok((alias.to_string(), path.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        for alias in self.aliases.keys() {
+            let mut path = alias;
+            let mut seen = vec![alias];
+            while let Some(next_path) = self.aliases.get(path) {
+                if seen.contains(&next_path) {
+                    return Err(CfgCommandsCheckError::CircularAlias {
+                        alias: alias.clone(),
+                    });
+                };
+                seen.push(next_path);
+                path = next_path;
+            }
+        }
         Ok(())
     }
 }
@@ -714,6 +2208,16 @@ impl CheckValue for CfgCommands {
 pub enum CfgCommandsCheckError {
     #[error("Commands root directory {0:?} is not a directory or could not be found")]
     BadRootDir(PathBuf),
+    #[error("Unknown kill signal name {0:?}")]
+    BadKillSignal(String),
+    #[error("Hook {0:?} is not an executable file or could not be found")]
+    BadHook(PathBuf),
+    #[error("Bad statistics field {name:?} with path {path:?}")]
+    BadStatisticsField { name: String, path: String },
+    #[error("Bad alias {alias:?} for command path {path:?}")]
+    BadAlias { alias: String, path: String },
+    #[error("Alias {alias:?} is circular")]
+    CircularAlias { alias: String },
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, StructOpt)]
@@ -752,6 +2256,51 @@ pub struct CfgLogging {
     )]
     #[serde(default = "defaults::logging::report")]
     pub report: PathBuf,
+    /// Maximum length of each logged line of commands output and HTTP request headers in bytes.
+    ///
+    /// Longer lines are truncated with a marker. 0 means no limit.
+    #[serde(default = "defaults::logging::max_line_length")]
+    #[structopt(
+        name = "logging-max-line-length",
+        long,
+        default_value = defaults::logging::max_line_length_str(),
+        env = "RESTCOMMANDER_LOGGING_MAX_LINE_LENGTH",
+    )]
+    pub max_line_length: usize,
+    /// Maximum number of logged lines of each command output.
+    ///
+    /// Remaining lines are dropped with a marker. 0 means no limit.
+    #[serde(default = "defaults::logging::max_line_count")]
+    #[structopt(
+        name = "logging-max-line-count",
+        long,
+        default_value = defaults::logging::max_line_count_str(),
+        env = "RESTCOMMANDER_LOGGING_MAX_LINE_COUNT",
+    )]
+    pub max_line_count: usize,
+    /// Format of HTTP request logs.
+    ///
+    /// Possible values: json | combined
+    /// `combined` writes each handled request in Apache/Nginx combined log format.
+    #[serde(default = "defaults::logging::format")]
+    #[structopt(
+        name = "logging-format",
+        long,
+        default_value = defaults::logging::format_str(),
+        env = "RESTCOMMANDER_LOGGING_FORMAT",
+    )]
+    pub format: CfgLoggingFormat,
+    /// Allow `/api/logs` endpoint that returns (and follows) the last lines of the current log
+    /// file if `output` is a directory.
+    ///
+    /// It is disabled by default since logs may contain sensitive information.
+    #[serde(default = "defaults::logging::api_logs")]
+    #[structopt(
+        name = "logging-api-logs",
+        long,
+        env = "RESTCOMMANDER_LOGGING_API_LOGS"
+    )]
+    pub api_logs: bool,
 }
 
 impl Default for CfgLogging {
@@ -760,6 +2309,10 @@ impl Default for CfgLogging {
             level_name: Default::default(),
             output: defaults::logging::output(),
             report: defaults::logging::report(),
+            max_line_length: defaults::logging::max_line_length(),
+            max_line_count: defaults::logging::max_line_count(),
+            format: Default::default(),
+            api_logs: defaults::logging::api_logs(),
         }
     }
 }
@@ -771,7 +2324,8 @@ pub struct CfgWWW {
     /// Also you can override RestCommander virtual files inside this folder.
     /// RestCommander virtual files are: index.html, index.js, login.html,
     /// login.js, commands.html, commands.js, restcommander-background-image.jpg,
-    /// favicon.ico, bootstrap.bundle.min.js, bootstrap.min.css, api.js, utils.js.
+    /// favicon.ico, bootstrap.bundle.min.js, bootstrap.min.css, api.js, utils.js,
+    /// manifest.webmanifest.
     #[serde(default = "defaults::www::static_directory")]
     #[structopt(
         name = "www-static-directory",
@@ -786,6 +2340,16 @@ pub struct CfgWWW {
     #[structopt(name = "www-enabled", long, env = "RESTCOMMANDER_WWW_ENABLED")]
     pub enabled: bool,
 
+    /// Serve an HTML listing of directories inside `static_directory` that have no index.html.
+    #[serde(default = "defaults::www::autoindex")]
+    #[structopt(name = "www-autoindex", long, env = "RESTCOMMANDER_WWW_AUTOINDEX")]
+    pub autoindex: bool,
+
+    /// File extension to `Content-Type` map that overrides guessed content-type of static files.
+    #[serde(default = "defaults::www::mime_types")]
+    #[structopt(skip)]
+    pub mime_types: HashMap<String, String>,
+
     /// You can access below configuration key/values from REST-API `/public/configuration` endpoint.
     #[serde(default = "defaults::www::configuration")]
     #[structopt(skip)]
@@ -803,6 +2367,8 @@ impl Default for CfgWWW {
         Self {
             static_directory: defaults::www::static_directory(),
             enabled: defaults::www::enabled(),
+            autoindex: defaults::www::autoindex(),
+            mime_types: defaults::www::mime_types(),
             configuration: defaults::www::configuration(),
         }
     }
@@ -833,6 +2399,185 @@ impl CheckValue for CfgWWW {
     }
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, StructOpt)]
+pub struct CfgNotifications {
+    /// An `http://` or `https://` URL to POST a CloudEvents JSON event to after each command run.
+    ///
+    /// The default value is empty which disables it.
+    #[serde(default = "defaults::notifications::cloudevents_url")]
+    #[structopt(
+        name = "notifications-cloudevents-url",
+        long,
+        default_value = defaults::notifications::cloudevents_url_str(),
+        env = "RESTCOMMANDER_NOTIFICATIONS_CLOUDEVENTS_URL",
+    )]
+    pub cloudevents_url: String,
+
+    /// The `source` attribute of CloudEvents events.
+    #[serde(default = "defaults::notifications::cloudevents_source")]
+    #[structopt(
+        name = "notifications-cloudevents-source",
+        long,
+        default_value = defaults::notifications::cloudevents_source_str(),
+        env = "RESTCOMMANDER_NOTIFICATIONS_CLOUDEVENTS_SOURCE",
+    )]
+    pub cloudevents_source: String,
+
+    /// An `http://` or `https://` URL to POST a JSON message to after each failed command run (e.g. a Slack
+    /// incoming webhook behind a relay).
+    ///
+    /// The default value is empty which disables it.
+    #[serde(default = "defaults::notifications::webhook_url")]
+    #[structopt(
+        name = "notifications-webhook-url",
+        long,
+        default_value = defaults::notifications::webhook_url_str(),
+        env = "RESTCOMMANDER_NOTIFICATIONS_WEBHOOK_URL",
+    )]
+    pub webhook_url: String,
+
+    /// Body of webhook messages.
+    ///
+    /// Placeholders: {path} {error} {exit_code} {severity} {username} {duration}
+    #[serde(default = "defaults::notifications::webhook_template")]
+    #[structopt(
+        name = "notifications-webhook-template",
+        long,
+        default_value = defaults::notifications::webhook_template_str(),
+        env = "RESTCOMMANDER_NOTIFICATIONS_WEBHOOK_TEMPLATE",
+    )]
+    pub webhook_template: String,
+
+    /// Minimum severity of failed command runs to send webhook messages for.
+    ///
+    /// Possible values: warning (4xx status-codes) | error (5xx status-codes)
+    #[serde(default = "defaults::notifications::webhook_min_severity")]
+    #[structopt(
+        name = "notifications-webhook-min-severity",
+        long,
+        default_value = defaults::notifications::webhook_min_severity_str(),
+        env = "RESTCOMMANDER_NOTIFICATIONS_WEBHOOK_MIN_SEVERITY",
+    )]
+    pub webhook_min_severity: CfgNotificationsSeverity,
+
+    /// Number of retries if sending a notification fails or gets a non-2xx status-code.
+    #[serde(default = "defaults::notifications::retry_count")]
+    #[structopt(
+        name = "notifications-retry-count",
+        long,
+        default_value = defaults::notifications::retry_count_str(),
+        env = "RESTCOMMANDER_NOTIFICATIONS_RETRY_COUNT",
+    )]
+    pub retry_count: usize,
+
+    /// Delay between notification retries in milliseconds.
+    #[serde(default = "defaults::notifications::retry_delay")]
+    #[structopt(
+        name = "notifications-retry-delay",
+        long,
+        default_value = defaults::notifications::retry_delay_str(),
+        env = "RESTCOMMANDER_NOTIFICATIONS_RETRY_DELAY",
+    )]
+    pub retry_delay: u64,
+
+    /// Timeout of each notification request in seconds. 0 means no timeout.
+    #[serde(default = "defaults::notifications::timeout")]
+    #[structopt(
+        name = "notifications-timeout",
+        long,
+        default_value = defaults::notifications::timeout_str(),
+        env = "RESTCOMMANDER_NOTIFICATIONS_TIMEOUT",
+    )]
+    pub timeout: u64,
+}
+
+impl Default for CfgNotifications {
+    fn default() -> Self {
+        Self {
+            cloudevents_url: defaults::notifications::cloudevents_url(),
+            cloudevents_source: defaults::notifications::cloudevents_source(),
+            webhook_url: defaults::notifications::webhook_url(),
+            webhook_template: defaults::notifications::webhook_template(),
+            webhook_min_severity: defaults::notifications::webhook_min_severity(),
+            retry_count: defaults::notifications::retry_count(),
+            retry_delay: defaults::notifications::retry_delay(),
+            timeout: defaults::notifications::timeout(),
+        }
+    }
+}
+
+impl CheckValue for CfgNotifications {
+    type Error = CfgNotificationsCheckError;
+
+    fn check_value(&mut self) -> Result<(), Self::Error> {
+        if !self.cloudevents_url.is_empty() {
+            check_notification_url(&self.cloudevents_url)?;
+        };
+        if !self.webhook_url.is_empty() {
+            check_notification_url(&self.webhook_url)?;
+        };
+        Ok(())
+    }
+}
+
+/// HTTPS notifications are verified against the bundled Mozilla root certificates.
+fn check_notification_url(url: &str) -> Result<(), CfgNotificationsCheckError> {
+    match url.parse::<warp::http::Uri>() {
+        Ok(uri) if matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some() => {
+            Ok(())
+        }
+        _ => Err(CfgNotificationsCheckError::BadUrl(url.to_string())),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CfgNotificationsCheckError {
+    #[error("Notification URL {0:?} is not a valid `http://` or `https://` URL")]
+    BadUrl(String),
+}
+
+/// Severity of a command run which is made from its HTTP status-code.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum CfgNotificationsSeverity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl std::str::FromStr for CfgNotificationsSeverity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str().trim() {
+            "info" => Ok(Self::Info),
+            "warning" | "warn" => Ok(Self::Warning),
+            "error" => Ok(Self::Error),
+            unknown => Err(format!("Unknown severity name {:?}", unknown)),
+        }
+    }
+}
+
+impl CfgNotificationsSeverity {
+    pub fn from_status_code(status_code: StatusCode) -> Self {
+        if status_code.is_server_error() {
+            Self::Error
+        } else if status_code.is_client_error() {
+            Self::Warning
+        } else {
+            Self::Info
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum CfgLoggingLevelName {
@@ -879,6 +2624,31 @@ impl CfgLoggingLevelName {
     }
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CfgLoggingFormat {
+    Json,
+    Combined,
+}
+
+impl Default for CfgLoggingFormat {
+    fn default() -> Self {
+        Self::Json
+    }
+}
+
+impl std::str::FromStr for CfgLoggingFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str().trim() {
+            "json" => Ok(Self::Json),
+            "combined" => Ok(Self::Combined),
+            unknown => Err(format!("Unknown log format {:?}", unknown)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, StructOpt)]
 #[structopt(about = "Prints hex-encoded sha512 of input")]
 pub struct CMDSha512 {
@@ -886,6 +2656,18 @@ pub struct CMDSha512 {
     input: Option<String>,
 }
 
+#[derive(Debug, Clone, StructOpt)]
+#[structopt(
+    about = "Prints hash of a password for `server.password_sha512` configuration or `server.password_file`. It is the same hash that `/api/setPassword` saves."
+)]
+pub struct CMDHashPassword {
+    #[structopt(
+        long,
+        about = "Reads password from the first line of stdin instead of prompting for it."
+    )]
+    stdin: bool,
+}
+
 #[derive(Debug, Clone, StructOpt)]
 #[structopt(about = "Prints base64-encoded of input")]
 pub struct CMDBase64 {
@@ -896,7 +2678,13 @@ pub struct CMDBase64 {
 impl Cfg {
     pub fn try_reload(&mut self) -> Result<(), CfgError> {
         let config_value = match self.filename.clone() {
-            Some(filename) => CfgValue::try_from(filename),
+            Some(filename) => CfgValue::try_from_file_list(
+                &[
+                    [filename].as_slice(),
+                    self.override_filename_list.as_slice(),
+                ]
+                .concat(),
+            ),
             None => Err(CfgError::NoConfigFileGiven),
         }?;
         self.config_value = config_value;
@@ -909,13 +2697,35 @@ impl TryFrom<PathBuf> for Cfg {
     type Error = CfgError;
 
     fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        Self::try_from(vec![path])
+    }
+}
+
+impl TryFrom<Vec<PathBuf>> for Cfg {
+    type Error = CfgError;
+
+    /// The first file is the main configuration file and others override it in order.
+    fn try_from(path_list: Vec<PathBuf>) -> Result<Self, Self::Error> {
+        let mut path_list = path_list
+            .into_iter()
+            .map(|path| {
+                if path.is_relative() {
+                    current_dir().unwrap().join(path)
+                } else {
+                    path
+                }
+            })
+            .collect::<Vec<_>>();
+        let config_value = CfgValue::try_from_file_list(&path_list)?;
+        let filename = if path_list.is_empty() {
+            None
+        } else {
+            Some(path_list.remove(0))
+        };
         Ok(Cfg {
-            config_value: CfgValue::try_from(path.clone())?,
-            filename: Some(if path.is_relative() {
-                current_dir().unwrap().join(path)
-            } else {
-                path
-            }),
+            config_value,
+            filename,
+            override_filename_list: path_list,
         })
     }
 }
@@ -931,6 +2741,7 @@ impl TryFrom<CfgValue> for Cfg {
         Ok(Cfg {
             config_value: value,
             filename: None,
+            override_filename_list: Vec::new(),
         })
     }
 }
@@ -962,13 +2773,84 @@ pub fn try_setup() -> Result<Cfg, Option<String>> {
             }
             Err(None)
         }
+        CMDOpt::HashPassword(CMDHashPassword { stdin }) => {
+            let password = if stdin {
+                let mut password = String::new();
+                std::io::stdin()
+                    .read_line(&mut password)
+                    .map_err(|reason| Some(format!("Could not read password: {}", reason)))?;
+                password.trim_end_matches(&['\r', '\n'][..]).to_string()
+            } else {
+                AskPass::new([0; 10240])
+                    .with_star('*')
+                    .askpass("Enter password: ")
+                    .map(|x| String::from_utf8(x.into()).unwrap())
+                    .map_err(|reason| Some(format!("Could not read password: {}", reason)))?
+            };
+            // Unlike `sha512` subcommand, It is not trimmed like passwords of `/api/setPassword`:
+            if password.is_empty() {
+                return Err(Some("password is empty!".to_string()));
+            };
+            println!("{}", utils::to_sha512(password));
+            Err(None)
+        }
         CMDOpt::Sample(sample_name) => {
             maybe_print(sample_name);
             Err(None)
         }
         CMDOpt::Playground(options) => Ok(Cfg::try_from(options).map_err(|reason| Some(reason))?),
-        CMDOpt::Config(config_file) => Ok(
-            Cfg::try_from(config_file.config_file).map_err(|reason| Some(reason.to_string()))?
-        ),
+        CMDOpt::Config(config_file) => Ok(Cfg::try_from(config_file.config_file_list)
+            .map_err(|reason| Some(reason.to_string()))?),
+        CMDOpt::Check(config_file) => {
+            let cfg = Cfg::try_from(config_file.config_file_list)
+                .map_err(|reason| Some(reason.to_string()))?;
+            check_commands(&cfg).map_err(Some)?;
+            println!("Configuration and commands are valid.");
+            Err(None)
+        }
+    }
+}
+
+/// Loads all commands of `commands.root_directory` and reports all their problems at once.
+fn check_commands(cfg: &Cfg) -> Result<(), String> {
+    let commands_cfg = &cfg.config_value.commands;
+    let http_base_path = PathBuf::from(cfg.config_value.server.http_base_path.clone())
+        .join(PathBuf::from(API_RUN_BASE_PATH).strip_prefix("/").unwrap());
+    let mut problem_list: Vec<String> = if commands_cfg.root_directory.is_dir() {
+        Command::empty(&commands_cfg.root_directory, &http_base_path, None)
+            .find_problems()
+            .iter()
+            .map(|problem| problem.to_string())
+            .collect()
+    } else {
+        vec![format!(
+            "Commands root directory {:?} is not a directory or could not be found",
+            commands_cfg.root_directory
+        )]
+    };
+    if problem_list.is_empty() {
+        // Aliases can only refer to commands of current environment:
+        if let Err(reason) = Command::new(
+            &commands_cfg.root_directory,
+            &http_base_path,
+            commands_cfg.environment.clone(),
+        )
+        .and_then(|commands| cmd::check_aliases(&commands_cfg.aliases, &commands))
+        {
+            problem_list.push(reason.to_string());
+        };
+    };
+    if problem_list.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Found {} problem(s):\n{}",
+            problem_list.len(),
+            problem_list
+                .iter()
+                .map(|problem| format!("  * {}", problem))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ))
     }
 }
@@ -1,6 +1,12 @@
 use sha2::{Digest, Sha512};
+use std::borrow::Cow;
 use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
 use std::time::Duration;
+use subtle::ConstantTimeEq;
 use tokio::sync::mpsc::Receiver;
 use tokio::time::timeout;
 use tracing::{debug, trace};
@@ -38,3 +44,70 @@ pub fn to_sha512(input: impl AsRef<[u8]> + Clone) -> String {
     hasher.update(input.clone());
     hex::encode(hasher.finalize())
 }
+
+/// Compares secrets (e.g. password hashes) in time that does not depend on their contents.
+pub fn constant_time_eq(first: &str, second: &str) -> bool {
+    first.as_bytes().ct_eq(second.as_bytes()).into()
+}
+
+/// Truncates `line` to `max_length` bytes with a marker. 0 means no limit.
+pub fn truncate_line(line: &str, max_length: usize) -> Cow<'_, str> {
+    if max_length == 0 || line.len() <= max_length {
+        return Cow::Borrowed(line);
+    };
+    let mut end = max_length;
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    Cow::Owned(format!(
+        "{}...[truncated {} bytes]",
+        &line[..end],
+        line.len() - end
+    ))
+}
+
+/// Truncates each line of `text` to `max_line_length` bytes and drops lines after
+/// `max_line_count` with a marker, so huge outputs do not flood logs. 0 means no limit.
+pub fn truncate_lines(text: &str, max_line_length: usize, max_line_count: usize) -> String {
+    let mut line_list: Vec<Cow<'_, str>> = text
+        .lines()
+        .take(if max_line_count == 0 {
+            usize::MAX
+        } else {
+            max_line_count
+        })
+        .map(|line| truncate_line(line, max_line_length))
+        .collect();
+    let line_count = text.lines().count();
+    if line_count > line_list.len() {
+        line_list.push(Cow::Owned(format!(
+            "...[truncated {} lines]",
+            line_count - line_list.len()
+        )));
+    };
+    line_list.join("\n")
+}
+
+/// Writes `data` to a temporary file next to `filename` and renames it to `filename`.
+///
+/// The temporary file is synced before renaming, so `filename` contains either the old or the
+/// new data even if the process crashes in the middle of writing.
+pub fn write_file_atomically(filename: &Path, data: impl AsRef<[u8]>) -> io::Result<()> {
+    let directory = match filename.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    let mut temp_file = tempfile::NamedTempFile::new_in(directory)?;
+    if let Ok(metadata) = fs::metadata(filename) {
+        temp_file
+            .as_file()
+            .set_permissions(metadata.permissions())?;
+    };
+    temp_file.write_all(data.as_ref())?;
+    temp_file.as_file().sync_all()?;
+    temp_file
+        .persist(filename)
+        .map_err(|persist_error| persist_error.error)?;
+    trace!(filename = ?filename, "Wrote file atomically.");
+    Ok(())
+}
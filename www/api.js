@@ -129,8 +129,12 @@ class Api {
         return this.fetch('reload/commands', filterFunction)
     }
 
-    async setPassword(password, filterFunction) {
-        return this.fetch('setPassword', filterFunction, 'POST', {}, JSON.stringify({'password': password}))
+    async setPassword(password, oldPassword, filterFunction) {
+        var body = {'password': password}
+        if (oldPassword) {
+            body['old_password'] = oldPassword
+        }
+        return this.fetch('setPassword', filterFunction, 'POST', {}, JSON.stringify(body))
     }
 
     async state(http_path, filterFunction) {
@@ -256,6 +256,20 @@ function appendSettings(element, count) {
             formElement,
             {'class': ''}
         )
+        var oldPasswordDivElement = document.createElement('div')
+        var oldPasswordInputElement = document.createElement('input')
+        setAttributes(
+            oldPasswordInputElement,
+            {
+                'class': 'form-control mb-2',
+                'type': 'password',
+                'id': 'old_password',
+                'name': 'old_password',
+                'placeholder': 'Current Password'
+            }
+        )
+        oldPasswordDivElement.appendChild(oldPasswordInputElement)
+        formElement.appendChild(oldPasswordDivElement)
         var passwordDivElement = document.createElement('div')
         var passwordInputElement = document.createElement('input')
         setAttributes(
@@ -286,8 +300,9 @@ function appendSettings(element, count) {
             event.preventDefault()
             var inputs = new FormData(event.target);
             const password = inputs.get('password')
+            const oldPassword = inputs.get('old_password')
             updateResultBeforeRequest()
-            const setPasswordResult = await new Api(ApiOpts).setPassword(password)
+            const setPasswordResult = await new Api(ApiOpts).setPassword(password, oldPassword)
             if (setPasswordResult.ok === true) {
                 setPasswordResult.result = 'Password Changed Successfully.'
             }